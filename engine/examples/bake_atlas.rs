@@ -0,0 +1,41 @@
+//! Packs every PNG of a directory into atlas pages plus their RON descriptions
+//!
+//! cargo run -p engine --example bake_atlas -- <images dir> <output dir> <name> [padding] [extrude]
+
+use engine::assets::packer::{AtlasPacker, PackerConfig};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 3 {
+        eprintln!("usage: bake_atlas <images dir> <output dir> <name> [padding] [extrude]");
+        std::process::exit(2);
+    }
+
+    let number = |i: usize, default: u32| -> u32 {
+        return args
+            .get(i)
+            .map(|v| v.parse().expect("padding and extrude must be numbers"))
+            .unwrap_or(default);
+    };
+    let config = PackerConfig::new()
+        .with_padding(number(3, 1))
+        .with_extrude(number(4, 1));
+
+    let mut packer = AtlasPacker::new(config);
+    let result = packer
+        .add_directory(&args[0])
+        .and_then(|_| packer.pack())
+        .and_then(|atlas| atlas.bake(&args[1], &args[2]));
+
+    match result {
+        Ok(files) => {
+            for file in files {
+                println!("{}", file.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -4,11 +4,13 @@ var<uniform> u_camera: mat4x4<f32>;
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) color: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
 }
 
 @vertex
@@ -18,6 +20,7 @@ fn vs_main(
     var out: VertexOutput;
 
     out.tex_coords = model.tex_coords;
+    out.color = model.color;
     out.clip_position = u_camera * vec4<f32>(model.position, 1.0);
 
     return out;
@@ -31,5 +34,5 @@ var s_diffuse: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.color;
 }
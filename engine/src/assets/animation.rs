@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use crate::assets::sprite::Sprite;
+
+/// Order the frames of a clip are played in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationDirection {
+    Forward,
+    Reverse,
+
+    /// Forward then back, without repeating the first and last frames
+    PingPong,
+
+    /// Backward then forward, without repeating the first and last frames
+    PingPongReverse,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationFrame {
+    pub sprite: Sprite,
+    pub duration: Duration,
+}
+
+/// Sequence of sprites, each shown for its own duration
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    frames: Vec<AnimationFrame>,
+    direction: AnimationDirection,
+
+    /// Times the clip plays before holding its last frame, 0 for forever
+    repeat: u16,
+
+    /// Indices into `frames` for one play of the clip
+    sequence: Vec<usize>,
+    cycle: Duration,
+}
+
+impl AnimationClip {
+    pub fn new(frames: Vec<AnimationFrame>, direction: AnimationDirection) -> Self {
+        assert!(
+            !frames.is_empty(),
+            "An animation clip needs at least one frame"
+        );
+
+        let forward: Vec<usize> = (0..frames.len()).collect();
+        let back: Vec<usize> = forward.iter().rev().copied().collect();
+        let inner = |v: &[usize]| -> Vec<usize> {
+            return v[1..v.len().saturating_sub(1).max(1)].to_vec();
+        };
+
+        let sequence = match direction {
+            AnimationDirection::Forward => forward,
+            AnimationDirection::Reverse => back,
+            AnimationDirection::PingPong => [forward.clone(), inner(&back)].concat(),
+            AnimationDirection::PingPongReverse => [back.clone(), inner(&forward)].concat(),
+        };
+        let cycle = sequence.iter().map(|i| frames[*i].duration).sum();
+
+        return Self {
+            frames,
+            direction,
+            repeat: 0,
+            sequence,
+            cycle,
+        };
+    }
+
+    /// Plays the clip `repeat` times then holds the last frame, 0 loops forever
+    pub fn with_repeat(mut self, repeat: u16) -> Self {
+        self.repeat = repeat;
+        return self;
+    }
+
+    pub fn frames(&self) -> &[AnimationFrame] {
+        return &self.frames;
+    }
+
+    pub fn direction(&self) -> AnimationDirection {
+        return self.direction;
+    }
+
+    pub fn repeat(&self) -> u16 {
+        return self.repeat;
+    }
+
+    /// Length of one play of the clip
+    pub fn cycle(&self) -> Duration {
+        return self.cycle;
+    }
+
+    /// Whether a clip that does not loop has played to its end
+    pub fn is_finished(&self, elapsed: Duration) -> bool {
+        return self.repeat > 0 && elapsed >= self.cycle * self.repeat as u32;
+    }
+
+    /// Sprite to show `elapsed` after the clip started
+    pub fn sprite_at(&self, elapsed: Duration) -> &Sprite {
+        return &self.frames[self.frame_index_at(elapsed)].sprite;
+    }
+
+    pub fn frame_index_at(&self, elapsed: Duration) -> usize {
+        if self.cycle.is_zero() || self.is_finished(elapsed) {
+            return *self.sequence.last().unwrap();
+        }
+
+        let mut time = Duration::from_nanos((elapsed.as_nanos() % self.cycle.as_nanos()) as u64);
+        for index in &self.sequence {
+            let duration = self.frames[*index].duration;
+            if time < duration {
+                return *index;
+            }
+            time -= duration;
+        }
+
+        return *self.sequence.last().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assets::AssetId, math::uv::UvRect};
+
+    fn clip(count: usize, direction: AnimationDirection) -> AnimationClip {
+        let frames = (0..count)
+            .map(|_| AnimationFrame {
+                sprite: Sprite::new(AssetId::new(0), UvRect::new(0.0, 0.0, 1.0, 1.0)),
+                duration: Duration::from_millis(100),
+            })
+            .collect();
+
+        return AnimationClip::new(frames, direction);
+    }
+
+    fn indices(clip: &AnimationClip, count: u64) -> Vec<usize> {
+        return (0..count)
+            .map(|i| clip.frame_index_at(Duration::from_millis(i * 100 + 50)))
+            .collect();
+    }
+
+    #[test]
+    fn test_directions() {
+        assert_eq!(
+            indices(&clip(3, AnimationDirection::Forward), 4),
+            vec![0, 1, 2, 0]
+        );
+        assert_eq!(
+            indices(&clip(3, AnimationDirection::Reverse), 4),
+            vec![2, 1, 0, 2]
+        );
+        assert_eq!(
+            indices(&clip(3, AnimationDirection::PingPong), 5),
+            vec![0, 1, 2, 1, 0]
+        );
+        assert_eq!(
+            indices(&clip(3, AnimationDirection::PingPongReverse), 5),
+            vec![2, 1, 0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn test_single_frame_ping_pong() {
+        let clip = clip(1, AnimationDirection::PingPong);
+
+        assert_eq!(clip.cycle(), Duration::from_millis(100));
+        assert_eq!(indices(&clip, 3), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn test_repeat_holds_last_frame() {
+        let clip = clip(2, AnimationDirection::Forward).with_repeat(2);
+
+        assert_eq!(indices(&clip, 6), vec![0, 1, 0, 1, 1, 1]);
+        assert!(!clip.is_finished(Duration::from_millis(399)));
+        assert!(clip.is_finished(Duration::from_millis(400)));
+    }
+}
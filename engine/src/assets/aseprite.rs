@@ -0,0 +1,1463 @@
+//! Reader for `.aseprite` files, so sprites and animations load without an export step
+//!
+//! Follows the file format specification of Aseprite 1.3. Visible layers are flattened
+//! with normal blending into one image per frame, tilemap layers are drawn from their
+//! embedded tileset. Other blend modes and external tilesets are not supported: every
+//! layer blends normally and tiles of external tilesets are left empty
+
+use std::{collections::HashMap, fmt, path::Path, time::Duration};
+
+use crate::{
+    assets::{
+        animation::{AnimationClip, AnimationDirection, AnimationFrame},
+        sprite::Sprite,
+        AssetId, AssetsRegistry,
+    },
+    math::{units::Pixels, uv::UvRect},
+};
+
+const FILE_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+const HEADER_SIZE: usize = 128;
+const FRAME_HEADER_SIZE: usize = 16;
+const CHUNK_HEADER_SIZE: usize = 6;
+
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+const CHUNK_SLICE: u16 = 0x2022;
+const CHUNK_TILESET: u16 = 0x2023;
+
+const LAYER_VISIBLE: u16 = 1;
+const LAYER_BACKGROUND: u16 = 8;
+const LAYER_TYPE_GROUP: u16 = 1;
+const LAYER_TYPE_TILEMAP: u16 = 2;
+
+const CEL_RAW: u16 = 0;
+const CEL_LINKED: u16 = 1;
+const CEL_COMPRESSED: u16 = 2;
+const CEL_TILEMAP: u16 = 3;
+
+const TILESET_EMBEDDED: u32 = 2;
+
+const SLICE_NINE_PATCH: u32 = 1;
+const SLICE_PIVOT: u32 = 2;
+
+/// Colors an indexed image can refer to with its one byte pixels
+const MAX_PALETTE_SIZE: usize = 256;
+
+#[derive(Debug)]
+pub enum AsepriteError {
+    Io(std::io::Error),
+
+    /// The file is not a valid aseprite file, `offset` is where reading failed
+    Invalid {
+        offset: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for AsepriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            AsepriteError::Io(e) => write!(f, "could not read aseprite file: {}", e),
+            AsepriteError::Invalid { offset, message } => {
+                write!(f, "invalid aseprite file at byte {}: {}", offset, message)
+            }
+        };
+    }
+}
+
+impl std::error::Error for AsepriteError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsepriteLayer {
+    pub name: String,
+
+    /// Visible in Aseprite, hidden layers are left out of the flattened frames
+    pub visible: bool,
+    pub opacity: u8,
+
+    /// Depth in the layer tree, children of a group are one level below it
+    pub child_level: u16,
+    pub is_group: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsepriteFrame {
+    /// Every visible layer flattened, the size of the canvas
+    pub image: image::RgbaImage,
+    pub duration: Duration,
+}
+
+/// Range of frames played as an animation
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsepriteTag {
+    pub name: String,
+    pub from: usize,
+
+    /// Last frame, included
+    pub to: usize,
+    pub direction: AnimationDirection,
+
+    /// Times the animation plays, 0 for forever
+    pub repeat: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SliceRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Shape of a slice from `frame` until the next key
+#[derive(Debug, Clone, PartialEq)]
+pub struct SliceKey {
+    pub frame: usize,
+    pub bounds: SliceRect,
+
+    /// Center of a 9-patch slice, relative to the bounds
+    pub center: Option<SliceRect>,
+
+    /// Pivot relative to the bounds
+    pub pivot: Option<glam::IVec2>,
+}
+
+/// Named region of the canvas
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsepriteSlice {
+    pub name: String,
+    pub keys: Vec<SliceKey>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsepriteFile {
+    pub width: u32,
+    pub height: u32,
+    pub layers: Vec<AsepriteLayer>,
+    pub frames: Vec<AsepriteFrame>,
+    pub tags: Vec<AsepriteTag>,
+    pub slices: Vec<AsepriteSlice>,
+}
+
+/// Frames packed in a grid, row by row, to upload as one texture
+pub struct AsepriteSheet {
+    pub image: image::RgbaImage,
+    columns: u32,
+}
+
+/// Assets of an aseprite file once registered in the `AssetsRegistry`
+#[derive(Debug, Clone)]
+pub struct AsepriteAsset {
+    pub texture: AssetId,
+
+    /// Sprite of each frame, in order
+    pub frames: Vec<AssetId>,
+
+    /// Animation clip of each tag, by tag name
+    pub animations: HashMap<String, AssetId>,
+
+    /// Sprite of each slice on the frame of its first key, by slice name
+    pub slices: HashMap<String, AssetId>,
+}
+
+/// Tiles stacked vertically in one image, as stored in the file
+struct Tileset {
+    tile_width: u32,
+    tile_height: u32,
+    image: image::RgbaImage,
+}
+
+/// Image of a cel, positioned on the canvas
+struct Cel {
+    layer: usize,
+    x: i32,
+    y: i32,
+    opacity: u8,
+    image: image::RgbaImage,
+}
+
+enum CelContent {
+    Image(Cel),
+
+    /// Same content as the cel of the layer on another frame
+    Linked {
+        layer: usize,
+        frame: usize,
+    },
+}
+
+impl AsepriteFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AsepriteError> {
+        let bytes = std::fs::read(path).map_err(AsepriteError::Io)?;
+        return Self::from_bytes(&bytes);
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AsepriteError> {
+        let mut header = Reader::new(bytes);
+        header.skip(4)?;
+        if header.word()? != FILE_MAGIC {
+            return Err(header.invalid("not an aseprite file"));
+        }
+
+        let frame_count = header.word()? as usize;
+        let width = header.word()? as u32;
+        let height = header.word()? as u32;
+        let depth = header.word()?;
+        header.skip(14)?;
+        let transparent_index = header.byte()?;
+
+        if !matches!(depth, 8 | 16 | 32) {
+            return Err(header.invalid(&format!("unsupported color depth {}", depth)));
+        }
+
+        let mut state = ParseState {
+            depth,
+            transparent_index,
+            palette: Vec::new(),
+            layers: Vec::new(),
+            layer_flags: Vec::new(),
+            layer_tilesets: Vec::new(),
+            tilesets: HashMap::new(),
+            tags: Vec::new(),
+            slices: Vec::new(),
+        };
+
+        let mut frames_cels: Vec<Vec<CelContent>> = Vec::with_capacity(frame_count);
+        let mut durations = Vec::with_capacity(frame_count);
+        let mut offset = HEADER_SIZE;
+
+        for _ in 0..frame_count {
+            let mut frame = Reader::at(bytes, offset);
+            let frame_size = frame.dword()? as usize;
+            if frame.word()? != FRAME_MAGIC {
+                return Err(frame.invalid("bad frame magic number"));
+            }
+
+            let old_chunks = frame.word()? as usize;
+            let duration = frame.word()?;
+            frame.skip(2)?;
+            let new_chunks = frame.dword()? as usize;
+            let chunk_count = if new_chunks == 0 {
+                old_chunks
+            } else {
+                new_chunks
+            };
+
+            let mut cels = Vec::new();
+            let mut chunk_offset = offset + FRAME_HEADER_SIZE;
+            for _ in 0..chunk_count {
+                let mut chunk = Reader::at(bytes, chunk_offset);
+                let chunk_size = chunk.dword()? as usize;
+                let chunk_type = chunk.word()?;
+                if chunk_size < CHUNK_HEADER_SIZE {
+                    return Err(chunk.invalid("chunk smaller than its header"));
+                }
+
+                let end = chunk_offset + chunk_size;
+                let mut data = chunk.limit(end)?;
+                if let Some(cel) = state.read_chunk(chunk_type, &mut data, width, height)? {
+                    cels.push(cel);
+                }
+
+                chunk_offset = end;
+            }
+
+            frames_cels.push(cels);
+            durations.push(Duration::from_millis(duration as u64));
+            offset += frame_size;
+        }
+
+        let frames = flatten(&frames_cels, &state, width, height, durations)?;
+
+        return Ok(Self {
+            width,
+            height,
+            layers: state.layers,
+            frames,
+            tags: state.tags,
+            slices: state.slices,
+        });
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&AsepriteTag> {
+        return self.tags.iter().find(|tag| tag.name == name);
+    }
+
+    /// Packs the frames in a grid close to a square, to stay within texture limits
+    pub fn sheet(&self) -> AsepriteSheet {
+        let count = self.frames.len().max(1) as u32;
+        let columns = (count as f32).sqrt().ceil() as u32;
+        let rows = count.div_ceil(columns);
+
+        let mut image = image::RgbaImage::new(self.width * columns, self.height * rows);
+        for (index, frame) in self.frames.iter().enumerate() {
+            let (x, y) = cell(index, columns);
+            image::imageops::replace(
+                &mut image,
+                &frame.image,
+                (x * self.width) as i64,
+                (y * self.height) as i64,
+            );
+        }
+
+        return AsepriteSheet { image, columns };
+    }
+
+    /// Registers a sprite per frame and slice, and a clip per tag, drawn from the
+    /// sheet uploaded as `texture`
+    pub fn register(
+        &self,
+        sheet: &AsepriteSheet,
+        texture: AssetId,
+        registry: &mut AssetsRegistry,
+    ) -> AsepriteAsset {
+        let sheet_size = [
+            Pixels::new(sheet.image.width() as f32),
+            Pixels::new(sheet.image.height() as f32),
+        ];
+        let sprite = |frame: usize, x: u32, y: u32, width: u32, height: u32| -> Sprite {
+            let (column, row) = cell(frame, sheet.columns);
+            let uv = UvRect::from_pixels(
+                [
+                    Pixels::new((column * self.width + x) as f32),
+                    Pixels::new((row * self.height + y) as f32),
+                ],
+                [Pixels::new(width as f32), Pixels::new(height as f32)],
+                sheet_size,
+            );
+            return Sprite::new(texture, uv);
+        };
+
+        let frames = (0..self.frames.len())
+            .map(|i| registry.insert_sprite(sprite(i, 0, 0, self.width, self.height)))
+            .collect();
+
+        let mut animations = HashMap::new();
+        for tag in &self.tags {
+            let last = tag.to.min(self.frames.len().saturating_sub(1));
+            if tag.from > last {
+                continue;
+            }
+
+            let frames = (tag.from..=last)
+                .map(|i| AnimationFrame {
+                    sprite: sprite(i, 0, 0, self.width, self.height),
+                    duration: self.frames[i].duration,
+                })
+                .collect();
+            let clip = AnimationClip::new(frames, tag.direction).with_repeat(tag.repeat);
+
+            animations.insert(tag.name.clone(), registry.insert_animation(clip));
+        }
+
+        let mut slices = HashMap::new();
+        for slice in &self.slices {
+            let Some(key) = slice.keys.first() else {
+                continue;
+            };
+
+            // Only the part of the slice on the canvas can be drawn
+            let x0 = key.bounds.x.clamp(0, self.width as i32) as u32;
+            let y0 = key.bounds.y.clamp(0, self.height as i32) as u32;
+            let x1 = slice_end(key.bounds.x, key.bounds.width, self.width);
+            let y1 = slice_end(key.bounds.y, key.bounds.height, self.height);
+            if x1 <= x0 || y1 <= y0 || key.frame >= self.frames.len() {
+                continue;
+            }
+
+            let id = registry.insert_sprite(sprite(key.frame, x0, y0, x1 - x0, y1 - y0));
+            slices.insert(slice.name.clone(), id);
+        }
+
+        return AsepriteAsset {
+            texture,
+            frames,
+            animations,
+            slices,
+        };
+    }
+}
+
+/// Column and row of a frame in the sheet
+fn cell(index: usize, columns: u32) -> (u32, u32) {
+    return (index as u32 % columns, index as u32 / columns);
+}
+
+struct ParseState {
+    depth: u16,
+    transparent_index: u8,
+    palette: Vec<[u8; 4]>,
+    layers: Vec<AsepriteLayer>,
+    layer_flags: Vec<u16>,
+
+    /// Tileset of each tilemap layer
+    layer_tilesets: Vec<Option<u32>>,
+    tilesets: HashMap<u32, Tileset>,
+    tags: Vec<AsepriteTag>,
+    slices: Vec<AsepriteSlice>,
+}
+
+impl ParseState {
+    fn read_chunk(
+        &mut self,
+        chunk_type: u16,
+        data: &mut Reader,
+        width: u32,
+        height: u32,
+    ) -> Result<Option<CelContent>, AsepriteError> {
+        match chunk_type {
+            CHUNK_LAYER => self.read_layer(data)?,
+            CHUNK_CEL => return self.read_cel(data, width, height),
+            CHUNK_PALETTE => self.read_palette(data)?,
+            CHUNK_OLD_PALETTE if self.palette.is_empty() => self.read_old_palette(data)?,
+            CHUNK_TAGS => self.read_tags(data)?,
+            CHUNK_SLICE => self.read_slice(data)?,
+            CHUNK_TILESET => self.read_tileset(data)?,
+            _ => {}
+        }
+
+        return Ok(None);
+    }
+
+    fn read_layer(&mut self, data: &mut Reader) -> Result<(), AsepriteError> {
+        let flags = data.word()?;
+        let layer_type = data.word()?;
+        let child_level = data.word()?;
+        data.skip(6)?;
+        let opacity = data.byte()?;
+        data.skip(3)?;
+        let name = data.string()?;
+        let tileset = match layer_type == LAYER_TYPE_TILEMAP {
+            true => Some(data.dword()?),
+            false => None,
+        };
+
+        self.layer_flags.push(flags);
+        self.layer_tilesets.push(tileset);
+        self.layers.push(AsepriteLayer {
+            name,
+            visible: flags & LAYER_VISIBLE != 0,
+            opacity,
+            child_level,
+            is_group: layer_type == LAYER_TYPE_GROUP,
+        });
+
+        return Ok(());
+    }
+
+    fn read_cel(
+        &self,
+        data: &mut Reader,
+        width: u32,
+        height: u32,
+    ) -> Result<Option<CelContent>, AsepriteError> {
+        let layer = data.word()? as usize;
+        let x = data.short()? as i32;
+        let y = data.short()? as i32;
+        let opacity = data.byte()?;
+        let cel_type = data.word()?;
+        data.skip(7)?;
+
+        let (cel_width, cel_height, pixels) = match cel_type {
+            CEL_RAW => {
+                let (w, h) = self.cel_size(data, width, height)?;
+                (w, h, data.rest().to_vec())
+            }
+            CEL_LINKED => {
+                let frame = data.word()? as usize;
+                return Ok(Some(CelContent::Linked { layer, frame }));
+            }
+            CEL_COMPRESSED => {
+                let (w, h) = self.cel_size(data, width, height)?;
+
+                // A cel never inflates to more than its pixels
+                let limit = data.size(&[w, h, (self.depth / 8) as u32])? as usize;
+                let pixels =
+                    miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(data.rest(), limit)
+                        .map_err(|e| data.invalid(&format!("could not inflate cel: {:?}", e)))?;
+                (w, h, pixels)
+            }
+            CEL_TILEMAP => {
+                let image = self.read_tilemap(data, layer)?;
+                return Ok(Some(CelContent::Image(Cel {
+                    layer,
+                    x,
+                    y,
+                    opacity,
+                    image,
+                })));
+            }
+            other => return Err(data.invalid(&format!("unknown cel type {}", other))),
+        };
+
+        let image = self.decode_pixels(data, cel_width, cel_height, &pixels, Some(layer))?;
+        return Ok(Some(CelContent::Image(Cel {
+            layer,
+            x,
+            y,
+            opacity,
+            image,
+        })));
+    }
+
+    /// Size of an image cel, checked before its pixels are read
+    fn cel_size(
+        &self,
+        data: &mut Reader,
+        width: u32,
+        height: u32,
+    ) -> Result<(u32, u32), AsepriteError> {
+        let w = data.word()? as u32;
+        let h = data.word()? as u32;
+        if w > width.max(1) * 4 || h > height.max(1) * 4 {
+            return Err(data.invalid("cel larger than the canvas"));
+        }
+
+        return Ok((w, h));
+    }
+
+    /// Converts the pixels of a cel from the color depth of the file to RGBA
+    fn decode_pixels(
+        &self,
+        data: &Reader,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        layer: Option<usize>,
+    ) -> Result<image::RgbaImage, AsepriteError> {
+        let bytes_per_pixel = (self.depth / 8) as usize;
+        let count = data.size(&[width, height])? as usize;
+        if pixels.len() < count * bytes_per_pixel {
+            return Err(data.invalid("cel has fewer pixels than its size"));
+        }
+
+        // The transparent index is opaque on the background layer
+        let is_background = layer
+            .and_then(|layer| self.layer_flags.get(layer))
+            .is_some_and(|flags| flags & LAYER_BACKGROUND != 0);
+
+        let mut rgba = Vec::with_capacity(count * 4);
+        for pixel in pixels.chunks_exact(bytes_per_pixel).take(count) {
+            let color = match self.depth {
+                32 => [pixel[0], pixel[1], pixel[2], pixel[3]],
+                16 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+                _ if pixel[0] == self.transparent_index && !is_background => [0, 0, 0, 0],
+                _ => self
+                    .palette
+                    .get(pixel[0] as usize)
+                    .copied()
+                    .unwrap_or([0, 0, 0, 0]),
+            };
+            rgba.extend_from_slice(&color);
+        }
+
+        return Ok(image::RgbaImage::from_raw(width, height, rgba)
+            .expect("Decoded cel size does not match its pixels"));
+    }
+
+    /// Draws the tiles of a tilemap cel into one image
+    fn read_tilemap(
+        &self,
+        data: &mut Reader,
+        layer: usize,
+    ) -> Result<image::RgbaImage, AsepriteError> {
+        let columns = data.word()? as u32;
+        let rows = data.word()? as u32;
+        let bits = data.word()?;
+        let id_mask = data.dword()?;
+        let x_flip = data.dword()?;
+        let y_flip = data.dword()?;
+        let diagonal_flip = data.dword()?;
+        data.skip(10)?;
+
+        if bits != 32 {
+            return Err(data.invalid(&format!("unsupported {} bits per tile", bits)));
+        }
+
+        let tiles = miniz_oxide::inflate::decompress_to_vec_zlib(data.rest())
+            .map_err(|e| data.invalid(&format!("could not inflate tilemap: {:?}", e)))?;
+        let tile_total = data.size(&[columns, rows])?;
+        if tiles.len() < data.size(&[tile_total, 4])? as usize {
+            return Err(data.invalid("tilemap has fewer tiles than its size"));
+        }
+
+        let tileset = self
+            .layer_tilesets
+            .get(layer)
+            .copied()
+            .flatten()
+            .and_then(|id| self.tilesets.get(&id));
+        let Some(tileset) = tileset else {
+            return Err(data.invalid("tilemap cel without an embedded tileset"));
+        };
+
+        let (tile_width, tile_height) = (tileset.tile_width, tileset.tile_height);
+        let tile_count = tileset.image.height() / tile_height.max(1);
+        let image_width = data.size(&[columns, tile_width])?;
+        let image_height = data.size(&[rows, tile_height])?;
+        data.size(&[image_width, image_height, 4])?;
+        let mut image = image::RgbaImage::new(image_width, image_height);
+
+        for (index, tile) in tiles.chunks_exact(4).take(tile_total as usize).enumerate() {
+            let tile = u32::from_le_bytes([tile[0], tile[1], tile[2], tile[3]]);
+            let id = tile & id_mask;
+            if id >= tile_count {
+                continue;
+            }
+
+            let origin_x = index as u32 % columns * tile_width;
+            let origin_y = index as u32 / columns * tile_height;
+            for ty in 0..tile_height {
+                for tx in 0..tile_width {
+                    // Aseprite applies the diagonal flip first, swapping the axes
+                    let (mut sx, mut sy) = match tile & diagonal_flip != 0 {
+                        true => (ty, tx),
+                        false => (tx, ty),
+                    };
+                    // Swapped axes of a tile that is not square fall outside of it
+                    if sx >= tile_width || sy >= tile_height {
+                        continue;
+                    }
+                    if tile & x_flip != 0 {
+                        sx = tile_width - 1 - sx;
+                    }
+                    if tile & y_flip != 0 {
+                        sy = tile_height - 1 - sy;
+                    }
+
+                    let pixel = *tileset.image.get_pixel(sx, id * tile_height + sy);
+                    image.put_pixel(origin_x + tx, origin_y + ty, pixel);
+                }
+            }
+        }
+
+        return Ok(image);
+    }
+
+    fn read_tileset(&mut self, data: &mut Reader) -> Result<(), AsepriteError> {
+        let id = data.dword()?;
+        let flags = data.dword()?;
+        let tile_count = data.dword()?;
+        let tile_width = data.word()? as u32;
+        let tile_height = data.word()? as u32;
+        data.skip(16)?;
+        data.string()?;
+
+        if flags & 1 != 0 {
+            data.skip(8)?;
+        }
+
+        // Tiles of external tilesets stay empty
+        if flags & TILESET_EMBEDDED == 0 {
+            return Ok(());
+        }
+
+        let length = data.dword()? as usize;
+        let compressed = data.take(length)?;
+        let pixels = miniz_oxide::inflate::decompress_to_vec_zlib(compressed)
+            .map_err(|e| data.invalid(&format!("could not inflate tileset: {:?}", e)))?;
+        let height = data.size(&[tile_height, tile_count])?;
+        let image = self.decode_pixels(data, tile_width, height, &pixels, None)?;
+
+        self.tilesets.insert(
+            id,
+            Tileset {
+                tile_width,
+                tile_height,
+                image,
+            },
+        );
+
+        return Ok(());
+    }
+
+    /// Only indexed images draw from the palette, the other depths skip it
+    fn read_palette(&mut self, data: &mut Reader) -> Result<(), AsepriteError> {
+        if self.depth != 8 {
+            return Ok(());
+        }
+
+        let size = data.dword()? as usize;
+        let first = data.dword()? as usize;
+        let last = data.dword()? as usize;
+        data.skip(8)?;
+
+        if size > MAX_PALETTE_SIZE {
+            return Err(data.invalid(&format!("palette of {} colors", size)));
+        }
+
+        if self.palette.len() < size {
+            self.palette.resize(size, [0, 0, 0, 0]);
+        }
+
+        for index in first..=last {
+            let flags = data.word()?;
+            let color = [data.byte()?, data.byte()?, data.byte()?, data.byte()?];
+            if flags & 1 != 0 {
+                data.string()?;
+            }
+
+            if index >= self.palette.len() {
+                return Err(data.invalid("palette entry out of the palette"));
+            }
+            self.palette[index] = color;
+        }
+
+        return Ok(());
+    }
+
+    /// Palette chunk of files saved before Aseprite 1.2, only read without a new one
+    fn read_old_palette(&mut self, data: &mut Reader) -> Result<(), AsepriteError> {
+        if self.depth != 8 {
+            return Ok(());
+        }
+
+        let packets = data.word()?;
+        let mut index = 0;
+
+        for _ in 0..packets {
+            index += data.byte()? as usize;
+            let count = match data.byte()? {
+                0 => 256,
+                n => n as usize,
+            };
+
+            for _ in 0..count {
+                let color = [data.byte()?, data.byte()?, data.byte()?, 255];
+                if index >= MAX_PALETTE_SIZE {
+                    return Err(data.invalid("palette entry out of the palette"));
+                }
+                if self.palette.len() <= index {
+                    self.palette.resize(index + 1, [0, 0, 0, 0]);
+                }
+                self.palette[index] = color;
+                index += 1;
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn read_tags(&mut self, data: &mut Reader) -> Result<(), AsepriteError> {
+        let count = data.word()?;
+        data.skip(8)?;
+
+        for _ in 0..count {
+            let from = data.word()? as usize;
+            let to = data.word()? as usize;
+            let direction = match data.byte()? {
+                0 => AnimationDirection::Forward,
+                1 => AnimationDirection::Reverse,
+                2 => AnimationDirection::PingPong,
+                3 => AnimationDirection::PingPongReverse,
+                other => return Err(data.invalid(&format!("unknown tag direction {}", other))),
+            };
+            let repeat = data.word()?;
+            data.skip(10)?;
+            let name = data.string()?;
+
+            self.tags.push(AsepriteTag {
+                name,
+                from,
+                to,
+                direction,
+                repeat,
+            });
+        }
+
+        return Ok(());
+    }
+
+    fn read_slice(&mut self, data: &mut Reader) -> Result<(), AsepriteError> {
+        let count = data.dword()?;
+        let flags = data.dword()?;
+        data.skip(4)?;
+        let name = data.string()?;
+
+        let mut keys = Vec::new();
+        for _ in 0..count {
+            let frame = data.dword()? as usize;
+            let bounds = data.slice_rect()?;
+            let center = match flags & SLICE_NINE_PATCH != 0 {
+                true => Some(data.slice_rect()?),
+                false => None,
+            };
+            let pivot = match flags & SLICE_PIVOT != 0 {
+                true => Some(glam::IVec2::new(data.long()?, data.long()?)),
+                false => None,
+            };
+
+            keys.push(SliceKey {
+                frame,
+                bounds,
+                center,
+                pivot,
+            });
+        }
+
+        self.slices.push(AsepriteSlice { name, keys });
+        return Ok(());
+    }
+}
+
+/// End of a slice on one axis clamped to the canvas, in i64 since the file can hold
+/// any start and size
+fn slice_end(start: i32, size: u32, canvas: u32) -> u32 {
+    return (start as i64 + size as i64).clamp(0, canvas as i64) as u32;
+}
+
+/// Composes the cels of each frame bottom layer first
+fn flatten(
+    frames_cels: &[Vec<CelContent>],
+    state: &ParseState,
+    width: u32,
+    height: u32,
+    durations: Vec<Duration>,
+) -> Result<Vec<AsepriteFrame>, AsepriteError> {
+    let visible = visible_layers(&state.layers);
+    let mut frames = Vec::with_capacity(frames_cels.len());
+
+    for (cels, duration) in frames_cels.iter().zip(durations) {
+        let mut resolved: Vec<&Cel> = Vec::new();
+        for content in cels {
+            let cel = match content {
+                CelContent::Image(cel) => cel,
+                CelContent::Linked { layer, frame } => {
+                    match find_cel(frames_cels, *layer, *frame) {
+                        Some(cel) => cel,
+                        None => {
+                            return Err(AsepriteError::Invalid {
+                                offset: 0,
+                                message: format!("cel linked to an empty frame {}", frame),
+                            })
+                        }
+                    }
+                }
+            };
+            resolved.push(cel);
+        }
+        resolved.sort_by_key(|cel| cel.layer);
+
+        let mut image = image::RgbaImage::new(width, height);
+        for cel in resolved {
+            if !visible.get(cel.layer).copied().unwrap_or(false) {
+                continue;
+            }
+
+            let opacity = cel.opacity as u32 * state.layers[cel.layer].opacity as u32 / 255;
+            blend_cel(&mut image, cel, opacity as u8);
+        }
+
+        frames.push(AsepriteFrame { image, duration });
+    }
+
+    return Ok(frames);
+}
+
+fn find_cel(frames_cels: &[Vec<CelContent>], layer: usize, frame: usize) -> Option<&Cel> {
+    return frames_cels
+        .get(frame)?
+        .iter()
+        .find_map(|content| match content {
+            CelContent::Image(cel) if cel.layer == layer => Some(cel),
+            _ => None,
+        });
+}
+
+/// A layer is drawn when it and every group above it are visible
+fn visible_layers(layers: &[AsepriteLayer]) -> Vec<bool> {
+    // Visibility of the group at each level above the current layer
+    let mut groups: Vec<bool> = Vec::new();
+    let mut visible = Vec::with_capacity(layers.len());
+
+    for layer in layers {
+        let level = layer.child_level as usize;
+        groups.truncate(level);
+        let parents_visible = groups.iter().all(|v| *v);
+
+        visible.push(parents_visible && layer.visible && !layer.is_group);
+        if layer.is_group {
+            groups.resize(level, true);
+            groups.push(layer.visible);
+        }
+    }
+
+    return visible;
+}
+
+/// Draws the cel over the image with the "normal" blend mode
+fn blend_cel(image: &mut image::RgbaImage, cel: &Cel, opacity: u8) {
+    for (x, y, source) in cel.image.enumerate_pixels() {
+        let tx = cel.x + x as i32;
+        let ty = cel.y + y as i32;
+        if tx < 0 || ty < 0 || tx >= image.width() as i32 || ty >= image.height() as i32 {
+            continue;
+        }
+
+        let target = image.get_pixel_mut(tx as u32, ty as u32);
+        let source_alpha = source.0[3] as f32 / 255.0 * opacity as f32 / 255.0;
+        let target_alpha = target.0[3] as f32 / 255.0;
+        let alpha = source_alpha + target_alpha * (1.0 - source_alpha);
+        if alpha <= 0.0 {
+            continue;
+        }
+
+        for i in 0..3 {
+            let color = (source.0[i] as f32 * source_alpha
+                + target.0[i] as f32 * target_alpha * (1.0 - source_alpha))
+                / alpha;
+            target.0[i] = color.round() as u8;
+        }
+        target.0[3] = (alpha * 255.0).round() as u8;
+    }
+}
+
+/// Little-endian reader over a byte range of the file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    end: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        return Self::at(bytes, 0);
+    }
+
+    fn at(bytes: &'a [u8], offset: usize) -> Self {
+        return Self {
+            bytes,
+            offset,
+            end: bytes.len(),
+        };
+    }
+
+    /// Reader over the remaining bytes up to `end`
+    fn limit(&self, end: usize) -> Result<Reader<'a>, AsepriteError> {
+        if end > self.end {
+            return Err(self.invalid("chunk goes past the end of the file"));
+        }
+
+        return Ok(Reader {
+            bytes: self.bytes,
+            offset: self.offset,
+            end,
+        });
+    }
+
+    /// Product of sizes read from the file, a corrupt file can make it overflow
+    fn size(&self, factors: &[u32]) -> Result<u32, AsepriteError> {
+        return factors
+            .iter()
+            .try_fold(1u32, |product, factor| product.checked_mul(*factor))
+            .ok_or_else(|| self.invalid("size is too large"));
+    }
+
+    fn invalid(&self, message: &str) -> AsepriteError {
+        return AsepriteError::Invalid {
+            offset: self.offset,
+            message: message.to_string(),
+        };
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], AsepriteError> {
+        if self.offset + count > self.end {
+            return Err(self.invalid("unexpected end of data"));
+        }
+
+        let bytes = &self.bytes[self.offset..self.offset + count];
+        self.offset += count;
+        return Ok(bytes);
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), AsepriteError> {
+        self.take(count)?;
+        return Ok(());
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.bytes[self.offset.min(self.end)..self.end];
+        self.offset = self.end;
+        return bytes;
+    }
+
+    fn byte(&mut self) -> Result<u8, AsepriteError> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    fn word(&mut self) -> Result<u16, AsepriteError> {
+        let b = self.take(2)?;
+        return Ok(u16::from_le_bytes([b[0], b[1]]));
+    }
+
+    fn short(&mut self) -> Result<i16, AsepriteError> {
+        return Ok(self.word()? as i16);
+    }
+
+    fn dword(&mut self) -> Result<u32, AsepriteError> {
+        let b = self.take(4)?;
+        return Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    }
+
+    fn long(&mut self) -> Result<i32, AsepriteError> {
+        return Ok(self.dword()? as i32);
+    }
+
+    fn string(&mut self) -> Result<String, AsepriteError> {
+        let length = self.word()? as usize;
+        let bytes = self.take(length)?;
+        return Ok(String::from_utf8_lossy(bytes).to_string());
+    }
+
+    fn slice_rect(&mut self) -> Result<SliceRect, AsepriteError> {
+        return Ok(SliceRect {
+            x: self.long()?,
+            y: self.long()?,
+            width: self.dword()?,
+            height: self.dword()?,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes aseprite files chunk by chunk, only what the tests need
+    struct Builder {
+        width: u16,
+        height: u16,
+        frames: Vec<(u16, Vec<Vec<u8>>)>,
+    }
+
+    impl Builder {
+        fn new(width: u16, height: u16) -> Self {
+            return Self {
+                width,
+                height,
+                frames: Vec::new(),
+            };
+        }
+
+        fn frame(&mut self, duration: u16) -> &mut Self {
+            self.frames.push((duration, Vec::new()));
+            return self;
+        }
+
+        fn chunk(&mut self, chunk_type: u16, data: Vec<u8>) -> &mut Self {
+            let mut chunk = Vec::new();
+            chunk.extend_from_slice(&(data.len() as u32 + 6).to_le_bytes());
+            chunk.extend_from_slice(&chunk_type.to_le_bytes());
+            chunk.extend_from_slice(&data);
+
+            self.frames.last_mut().unwrap().1.push(chunk);
+            return self;
+        }
+
+        fn layer(&mut self, name: &str, visible: bool, opacity: u8, level: u16) -> &mut Self {
+            let mut data = Vec::new();
+            data.extend_from_slice(&(visible as u16).to_le_bytes());
+            data.extend_from_slice(&0u16.to_le_bytes());
+            data.extend_from_slice(&level.to_le_bytes());
+            data.extend_from_slice(&[0; 6]);
+            data.push(opacity);
+            data.extend_from_slice(&[0; 3]);
+            push_string(&mut data, name);
+
+            return self.chunk(CHUNK_LAYER, data);
+        }
+
+        fn group(&mut self, name: &str, visible: bool) -> &mut Self {
+            self.layer(name, visible, 255, 0);
+
+            // The layer type follows the flags
+            let chunk = self.frames.last_mut().unwrap().1.last_mut().unwrap();
+            chunk[8..10].copy_from_slice(&LAYER_TYPE_GROUP.to_le_bytes());
+            return self;
+        }
+
+        fn cel(&mut self, layer: u16, x: i16, y: i16, size: [u16; 2], color: [u8; 4]) -> &mut Self {
+            let pixels: Vec<u8> = (0..size[0] * size[1]).flat_map(|_| color).collect();
+
+            let mut data = cel_header(layer, x, y, CEL_COMPRESSED);
+            data.extend_from_slice(&size[0].to_le_bytes());
+            data.extend_from_slice(&size[1].to_le_bytes());
+            data.extend_from_slice(&miniz_oxide::deflate::compress_to_vec_zlib(&pixels, 6));
+
+            return self.chunk(CHUNK_CEL, data);
+        }
+
+        fn tilemap_layer(&mut self, name: &str, tileset: u32) -> &mut Self {
+            let mut data = Vec::new();
+            data.extend_from_slice(&1u16.to_le_bytes());
+            data.extend_from_slice(&LAYER_TYPE_TILEMAP.to_le_bytes());
+            data.extend_from_slice(&[0; 8]);
+            data.push(255);
+            data.extend_from_slice(&[0; 3]);
+            push_string(&mut data, name);
+            data.extend_from_slice(&tileset.to_le_bytes());
+
+            return self.chunk(CHUNK_LAYER, data);
+        }
+
+        /// Embedded tileset, the tiles are stacked vertically in `pixels`
+        fn tileset(
+            &mut self,
+            id: u32,
+            tile_size: [u16; 2],
+            count: u32,
+            pixels: &[u8],
+        ) -> &mut Self {
+            let mut data = Vec::new();
+            data.extend_from_slice(&id.to_le_bytes());
+            data.extend_from_slice(&TILESET_EMBEDDED.to_le_bytes());
+            data.extend_from_slice(&count.to_le_bytes());
+            data.extend_from_slice(&tile_size[0].to_le_bytes());
+            data.extend_from_slice(&tile_size[1].to_le_bytes());
+            data.extend_from_slice(&[0; 16]);
+            push_string(&mut data, "tiles");
+            let compressed = miniz_oxide::deflate::compress_to_vec_zlib(pixels, 6);
+            data.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            data.extend_from_slice(&compressed);
+
+            return self.chunk(CHUNK_TILESET, data);
+        }
+
+        /// Tilemap cel with the bit masks Aseprite writes: the id in the low bits, then
+        /// the x, y and diagonal flips
+        fn tilemap(&mut self, layer: u16, size: [u16; 2], tiles: &[u32]) -> &mut Self {
+            let mut data = cel_header(layer, 0, 0, CEL_TILEMAP);
+            data.extend_from_slice(&size[0].to_le_bytes());
+            data.extend_from_slice(&size[1].to_le_bytes());
+            data.extend_from_slice(&32u16.to_le_bytes());
+            for mask in [TILE_ID, TILE_X_FLIP, TILE_Y_FLIP, TILE_DIAGONAL_FLIP] {
+                data.extend_from_slice(&mask.to_le_bytes());
+            }
+            data.extend_from_slice(&[0; 10]);
+            let tiles: Vec<u8> = tiles.iter().flat_map(|t| t.to_le_bytes()).collect();
+            data.extend_from_slice(&miniz_oxide::deflate::compress_to_vec_zlib(&tiles, 6));
+
+            return self.chunk(CHUNK_CEL, data);
+        }
+
+        fn linked_cel(&mut self, layer: u16, frame: u16) -> &mut Self {
+            let mut data = cel_header(layer, 0, 0, CEL_LINKED);
+            data.extend_from_slice(&frame.to_le_bytes());
+
+            return self.chunk(CHUNK_CEL, data);
+        }
+
+        fn tag(&mut self, name: &str, from: u16, to: u16, direction: u8, repeat: u16) -> &mut Self {
+            let mut data = Vec::new();
+            data.extend_from_slice(&1u16.to_le_bytes());
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&from.to_le_bytes());
+            data.extend_from_slice(&to.to_le_bytes());
+            data.push(direction);
+            data.extend_from_slice(&repeat.to_le_bytes());
+            data.extend_from_slice(&[0; 10]);
+            push_string(&mut data, name);
+
+            return self.chunk(CHUNK_TAGS, data);
+        }
+
+        fn slice(&mut self, name: &str, bounds: [i32; 4], pivot: [i32; 2]) -> &mut Self {
+            let mut data = Vec::new();
+            data.extend_from_slice(&1u32.to_le_bytes());
+            data.extend_from_slice(&SLICE_PIVOT.to_le_bytes());
+            data.extend_from_slice(&[0; 4]);
+            push_string(&mut data, name);
+            data.extend_from_slice(&0u32.to_le_bytes());
+            for value in bounds.iter().chain(pivot.iter()) {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+
+            return self.chunk(CHUNK_SLICE, data);
+        }
+
+        fn build(&self) -> Vec<u8> {
+            let mut body = Vec::new();
+            for (duration, chunks) in &self.frames {
+                let size: usize = FRAME_HEADER_SIZE + chunks.iter().map(|c| c.len()).sum::<usize>();
+                body.extend_from_slice(&(size as u32).to_le_bytes());
+                body.extend_from_slice(&FRAME_MAGIC.to_le_bytes());
+                body.extend_from_slice(&(chunks.len() as u16).to_le_bytes());
+                body.extend_from_slice(&duration.to_le_bytes());
+                body.extend_from_slice(&[0; 2]);
+                body.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+                for chunk in chunks {
+                    body.extend_from_slice(chunk);
+                }
+            }
+
+            let mut file = vec![0; HEADER_SIZE];
+            file[0..4].copy_from_slice(&((HEADER_SIZE + body.len()) as u32).to_le_bytes());
+            file[4..6].copy_from_slice(&FILE_MAGIC.to_le_bytes());
+            file[6..8].copy_from_slice(&(self.frames.len() as u16).to_le_bytes());
+            file[8..10].copy_from_slice(&self.width.to_le_bytes());
+            file[10..12].copy_from_slice(&self.height.to_le_bytes());
+            file[12..14].copy_from_slice(&32u16.to_le_bytes());
+            file.extend_from_slice(&body);
+
+            return file;
+        }
+    }
+
+    fn push_string(data: &mut Vec<u8>, value: &str) {
+        data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        data.extend_from_slice(value.as_bytes());
+    }
+
+    fn cel_header(layer: u16, x: i16, y: i16, cel_type: u16) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&layer.to_le_bytes());
+        data.extend_from_slice(&x.to_le_bytes());
+        data.extend_from_slice(&y.to_le_bytes());
+        data.push(255);
+        data.extend_from_slice(&cel_type.to_le_bytes());
+        data.extend_from_slice(&[0; 7]);
+        return data;
+    }
+
+    const TILE_ID: u32 = 0x1fff_ffff;
+    const TILE_X_FLIP: u32 = 0x2000_0000;
+    const TILE_Y_FLIP: u32 = 0x4000_0000;
+    const TILE_DIAGONAL_FLIP: u32 = 0x8000_0000;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    /// Two frames of a 4x4 canvas: a red background, a blue square on top of it,
+    /// a hidden layer and a group hidden with its child
+    fn sample() -> Vec<u8> {
+        let mut builder = Builder::new(4, 4);
+        builder
+            .frame(100)
+            .layer("background", true, 255, 0)
+            .layer("square", true, 255, 0)
+            .layer("hidden", false, 255, 0)
+            .group("hidden group", false)
+            .layer("in hidden group", true, 255, 1)
+            .tag("walk", 0, 1, 2, 3)
+            .slice("square", [1, 1, 2, 2], [1, 2])
+            .cel(0, 0, 0, [4, 4], RED)
+            .cel(1, 1, 1, [2, 2], BLUE)
+            .cel(2, 0, 0, [4, 4], [0, 255, 0, 255])
+            .cel(4, 0, 0, [4, 4], [0, 255, 0, 255])
+            .frame(250)
+            .linked_cel(0, 0)
+            .cel(1, 2, 2, [4, 4], [0, 0, 255, 128]);
+
+        return builder.build();
+    }
+
+    #[test]
+    fn test_header_layers_and_durations() {
+        let file = AsepriteFile::from_bytes(&sample()).unwrap();
+
+        assert_eq!((file.width, file.height), (4, 4));
+        let names: Vec<&str> = file.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "background",
+                "square",
+                "hidden",
+                "hidden group",
+                "in hidden group"
+            ]
+        );
+        assert!(file.layers[3].is_group);
+        assert_eq!(file.layers[4].child_level, 1);
+
+        let durations: Vec<Duration> = file.frames.iter().map(|f| f.duration).collect();
+        assert_eq!(
+            durations,
+            vec![Duration::from_millis(100), Duration::from_millis(250)]
+        );
+    }
+
+    #[test]
+    fn test_flattens_visible_layers_only() {
+        let file = AsepriteFile::from_bytes(&sample()).unwrap();
+        let first = &file.frames[0].image;
+
+        assert_eq!(first.get_pixel(0, 0).0, RED);
+        assert_eq!(first.get_pixel(1, 1).0, BLUE);
+        assert_eq!(first.get_pixel(2, 2).0, BLUE);
+        assert_eq!(first.get_pixel(3, 3).0, RED);
+    }
+
+    #[test]
+    fn test_linked_cel_and_blending() {
+        let file = AsepriteFile::from_bytes(&sample()).unwrap();
+        let second = &file.frames[1].image;
+
+        // The background is linked to the first frame, the square is half transparent
+        // and hangs off the canvas
+        assert_eq!(second.get_pixel(0, 0).0, RED);
+        assert_eq!(second.get_pixel(3, 3).0, [127, 0, 128, 255]);
+    }
+
+    #[test]
+    fn test_tags_and_slices() {
+        let file = AsepriteFile::from_bytes(&sample()).unwrap();
+
+        let tag = file.tag("walk").unwrap();
+        assert_eq!((tag.from, tag.to), (0, 1));
+        assert_eq!(tag.direction, AnimationDirection::PingPong);
+        assert_eq!(tag.repeat, 3);
+        assert!(file.tag("run").is_none());
+
+        assert_eq!(file.slices.len(), 1);
+        let key = &file.slices[0].keys[0];
+        assert_eq!(
+            key.bounds,
+            SliceRect {
+                x: 1,
+                y: 1,
+                width: 2,
+                height: 2
+            }
+        );
+        assert_eq!(key.center, None);
+        assert_eq!(key.pivot, Some(glam::IVec2::new(1, 2)));
+    }
+
+    #[test]
+    fn test_register_sprites_and_clips() {
+        let file = AsepriteFile::from_bytes(&sample()).unwrap();
+        let sheet = file.sheet();
+        assert_eq!(sheet.image.dimensions(), (8, 4));
+        assert_eq!(sheet.image.get_pixel(4, 0).0, RED);
+
+        let mut registry = AssetsRegistry::new();
+        let texture = registry.insert_texture(crate::assets::texture::Texture::new());
+        let asset = file.register(&sheet, texture, &mut registry);
+
+        assert_eq!(asset.frames.len(), 2);
+        let second = registry.sprite(asset.frames[1]).unwrap();
+        assert_eq!(second.texture_id(), texture);
+        assert_eq!(second.uv().to_array(), [0.5, 0.0, 0.5, 1.0]);
+
+        let clip = registry.animation(asset.animations["walk"]).unwrap();
+        assert_eq!(clip.frames().len(), 2);
+        assert_eq!(clip.repeat(), 3);
+        assert_eq!(clip.cycle(), Duration::from_millis(350));
+
+        let slice = registry.sprite(asset.slices["square"]).unwrap();
+        assert_eq!(slice.uv().to_array(), [0.125, 0.25, 0.25, 0.5]);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(matches!(
+            AsepriteFile::from_bytes(&[0; 200]),
+            Err(AsepriteError::Invalid { offset: 6, .. })
+        ));
+
+        let mut truncated = sample();
+        truncated.truncate(truncated.len() - 10);
+        assert!(AsepriteFile::from_bytes(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_oversized_dimensions() {
+        let invalid_size = |builder: &Builder| {
+            return matches!(
+                AsepriteFile::from_bytes(&builder.build()),
+                Err(AsepriteError::Invalid { message, .. }) if message == "size is too large"
+            );
+        };
+
+        let mut builder = Builder::new(4, 4);
+        builder.frame(100).tileset(0, [16, 16], u32::MAX, &[0; 64]);
+        assert!(invalid_size(&builder));
+
+        let mut builder = Builder::new(4, 4);
+        builder
+            .frame(100)
+            .tileset(0, [16, 16], 1, &[0; 1024])
+            .tilemap_layer("map", 0)
+            .tilemap(0, [u16::MAX, u16::MAX], &[0; 4]);
+        assert!(invalid_size(&builder));
+    }
+
+    #[test]
+    fn test_hostile_values() {
+        // Swapping the axes of a 1x2 tile reads outside of it, those pixels stay empty
+        let mut builder = Builder::new(2, 2);
+        builder
+            .frame(100)
+            .tileset(0, [1, 2], 1, &[RED, BLUE].concat())
+            .tilemap_layer("map", 0)
+            .tilemap(0, [1, 1], &[TILE_DIAGONAL_FLIP | TILE_X_FLIP]);
+        let file = AsepriteFile::from_bytes(&builder.build()).unwrap();
+        assert_eq!(file.frames[0].image.get_pixel(0, 1).0, [0, 0, 0, 0]);
+
+        // A slice reaching past the largest coordinate is clamped to the canvas
+        let mut builder = Builder::new(4, 4);
+        builder
+            .frame(100)
+            .layer("background", true, 255, 0)
+            .slice("huge", [i32::MAX, 0, i32::MAX, 1], [0, 0])
+            .cel(0, 0, 0, [4, 4], RED);
+        let file = AsepriteFile::from_bytes(&builder.build()).unwrap();
+        let mut registry = AssetsRegistry::new();
+        let texture = registry.insert_texture(crate::assets::texture::Texture::new());
+        let asset = file.register(&file.sheet(), texture, &mut registry);
+        assert!(asset.slices.is_empty());
+
+        // Cels are checked before inflating their pixels
+        let mut cel = cel_header(0, 0, 0, CEL_COMPRESSED);
+        cel.extend_from_slice(&u16::MAX.to_le_bytes());
+        cel.extend_from_slice(&u16::MAX.to_le_bytes());
+        cel.extend_from_slice(b"not zlib");
+        let mut builder = Builder::new(4, 4);
+        builder
+            .frame(100)
+            .layer("background", true, 255, 0)
+            .chunk(CHUNK_CEL, cel);
+        assert!(matches!(
+            AsepriteFile::from_bytes(&builder.build()),
+            Err(AsepriteError::Invalid { message, .. }) if message == "cel larger than the canvas"
+        ));
+
+        // Indexed images have at most 256 colors
+        let mut palette = Vec::new();
+        for value in [u32::MAX, 0, 0] {
+            palette.extend_from_slice(&value.to_le_bytes());
+        }
+        palette.extend_from_slice(&[0; 14]);
+        let mut builder = Builder::new(4, 4);
+        builder.frame(100).chunk(CHUNK_PALETTE, palette);
+        let mut bytes = builder.build();
+        bytes[12..14].copy_from_slice(&8u16.to_le_bytes());
+        assert!(matches!(
+            AsepriteFile::from_bytes(&bytes),
+            Err(AsepriteError::Invalid { message, .. }) if message.starts_with("palette of")
+        ));
+    }
+
+    #[test]
+    fn test_loads_project_files() {
+        let root = concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/aseprite/");
+
+        let farm = AsepriteFile::load(format!("{}farm-game.aseprite", root)).unwrap();
+        assert_eq!((farm.width, farm.height), (320, 320));
+        assert_eq!(farm.frames.len(), 1);
+        // Both files are a single tilemap layer drawn from an embedded tileset
+        let image = &farm.frames[0].image;
+        assert_eq!(image.get_pixel(0, 0).0[3], 255);
+        assert_ne!(image.get_pixel(0, 0), image.get_pixel(160, 80));
+
+        let tileset = AsepriteFile::load(format!("{}tileset.aseprite", root)).unwrap();
+        assert_eq!((tileset.width, tileset.height), (500, 500));
+        assert!(tileset.frames[0].image.pixels().any(|p| p.0[3] > 0));
+    }
+}
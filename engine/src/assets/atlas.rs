@@ -1,60 +1,576 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
 
-use crate::math::{self, units::Pixels};
+use ron::value::RawValue;
 
+use crate::{
+    assets::{sprite::Sprite, texture::Texture, AssetId, Handle, SpriteId},
+    ecs::resource::Resource,
+    math::{self, units::Pixels},
+};
+
+/// Named regions of a texture, each drawn as a sprite
+///
+/// Built in code with `Atlas::new`, or from a RON description with `AtlasFile::load`
+/// so sprites can be added without touching Rust
 pub struct Atlas {
-    texture: Arc<Texture>,
+    texture: AssetId,
     tile_size: [Pixels; 2],
     regions: HashMap<SpriteId, AtlasRegion>,
+    names: HashMap<String, SpriteId>,
+
+    /// Keeps a texture loaded by path alive as long as the atlas
+    texture_handle: Option<Handle<Texture>>,
 }
 
-struct AtlasRegion {
+impl Resource for Atlas {}
+
+pub struct AtlasRegion {
     id: SpriteId,
     uv: math::uv::UvRect,
 }
 
+impl AtlasRegion {
+    pub fn id(&self) -> SpriteId {
+        return self.id;
+    }
+
+    pub fn uv(&self) -> math::uv::UvRect {
+        return self.uv;
+    }
+}
+
+/// Where a region is in the texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum RegionPlacement {
+    /// A single cell of the grid, by column and row
+    Cell(u32, u32),
+
+    /// A block of cells, e.g. a tree two tiles high
+    Cells { x: u32, y: u32, w: u32, h: u32 },
+
+    /// Pixels, ignoring the grid
+    Rect { x: u32, y: u32, w: u32, h: u32 },
+}
+
 pub struct AtlasRegionDescriptor {
     pub id: SpriteId,
-    pub coords: glam::UVec2,
+    pub name: Option<String>,
+    pub placement: RegionPlacement,
 }
 
 pub struct AtlasConfig {
     pub tile_size: [Pixels; 2],
+
+    /// Pixels between the texture border and the first cells
+    pub margin: [Pixels; 2],
+
+    /// Pixels between two cells
+    pub spacing: [Pixels; 2],
+}
+
+#[derive(Debug)]
+pub enum AtlasErrorKind {
+    Io(std::io::Error),
+    Parse(Box<ron::error::SpannedError>),
+    Image(Box<image::ImageError>),
+    DuplicateId(SpriteId),
+    DuplicateName(String),
+
+    /// A region reaching outside of the texture, by name or id
+    OutOfBounds(String),
+}
+
+/// Line of the atlas description an error comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+#[derive(Debug)]
+pub struct AtlasError {
+    /// `None` for atlases built in code
+    pub location: Option<SourceLocation>,
+    pub kind: AtlasErrorKind,
+
+    /// Index of the region definition the error comes from
+    region: Option<usize>,
+}
+
+impl AtlasError {
+    fn new(kind: AtlasErrorKind) -> Self {
+        return Self {
+            location: None,
+            kind,
+            region: None,
+        };
+    }
+
+    fn in_region(mut self, index: usize) -> Self {
+        self.region = Some(index);
+        return self;
+    }
+
+    fn at(mut self, path: &Path, line: usize) -> Self {
+        self.location = Some(SourceLocation {
+            path: path.to_path_buf(),
+            line,
+        });
+        return self;
+    }
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{}:{}: ", location.path.display(), location.line)?;
+        }
+
+        return match &self.kind {
+            AtlasErrorKind::Io(e) => write!(f, "could not read atlas: {}", e),
+            AtlasErrorKind::Parse(e) => write!(f, "could not parse atlas: {}", e.code),
+            AtlasErrorKind::Image(e) => write!(f, "could not read atlas texture: {}", e),
+            AtlasErrorKind::DuplicateId(id) => write!(f, "duplicated definition for sprite {}", id),
+            AtlasErrorKind::DuplicateName(name) => {
+                write!(f, "duplicated definition for sprite \"{}\"", name)
+            }
+            AtlasErrorKind::OutOfBounds(region) => {
+                write!(f, "sprite {} reaches outside of the texture", region)
+            }
+        };
+    }
+}
+
+impl std::error::Error for AtlasError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match &self.kind {
+            AtlasErrorKind::Io(e) => Some(e),
+            AtlasErrorKind::Parse(e) => Some(e.as_ref()),
+            AtlasErrorKind::Image(e) => Some(e.as_ref()),
+            _ => None,
+        };
+    }
 }
 
 impl Atlas {
-    pub fn new<I>(texture: Arc<Texture>, definitions: I, config: AtlasConfig) -> Self
+    pub fn new<I>(
+        texture: AssetId,
+        texture_size: [Pixels; 2],
+        definitions: I,
+        config: AtlasConfig,
+    ) -> Result<Self, AtlasError>
     where
         I: IntoIterator<Item = AtlasRegionDescriptor>,
     {
-        let dimensions = texture.dimensions();
-
         let mut regions: HashMap<SpriteId, AtlasRegion> = HashMap::new();
-        for d in definitions {
-            let uv = math::uv::UvRect::from_pixels(
-                [
-                    (d.coords.x as f32) * config.tile_size[0],
-                    (d.coords.y as f32) * config.tile_size[1],
-                ],
-                [config.tile_size[0], config.tile_size[1]],
-                [dimensions[0], dimensions[1]],
-            );
+        let mut names: HashMap<String, SpriteId> = HashMap::new();
 
-            let result = regions.insert(d.id, AtlasRegion { id: d.id, uv });
+        for (index, d) in definitions.into_iter().enumerate() {
+            let (coords, size) = pixel_rect(d.placement, &config);
+            let label = match &d.name {
+                Some(name) => format!("\"{}\"", name),
+                None => d.id.to_string(),
+            };
 
-            if result.is_some() {
-                panic!("duplicated definition for sprite {}", d.id)
+            let outside = (coords[0] + size[0]).value() > texture_size[0].value()
+                || (coords[1] + size[1]).value() > texture_size[1].value();
+            if outside {
+                let error = AtlasError::new(AtlasErrorKind::OutOfBounds(label));
+                return Err(error.in_region(index));
+            }
+
+            let uv = math::uv::UvRect::from_pixels(coords, size, texture_size);
+
+            if regions.insert(d.id, AtlasRegion { id: d.id, uv }).is_some() {
+                let error = AtlasError::new(AtlasErrorKind::DuplicateId(d.id));
+                return Err(error.in_region(index));
+            }
+
+            if let Some(name) = d.name {
+                if names.contains_key(&name) {
+                    let error = AtlasError::new(AtlasErrorKind::DuplicateName(name));
+                    return Err(error.in_region(index));
+                }
+                names.insert(name, d.id);
             }
         }
 
-        return Self {
+        return Ok(Self {
             texture,
             tile_size: config.tile_size,
             regions,
+            names,
+            texture_handle: None,
+        });
+    }
+
+    pub fn texture(&self) -> AssetId {
+        return self.texture;
+    }
+
+    /// Builds the atlas over a texture that is unloaded once the atlas and every other
+    /// handle to it are dropped
+    pub fn with_texture_handle(mut self, handle: Handle<Texture>) -> Self {
+        assert_eq!(
+            handle.id(),
+            self.texture,
+            "The handle must be the texture of the atlas"
+        );
+        self.texture_handle = Some(handle);
+        return self;
+    }
+
+    pub fn tile_size(&self) -> [Pixels; 2] {
+        return self.tile_size;
+    }
+
+    pub fn region(&self, sprite_id: SpriteId) -> Option<&AtlasRegion> {
+        return self.regions.get(&sprite_id);
+    }
+
+    pub fn id(&self, name: &str) -> Option<SpriteId> {
+        return self.names.get(name).copied();
+    }
+
+    pub fn sprite(&self, sprite_id: SpriteId) -> Option<Sprite> {
+        return self
+            .region(sprite_id)
+            .map(|region| Sprite::new(self.texture, region.uv));
+    }
+
+    pub fn sprite_by_name(&self, name: &str) -> Option<Sprite> {
+        return self.id(name).and_then(|id| self.sprite(id));
+    }
+}
+
+/// Top-left corner and size of the placement, in pixels
+fn pixel_rect(placement: RegionPlacement, config: &AtlasConfig) -> ([Pixels; 2], [Pixels; 2]) {
+    let cells = |x: u32, y: u32, w: u32, h: u32| {
+        let axis = |i: usize, cell: u32, count: u32| {
+            let start =
+                config.margin[i] + (cell as f32) * (config.tile_size[i] + config.spacing[i]);
+            let size = (count as f32) * config.tile_size[i]
+                + (count.saturating_sub(1) as f32) * config.spacing[i];
+            return (start, size);
         };
+
+        let (x, w) = axis(0, x, w);
+        let (y, h) = axis(1, y, h);
+        return ([x, y], [w, h]);
+    };
+
+    return match placement {
+        RegionPlacement::Cell(x, y) => cells(x, y, 1, 1),
+        RegionPlacement::Cells { x, y, w, h } => cells(x, y, w, h),
+        RegionPlacement::Rect { x, y, w, h } => (
+            [Pixels::new(x as f32), Pixels::new(y as f32)],
+            [Pixels::new(w as f32), Pixels::new(h as f32)],
+        ),
+    };
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AtlasFileRegion {
+    pub name: String,
+
+    /// Defaults to the id following the previous region
+    #[serde(default)]
+    pub id: Option<u16>,
+    pub at: RegionPlacement,
+}
+
+/// Atlas description, loaded from a RON file of the form
+///
+/// ```ron
+/// (
+///     texture: "atlas.png",
+///     tile_size: (16, 16),
+///     regions: [
+///         (name: "grass", at: Cell(1, 0)),
+///         (name: "tree", at: Cells(x: 0, y: 1, w: 1, h: 2), id: 10),
+///         (name: "sign", at: Rect(x: 32, y: 16, w: 12, h: 10)),
+///     ],
+/// )
+/// ```
+///
+/// with optional `margin: (x, y)` and `spacing: (x, y)` in pixels. The texture path is
+/// relative to the description file
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AtlasFile {
+    pub texture: PathBuf,
+    pub tile_size: (u32, u32),
+
+    #[serde(default)]
+    pub margin: (u32, u32),
+
+    #[serde(default)]
+    pub spacing: (u32, u32),
+    pub regions: Vec<AtlasFileRegion>,
+
+    /// Where the description was loaded from, to resolve the texture and report errors
+    #[serde(skip)]
+    path: PathBuf,
+
+    /// Lines of the `texture` value and of each region, for error messages
+    #[serde(skip)]
+    texture_line: usize,
+
+    #[serde(skip)]
+    region_lines: Vec<usize>,
+}
+
+/// Values of the description borrowed from its source, which tells where they are
+#[derive(serde::Deserialize)]
+struct AtlasFileSpans<'a> {
+    #[serde(borrow)]
+    texture: &'a RawValue,
+
+    #[serde(borrow)]
+    regions: Vec<&'a RawValue>,
+}
+
+/// Line of a value borrowed from `source`
+fn line_in(source: &str, value: &RawValue) -> usize {
+    let offset = value.trim().get_ron().as_ptr() as usize - source.as_ptr() as usize;
+    return source[..offset].matches('\n').count() + 1;
+}
+
+impl AtlasFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AtlasError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| AtlasError::new(AtlasErrorKind::Io(e)).at(path, 0))?;
+
+        return Self::from_ron(path, &source);
     }
 
-    pub fn region(&self, sprite_id: SpriteId) -> &AtlasRegion {
-        return self.regions.get(&sprite_id).unwrap();
+    /// `path` is used to resolve the texture path and in error messages
+    pub fn from_ron(path: impl AsRef<Path>, source: &str) -> Result<Self, AtlasError> {
+        let path = path.as_ref();
+        // Lets the optional fields be written `id: 10` instead of `id: Some(10)`
+        let options = ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME);
+
+        let parse_error = |e: ron::error::SpannedError| {
+            let line = e.span.start.line;
+            return AtlasError::new(AtlasErrorKind::Parse(Box::new(e))).at(path, line);
+        };
+
+        let mut file: AtlasFile = options.from_str(source).map_err(parse_error)?;
+        let spans: AtlasFileSpans = options.from_str(source).map_err(parse_error)?;
+
+        file.path = path.to_path_buf();
+        file.texture_line = line_in(source, spans.texture);
+        file.region_lines = spans.regions.iter().map(|r| line_in(source, r)).collect();
+
+        return Ok(file);
+    }
+
+    pub fn texture_path(&self) -> PathBuf {
+        return match self.path.parent() {
+            Some(dir) => dir.join(&self.texture),
+            None => self.texture.clone(),
+        };
+    }
+
+    /// Builds the atlas with the size of the texture file
+    pub fn build(&self, texture: AssetId) -> Result<Atlas, AtlasError> {
+        let texture_path = self.texture_path();
+        let (width, height) = image::image_dimensions(&texture_path).map_err(|e| {
+            return AtlasError::new(AtlasErrorKind::Image(Box::new(e)))
+                .at(&self.path, self.texture_line);
+        })?;
+
+        return self.build_with_size(texture, [width, height]);
+    }
+
+    pub fn build_with_size(
+        &self,
+        texture: AssetId,
+        texture_size: [u32; 2],
+    ) -> Result<Atlas, AtlasError> {
+        let pixels = |(x, y): (u32, u32)| [Pixels::new(x as f32), Pixels::new(y as f32)];
+
+        let mut next_id = 0;
+        let descriptors: Vec<AtlasRegionDescriptor> = self
+            .regions
+            .iter()
+            .map(|r| {
+                let id = r.id.unwrap_or(next_id);
+                next_id = id.wrapping_add(1);
+
+                return AtlasRegionDescriptor {
+                    id: SpriteId(id),
+                    name: Some(r.name.clone()),
+                    placement: r.at,
+                };
+            })
+            .collect();
+
+        let config = AtlasConfig {
+            tile_size: pixels(self.tile_size),
+            margin: pixels(self.margin),
+            spacing: pixels(self.spacing),
+        };
+        let size = pixels((texture_size[0], texture_size[1]));
+
+        return Atlas::new(texture, size, descriptors, config).map_err(|e| {
+            let line = e
+                .region
+                .and_then(|index| self.region_lines.get(index))
+                .copied()
+                .unwrap_or(0);
+            return e.at(&self.path, line);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"(
+    texture: "atlas.png",
+    tile_size: (16, 16),
+    regions: [
+        (name: "soil", at: Cell(0, 0)),
+        (name: "grass", at: Cell(1, 0)),
+        (name: "pond", at: Cells(x: 1, y: 0, w: 2, h: 2), id: 10),
+        (name: "sign", at: Rect(x: 0, y: 16, w: 8, h: 4)),
+    ],
+)"#;
+
+    fn atlas_file(source: &str) -> AtlasFile {
+        return AtlasFile::from_ron("assets/atlas.ron", source).unwrap();
+    }
+
+    #[test]
+    fn test_load_from_ron() {
+        let file = atlas_file(SOURCE);
+        assert_eq!(file.texture_path(), PathBuf::from("assets/atlas.png"));
+
+        let atlas = file.build_with_size(AssetId::new(3), [48, 32]).unwrap();
+
+        assert_eq!(atlas.id("soil"), Some(SpriteId(0)));
+        assert_eq!(atlas.id("grass"), Some(SpriteId(1)));
+        assert_eq!(atlas.id("pond"), Some(SpriteId(10)));
+        assert_eq!(atlas.id("sign"), Some(SpriteId(11)));
+        assert_eq!(atlas.id("missing"), None);
+
+        let pond = atlas.sprite_by_name("pond").unwrap();
+        assert_eq!(pond.texture_id(), AssetId::new(3));
+        assert_eq!(pond.uv().to_array(), [1.0 / 3.0, 0.0, 2.0 / 3.0, 1.0]);
+
+        let sign = atlas.sprite(SpriteId(11)).unwrap();
+        assert_eq!(sign.uv().to_array(), [0.0, 0.5, 8.0 / 48.0, 0.125]);
+    }
+
+    #[test]
+    fn test_margin_and_spacing() {
+        let config = AtlasConfig {
+            tile_size: [Pixels::new(16.0), Pixels::new(16.0)],
+            margin: [Pixels::new(1.0), Pixels::new(2.0)],
+            spacing: [Pixels::new(2.0), Pixels::new(2.0)],
+        };
+
+        let (coords, size) = pixel_rect(
+            RegionPlacement::Cells {
+                x: 1,
+                y: 1,
+                w: 2,
+                h: 1,
+            },
+            &config,
+        );
+
+        assert_eq!(coords, [Pixels::new(19.0), Pixels::new(20.0)]);
+        assert_eq!(size, [Pixels::new(34.0), Pixels::new(16.0)]);
+    }
+
+    #[test]
+    fn test_duplicate_name_reports_line() {
+        let source = SOURCE.replace("\"grass\"", "\"soil\"");
+        let error = atlas_file(&source)
+            .build_with_size(AssetId::new(0), [48, 32])
+            .err()
+            .unwrap();
+
+        assert!(matches!(error.kind, AtlasErrorKind::DuplicateName(ref n) if n == "soil"));
+        assert_eq!(
+            error.location,
+            Some(SourceLocation {
+                path: PathBuf::from("assets/atlas.ron"),
+                line: 6,
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "assets/atlas.ron:6: duplicated definition for sprite \"soil\""
+        );
+    }
+
+    #[test]
+    fn test_duplicate_id_reports_line() {
+        let source = SOURCE.replace("id: 10", "id: 0");
+        let error = atlas_file(&source)
+            .build_with_size(AssetId::new(0), [48, 32])
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            error.kind,
+            AtlasErrorKind::DuplicateId(SpriteId(0))
+        ));
+        assert_eq!(error.location.unwrap().line, 7);
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let error = atlas_file(SOURCE)
+            .build_with_size(AssetId::new(0), [32, 32])
+            .err()
+            .unwrap();
+
+        assert!(matches!(error.kind, AtlasErrorKind::OutOfBounds(_)));
+        assert_eq!(error.location.unwrap().line, 7);
+    }
+
+    #[test]
+    fn test_parse_error_reports_line() {
+        let source = SOURCE.replace("Cell(1, 0)", "Cel(1, 0)");
+        let error = AtlasFile::from_ron("atlas.ron", &source).err().unwrap();
+
+        assert!(matches!(error.kind, AtlasErrorKind::Parse(_)));
+        assert!(std::error::Error::source(&error).is_some());
+        assert_eq!(error.location.unwrap().line, 6);
+    }
+
+    #[test]
+    fn test_lines_ignore_names_in_comments() {
+        let source = SOURCE
+            .replace("(\n", "(\n    // texture exported from \"pond\".aseprite\n")
+            .replace(
+                "    ],",
+                "        // \"pond\" is drawn over \"grass\"\n    ],",
+            );
+
+        let error = atlas_file(&source)
+            .build_with_size(AssetId::new(0), [32, 32])
+            .err()
+            .unwrap();
+        assert!(matches!(error.kind, AtlasErrorKind::OutOfBounds(_)));
+        assert_eq!(error.location.unwrap().line, 8);
+
+        let error = AtlasFile::from_ron("missing/atlas.ron", &source)
+            .unwrap()
+            .build(AssetId::new(0))
+            .err()
+            .unwrap();
+        assert!(matches!(error.kind, AtlasErrorKind::Image(_)));
+        assert_eq!(error.location.unwrap().line, 3);
     }
 }
@@ -0,0 +1,173 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::assets::{aseprite::AsepriteError, atlas::AtlasError, packer::PackError, AssetId};
+
+/// Why an asset could not be loaded or found
+///
+/// Loaders with their own error type convert into this one, so `?` works across them
+#[derive(Debug)]
+pub enum AssetError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Image {
+        path: PathBuf,
+        source: Box<image::ImageError>,
+    },
+    Font {
+        path: PathBuf,
+        message: String,
+    },
+    Atlas(Box<AtlasError>),
+    Aseprite(AsepriteError),
+    Pack(PackError),
+
+    /// Nothing is registered with the id, or it was never uploaded to the GPU
+    NotFound(AssetId),
+}
+
+impl AssetError {
+    pub(crate) fn io(path: impl AsRef<Path>, source: std::io::Error) -> Self {
+        return AssetError::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        };
+    }
+
+    pub(crate) fn image(path: impl AsRef<Path>, source: image::ImageError) -> Self {
+        return AssetError::Image {
+            path: path.as_ref().to_path_buf(),
+            source: Box::new(source),
+        };
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            AssetError::Io { path, source } => {
+                write!(f, "could not read {}: {}", path.display(), source)
+            }
+            AssetError::Image { path, source } => {
+                write!(f, "could not decode image {}: {}", path.display(), source)
+            }
+            AssetError::Font { path, message } => {
+                write!(f, "could not parse font {}: {}", path.display(), message)
+            }
+            AssetError::Atlas(e) => write!(f, "{}", e),
+            AssetError::Aseprite(e) => write!(f, "{}", e),
+            AssetError::Pack(e) => write!(f, "{}", e),
+            AssetError::NotFound(id) => write!(
+                f,
+                "no asset with id {} (generation {})",
+                id.value(),
+                id.generation()
+            ),
+        };
+    }
+}
+
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            AssetError::Io { source, .. } => Some(source),
+            AssetError::Image { source, .. } => Some(source.as_ref()),
+            AssetError::Atlas(e) => Some(e.as_ref()),
+            AssetError::Aseprite(e) => Some(e),
+            AssetError::Pack(e) => Some(e),
+            AssetError::Font { .. } | AssetError::NotFound(_) => None,
+        };
+    }
+}
+
+impl From<AtlasError> for AssetError {
+    fn from(e: AtlasError) -> Self {
+        return AssetError::Atlas(Box::new(e));
+    }
+}
+
+impl From<AsepriteError> for AssetError {
+    fn from(e: AsepriteError) -> Self {
+        return AssetError::Aseprite(e);
+    }
+}
+
+impl From<PackError> for AssetError {
+    fn from(e: PackError) -> Self {
+        return AssetError::Pack(e);
+    }
+}
+
+/// Reads and decodes an image file to RGBA
+pub fn read_image(path: impl AsRef<Path>) -> Result<image::RgbaImage, AssetError> {
+    let path = path.as_ref();
+    let image = image::io::Reader::open(path)
+        .map_err(|e| AssetError::io(path, e))?
+        .decode()
+        .map_err(|e| AssetError::image(path, e))?;
+
+    return Ok(image.into_rgba8());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_image_errors() {
+        let missing = read_image("does/not/exist.png").unwrap_err();
+        assert!(matches!(missing, AssetError::Io { .. }));
+        assert!(missing
+            .to_string()
+            .starts_with("could not read does/not/exist.png"));
+
+        let path =
+            std::env::temp_dir().join(format!("engine_bad_image_{}.png", std::process::id()));
+        std::fs::write(&path, b"not a png").unwrap();
+        let corrupt = read_image(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(corrupt, AssetError::Image { .. }));
+        assert!(std::error::Error::source(&corrupt).is_some());
+    }
+
+    #[test]
+    fn test_read_image() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/atlas.png");
+        assert_eq!(read_image(path).unwrap().dimensions(), (48, 32));
+    }
+
+    #[test]
+    fn test_converts_loader_errors() {
+        let error: AssetError = PackError::DuplicateName("grass".to_string()).into();
+
+        assert!(matches!(error, AssetError::Pack(_)));
+        assert_eq!(error.to_string(), "duplicated image \"grass\"");
+    }
+
+    #[test]
+    fn test_not_found_shows_generation() {
+        let mut registry = crate::assets::AssetsRegistry::new();
+        let stale = registry
+            .add_texture(crate::assets::texture::Texture::new(), None)
+            .id();
+        registry.collect_unused();
+
+        // The freed slot is reused by the next texture
+        let live = registry.add_texture(crate::assets::texture::Texture::new(), None);
+        assert_eq!(live.id().value(), stale.value());
+
+        assert_eq!(
+            AssetError::NotFound(stale).to_string(),
+            "no asset with id 0 (generation 0)"
+        );
+        assert_eq!(
+            AssetError::NotFound(live.id()).to_string(),
+            "no asset with id 0 (generation 1)"
+        );
+    }
+}
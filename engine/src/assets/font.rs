@@ -0,0 +1,216 @@
+use crate::{
+    assets::{texture::Texture, AssetId, Handle},
+    math::uv::UvRect,
+};
+
+/// Placement of a glyph relative to the pen, in pixels with y going down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphMetrics {
+    /// How far the pen moves after the glyph
+    pub advance: f32,
+
+    /// Top-left corner of the glyph relative to the pen on the baseline
+    pub offset: glam::Vec2,
+    pub size: glam::Vec2,
+}
+
+/// Fixed size cells of a bitmap font sheet, laid out left to right, top to bottom
+pub struct BitmapFontConfig {
+    /// Size of a cell in pixels
+    pub glyph_size: glam::UVec2,
+
+    /// Character of the first cell, the next cells follow in code point order
+    pub first_char: char,
+    pub glyph_count: u32,
+}
+
+/// Pixel font drawn from a texture sheet
+pub struct BitmapFont {
+    texture: AssetId,
+    sheet_size: glam::UVec2,
+    config: BitmapFontConfig,
+
+    /// Keeps a sheet loaded by path alive as long as the font
+    texture_handle: Option<Handle<Texture>>,
+}
+
+impl BitmapFont {
+    pub fn new(texture: AssetId, sheet_size: glam::UVec2, config: BitmapFontConfig) -> Self {
+        if config.glyph_size.x == 0 || config.glyph_size.y == 0 {
+            panic!("bitmap font glyphs must not be empty");
+        }
+
+        if sheet_size.x < config.glyph_size.x {
+            panic!("bitmap font sheet is narrower than a glyph");
+        }
+
+        return Self {
+            texture,
+            sheet_size,
+            config,
+            texture_handle: None,
+        };
+    }
+
+    pub fn texture(&self) -> AssetId {
+        return self.texture;
+    }
+
+    /// Draws from a sheet that is unloaded once the font and every other handle to it
+    /// are dropped
+    pub fn with_texture_handle(mut self, handle: Handle<Texture>) -> Self {
+        assert_eq!(
+            handle.id(),
+            self.texture,
+            "The handle must be the sheet of the font"
+        );
+        self.texture_handle = Some(handle);
+        return self;
+    }
+
+    /// Region of the sheet drawn for the character, `None` when the sheet lacks it
+    pub fn uv(&self, c: char) -> Option<UvRect> {
+        let index = (c as u32).checked_sub(self.config.first_char as u32)?;
+        if index >= self.config.glyph_count {
+            return None;
+        }
+
+        let columns = self.sheet_size.x / self.config.glyph_size.x;
+        let cell = glam::UVec2::new(index % columns, index / columns) * self.config.glyph_size;
+        if cell.y + self.config.glyph_size.y > self.sheet_size.y {
+            return None;
+        }
+
+        return Some(UvRect::new(
+            cell.x as f32 / self.sheet_size.x as f32,
+            cell.y as f32 / self.sheet_size.y as f32,
+            self.config.glyph_size.x as f32 / self.sheet_size.x as f32,
+            self.config.glyph_size.y as f32 / self.sheet_size.y as f32,
+        ));
+    }
+
+    fn scale(&self, size: f32) -> f32 {
+        return size / self.config.glyph_size.y as f32;
+    }
+}
+
+pub enum Font {
+    /// TTF or OTF font, its glyphs are rasterized on demand into the glyph atlas
+    Vector(fontdue::Font),
+    Bitmap(BitmapFont),
+}
+
+impl Font {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let font = fontdue::Font::from_bytes(bytes, fontdue::FontSettings::default())?;
+        return Ok(Font::Vector(font));
+    }
+
+    /// Size the font is actually drawn at
+    ///
+    /// Vector glyphs are rasterized at whole pixel sizes so they can be cached
+    pub fn snap_size(&self, size: f32) -> f32 {
+        return match self {
+            Font::Vector(_) => size.round().max(1.0),
+            Font::Bitmap(_) => size,
+        };
+    }
+
+    /// Distance from the top of a line to its baseline
+    pub fn ascent(&self, size: f32) -> f32 {
+        return match self {
+            Font::Vector(font) => match font.horizontal_line_metrics(size) {
+                Some(metrics) => metrics.ascent,
+                None => size,
+            },
+            Font::Bitmap(_) => size,
+        };
+    }
+
+    /// Distance between the baselines of two lines
+    pub fn line_height(&self, size: f32) -> f32 {
+        return match self {
+            Font::Vector(font) => match font.horizontal_line_metrics(size) {
+                Some(metrics) => metrics.new_line_size,
+                None => size,
+            },
+            Font::Bitmap(_) => size,
+        };
+    }
+
+    pub fn glyph(&self, c: char, size: f32) -> GlyphMetrics {
+        return match self {
+            Font::Vector(font) => {
+                let metrics = font.metrics(c, size);
+                let height = metrics.height as f32;
+
+                GlyphMetrics {
+                    advance: metrics.advance_width,
+                    // fontdue measures the bottom of the glyph from the baseline, y up
+                    offset: glam::Vec2::new(metrics.xmin as f32, -(metrics.ymin as f32 + height)),
+                    size: glam::Vec2::new(metrics.width as f32, height),
+                }
+            }
+            Font::Bitmap(font) => {
+                let size = font.config.glyph_size.as_vec2() * font.scale(size);
+
+                GlyphMetrics {
+                    advance: size.x,
+                    offset: glam::Vec2::new(0.0, -size.y),
+                    size,
+                }
+            }
+        };
+    }
+
+    /// Adjustment of the advance between two characters
+    pub fn kern(&self, left: char, right: char, size: f32) -> f32 {
+        return match self {
+            Font::Vector(font) => font.horizontal_kern(left, right, size).unwrap_or(0.0),
+            Font::Bitmap(_) => 0.0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap_font() -> BitmapFont {
+        return BitmapFont::new(
+            AssetId::new(0),
+            glam::UVec2::new(32, 16),
+            BitmapFontConfig {
+                glyph_size: glam::UVec2::new(8, 8),
+                first_char: 'A',
+                glyph_count: 6,
+            },
+        );
+    }
+
+    #[test]
+    fn test_bitmap_uv() {
+        let font = bitmap_font();
+
+        assert_eq!(font.uv('A'), Some(UvRect::new(0.0, 0.0, 0.25, 0.5)));
+        assert_eq!(font.uv('F'), Some(UvRect::new(0.25, 0.5, 0.25, 0.5)));
+        assert_eq!(font.uv('G'), None);
+        assert_eq!(font.uv(' '), None);
+    }
+
+    #[test]
+    fn test_bitmap_metrics() {
+        let font = Font::Bitmap(bitmap_font());
+        let glyph = font.glyph('A', 16.0);
+
+        assert_eq!(glyph.advance, 16.0);
+        assert_eq!(glyph.offset, glam::Vec2::new(0.0, -16.0));
+        assert_eq!(glyph.size, glam::Vec2::new(16.0, 16.0));
+        assert_eq!(font.line_height(16.0), 16.0);
+    }
+
+    #[test]
+    fn test_invalid_font_bytes() {
+        assert!(Font::from_bytes(&[0, 1, 2, 3]).is_err());
+    }
+}
@@ -1,7 +1,22 @@
-use std::{collections::HashMap, hash::Hash};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Weak,
+    },
+};
 
-use crate::assets::{sprite::Sprite, texture::Texture};
+use crate::assets::{animation::AnimationClip, font::Font, sprite::Sprite, texture::Texture};
 
+pub mod animation;
+pub mod aseprite;
+pub mod atlas;
+pub mod error;
+pub mod font;
+pub mod packer;
 pub mod sprite;
 pub mod texture;
 
@@ -14,60 +29,361 @@ impl std::fmt::Display for SpriteId {
     }
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
-pub struct Handle<T> {
+/// Sends the id of the asset when the last strong handle to it is dropped
+struct HandleRef {
     id: AssetId,
+    dropped: Sender<AssetId>,
+}
+
+impl Drop for HandleRef {
+    fn drop(&mut self) {
+        // The registry is gone, nothing is left to free
+        let _ = self.dropped.send(self.id);
+    }
+}
+
+/// Strong reference to an asset, the asset is unloaded once every handle is dropped
+///
+/// Draw data such as `Sprite` keeps the plain `AssetId`, so a handle must be held
+/// somewhere, e.g. in a resource, for as long as the asset is drawn
+#[must_use = "the asset is unloaded once its last handle is dropped"]
+pub struct Handle<T> {
+    inner: Arc<HandleRef>,
     /// This prevents compiler from complaining about the unused generic parameter `T`
-    marker: std::marker::PhantomData<T>,
+    marker: PhantomData<fn() -> T>,
 }
 
 impl<T> Handle<T> {
-    pub fn new(id: AssetId) -> Self {
+    pub fn id(&self) -> AssetId {
+        return self.inner.id;
+    }
+
+    /// Reference that does not keep the asset loaded
+    pub fn downgrade(&self) -> WeakHandle<T> {
+        return WeakHandle {
+            id: self.inner.id,
+            inner: Arc::downgrade(&self.inner),
+            marker: PhantomData,
+        };
+    }
+
+    /// Strong handles to the asset alive, this one included
+    pub fn strong_count(&self) -> usize {
+        return Arc::strong_count(&self.inner);
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
         return Self {
-            id,
-            marker: std::marker::PhantomData,
+            inner: self.inner.clone(),
+            marker: PhantomData,
         };
     }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        return self.id() == other.id();
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return f.debug_tuple("Handle").field(&self.id()).finish();
+    }
+}
 
+/// Reference to an asset that lets it be unloaded
+pub struct WeakHandle<T> {
+    id: AssetId,
+    inner: Weak<HandleRef>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> WeakHandle<T> {
     pub fn id(&self) -> AssetId {
         return self.id;
     }
+
+    /// `None` once the asset was unloaded
+    pub fn upgrade(&self) -> Option<Handle<T>> {
+        return self.inner.upgrade().map(|inner| Handle {
+            inner,
+            marker: PhantomData,
+        });
+    }
+}
+
+impl<T> Clone for WeakHandle<T> {
+    fn clone(&self) -> Self {
+        return Self {
+            id: self.id,
+            inner: self.inner.clone(),
+            marker: PhantomData,
+        };
+    }
+}
+
+impl<T> std::fmt::Debug for WeakHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return f.debug_tuple("WeakHandle").field(&self.id).finish();
+    }
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
-pub struct AssetId(u16);
+/// Slot of an asset in the registry
+///
+/// Freed slots are reused with the next generation, so a stale id never matches the
+/// asset that took its place
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AssetId {
+    index: u16,
+    generation: u16,
+}
 
 impl AssetId {
-    pub fn new(v: u16) -> Self {
-        return AssetId(v);
+    pub const fn new(v: u16) -> Self {
+        return AssetId {
+            index: v,
+            generation: 0,
+        };
     }
 
     pub fn value(&self) -> u16 {
-        return self.0;
+        return self.index;
+    }
+
+    pub fn generation(&self) -> u16 {
+        return self.generation;
     }
 }
 
 pub struct AssetsRegistry {
     textures: HashMap<AssetId, Texture>,
     sprites: HashMap<AssetId, Sprite>,
+    fonts: HashMap<AssetId, Font>,
+    animations: HashMap<AssetId, AnimationClip>,
 
-    next_id: AssetId,
+    /// Textures by the canonical path of the file they were loaded from
+    texture_paths: HashMap<PathBuf, WeakHandle<Texture>>,
+
+    /// Current generation of each slot
+    generations: Vec<u16>,
+    free_slots: Vec<u16>,
+
+    dropped_sender: Sender<AssetId>,
+    dropped: Receiver<AssetId>,
 }
 
 impl AssetsRegistry {
     pub fn new() -> Self {
+        let (dropped_sender, dropped) = mpsc::channel();
+
         return Self {
             textures: HashMap::new(),
             sprites: HashMap::new(),
-            next_id: AssetId::new(0),
+            fonts: HashMap::new(),
+            animations: HashMap::new(),
+            texture_paths: HashMap::new(),
+            generations: Vec::new(),
+            free_slots: Vec::new(),
+            dropped_sender,
+            dropped,
         };
     }
 
+    /// The texture stays registered as long as the registry, see `add_texture` for
+    /// one unloaded with its handles
     pub fn insert_texture(&mut self, texture: Texture) -> AssetId {
-        let id = self.next_id;
-        self.next_id = AssetId(id.value() + 1);
+        let id = self.next_id();
         self.textures.insert(id, texture);
 
         return id;
     }
+
+    /// Registers the texture until its last handle is dropped, with the file it is
+    /// loaded from so `texture_by_path` finds it. The caller keeps the handle alive
+    /// for as long as the texture is used
+    pub fn add_texture(&mut self, texture: Texture, path: Option<&Path>) -> Handle<Texture> {
+        let id = self.insert_texture(texture);
+        let handle = self.handle(id);
+
+        if let Some(path) = path {
+            self.texture_paths
+                .insert(canonical(path), handle.downgrade());
+        }
+
+        return handle;
+    }
+
+    /// Texture loaded from the file, if it is still alive
+    pub fn texture_by_path(&self, path: impl AsRef<Path>) -> Option<Handle<Texture>> {
+        return self
+            .texture_paths
+            .get(&canonical(path.as_ref()))
+            .and_then(|weak| weak.upgrade());
+    }
+
+    pub fn contains(&self, id: AssetId) -> bool {
+        return self.textures.contains_key(&id)
+            || self.sprites.contains_key(&id)
+            || self.fonts.contains_key(&id)
+            || self.animations.contains_key(&id);
+    }
+
+    /// Frees the assets whose last handle was dropped, returning their ids so their
+    /// GPU resources can be released too
+    pub fn collect_unused(&mut self) -> Vec<AssetId> {
+        let ids: Vec<AssetId> = self.dropped.try_iter().collect();
+
+        for id in &ids {
+            self.textures.remove(id);
+            self.sprites.remove(id);
+            self.fonts.remove(id);
+            self.animations.remove(id);
+            self.texture_paths.retain(|_, weak| weak.id() != *id);
+
+            let index = id.index as usize;
+            self.generations[index] = self.generations[index].wrapping_add(1);
+            self.free_slots.push(id.index);
+        }
+
+        return ids;
+    }
+    pub fn insert_font(&mut self, font: Font) -> AssetId {
+        let id = self.next_id();
+        self.fonts.insert(id, font);
+
+        return id;
+    }
+
+    pub fn font(&self, id: AssetId) -> Option<&Font> {
+        return self.fonts.get(&id);
+    }
+
+    pub fn insert_sprite(&mut self, sprite: Sprite) -> AssetId {
+        let id = self.next_id();
+        self.sprites.insert(id, sprite);
+
+        return id;
+    }
+
+    pub fn sprite(&self, id: AssetId) -> Option<&Sprite> {
+        return self.sprites.get(&id);
+    }
+
+    pub fn insert_animation(&mut self, clip: AnimationClip) -> AssetId {
+        let id = self.next_id();
+        self.animations.insert(id, clip);
+
+        return id;
+    }
+
+    pub fn animation(&self, id: AssetId) -> Option<&AnimationClip> {
+        return self.animations.get(&id);
+    }
+
+    fn handle<T>(&self, id: AssetId) -> Handle<T> {
+        return Handle {
+            inner: Arc::new(HandleRef {
+                id,
+                dropped: self.dropped_sender.clone(),
+            }),
+            marker: PhantomData,
+        };
+    }
+
+    fn next_id(&mut self) -> AssetId {
+        if let Some(index) = self.free_slots.pop() {
+            return AssetId {
+                index,
+                generation: self.generations[index as usize],
+            };
+        }
+
+        let index = u16::try_from(self.generations.len())
+            .expect("Ran out of asset ids, unload assets before loading more");
+        self.generations.push(0);
+
+        return AssetId::new(index);
+    }
+}
+
+/// Same file through different relative paths gives the same key
+fn canonical(path: &Path) -> PathBuf {
+    return std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asset_unloaded_with_last_handle() {
+        let mut registry = AssetsRegistry::new();
+        let handle = registry.add_texture(Texture::new(), None);
+        let id = handle.id();
+        let weak = handle.downgrade();
+
+        let copy = handle.clone();
+        assert_eq!(copy.strong_count(), 2);
+        drop(handle);
+        assert!(registry.collect_unused().is_empty());
+        assert!(registry.contains(id));
+
+        drop(copy);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(registry.collect_unused(), vec![id]);
+        assert!(!registry.contains(id));
+    }
+
+    #[test]
+    fn test_freed_slots_are_reused_with_new_generation() {
+        let mut registry = AssetsRegistry::new();
+        let permanent = registry.insert_texture(Texture::new());
+        let handle = registry.add_texture(Texture::new(), None);
+        let old = handle.id();
+
+        drop(handle);
+        registry.collect_unused();
+        let reused = registry.insert_texture(Texture::new());
+
+        assert_eq!(reused.value(), old.value());
+        assert_ne!(reused, old);
+        assert_eq!(reused.generation(), old.generation() + 1);
+        assert!(registry.contains(permanent));
+    }
+
+    #[test]
+    fn test_texture_by_path() {
+        let mut registry = AssetsRegistry::new();
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/atlas.png");
+        let other_path = concat!(env!("CARGO_MANIFEST_DIR"), "/../engine/../assets/atlas.png");
+
+        let handle = registry.add_texture(Texture::new(), Some(Path::new(path)));
+        assert_eq!(registry.texture_by_path(other_path), Some(handle.clone()));
+        assert!(registry.texture_by_path("assets/tileset.png").is_none());
+
+        drop(handle);
+        assert!(registry.texture_by_path(path).is_none());
+        registry.collect_unused();
+        assert!(registry.texture_paths.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Ran out of asset ids")]
+    fn test_running_out_of_ids_panics() {
+        let mut registry = AssetsRegistry::new();
+        for _ in 0..=u16::MAX as u32 + 1 {
+            registry.insert_texture(Texture::new());
+        }
+    }
 }
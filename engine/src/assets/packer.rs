@@ -0,0 +1,625 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    assets::{sprite::Sprite, AssetId},
+    ecs::resource::Resource,
+    math::{units::Pixels, uv::UvRect},
+};
+
+pub struct PackerConfig {
+    /// Largest page, images that do not fit go to a new page
+    pub page_size: [u32; 2],
+
+    /// Transparent pixels between two images
+    pub padding: u32,
+
+    /// Times the border pixels of each image are repeated around it, so filtering
+    /// and rounding at the edges sample the image instead of its neighbour
+    pub extrude: u32,
+}
+
+impl PackerConfig {
+    pub fn new() -> Self {
+        return Self {
+            page_size: [2048, 2048],
+            padding: 1,
+            extrude: 1,
+        };
+    }
+
+    pub fn with_page_size(mut self, width: u32, height: u32) -> Self {
+        self.page_size = [width, height];
+        return self;
+    }
+
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        return self;
+    }
+
+    pub fn with_extrude(mut self, extrude: u32) -> Self {
+        self.extrude = extrude;
+        return self;
+    }
+}
+
+#[derive(Debug)]
+pub enum PackError {
+    Io(std::io::Error),
+    Image(String, Box<image::ImageError>),
+
+    /// A baked page could not be saved
+    Write(PathBuf, Box<image::ImageError>),
+    DuplicateName(String),
+
+    /// The image is larger than a page, with its padding and extrusion
+    TooLarge(String),
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            PackError::Io(e) => write!(f, "could not write atlas: {}", e),
+            PackError::Image(name, e) => write!(f, "could not read image \"{}\": {}", name, e),
+            PackError::Write(path, e) => {
+                write!(f, "could not write page {}: {}", path.display(), e)
+            }
+            PackError::DuplicateName(name) => write!(f, "duplicated image \"{}\"", name),
+            PackError::TooLarge(name) => write!(f, "image \"{}\" does not fit in a page", name),
+        };
+    }
+}
+
+impl std::error::Error for PackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            PackError::Io(e) => Some(e),
+            PackError::Image(_, e) => Some(e.as_ref()),
+            PackError::Write(_, e) => Some(e.as_ref()),
+            _ => None,
+        };
+    }
+}
+
+/// Packs many images into as few textures as possible
+///
+/// Images are placed with a skyline packer, tallest first. The result is uploaded
+/// at runtime with `Engine::pack_atlas`, or baked with `PackedAtlas::bake` into PNG
+/// pages plus descriptions that `AtlasFile::load` reads back
+pub struct AtlasPacker {
+    config: PackerConfig,
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+impl AtlasPacker {
+    pub fn new(config: PackerConfig) -> Self {
+        return Self {
+            config,
+            images: Vec::new(),
+        };
+    }
+
+    pub fn add(&mut self, name: &str, image: image::RgbaImage) -> Result<(), PackError> {
+        if self.images.iter().any(|(n, _)| n == name) {
+            return Err(PackError::DuplicateName(name.to_string()));
+        }
+
+        self.images.push((name.to_string(), image));
+        return Ok(());
+    }
+
+    /// Adds an image file, named after the file without its extension
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> Result<(), PackError> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let image = image::open(path)
+            .map_err(|e| PackError::Image(name.clone(), Box::new(e)))?
+            .into_rgba8();
+
+        return self.add(&name, image);
+    }
+
+    /// Adds every PNG of the directory, in name order
+    pub fn add_directory(&mut self, path: impl AsRef<Path>) -> Result<(), PackError> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+            .map_err(PackError::Io)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e.eq_ignore_ascii_case("png")))
+            .collect();
+        files.sort();
+
+        for file in files {
+            self.add_file(file)?;
+        }
+
+        return Ok(());
+    }
+
+    pub fn pack(&self) -> Result<PackedAtlas, PackError> {
+        let border = self.config.extrude * 2 + self.config.padding;
+        let [page_width, page_height] = self.config.page_size;
+
+        // Tallest first leaves the fewest gaps under the skyline
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|i| {
+            let (width, height) = self.images[*i].1.dimensions();
+            return (std::cmp::Reverse(height), std::cmp::Reverse(width));
+        });
+
+        let mut skylines: Vec<Skyline> = Vec::new();
+        let mut placements: Vec<Option<(usize, u32, u32)>> = vec![None; self.images.len()];
+
+        for index in order {
+            let (name, image) = &self.images[index];
+            let width = image.width() + border;
+            let height = image.height() + border;
+            if width > page_width || height > page_height {
+                return Err(PackError::TooLarge(name.clone()));
+            }
+
+            let found = skylines
+                .iter_mut()
+                .enumerate()
+                .find_map(|(page, s)| s.insert(width, height).map(|(x, y)| (page, x, y)));
+
+            placements[index] = Some(match found {
+                Some(placement) => placement,
+                None => {
+                    let mut skyline = Skyline::new(page_width, page_height);
+                    let (x, y) = skyline.insert(width, height).unwrap();
+                    skylines.push(skyline);
+                    (skylines.len() - 1, x, y)
+                }
+            });
+        }
+
+        // Pages are cropped to what they use
+        let mut pages: Vec<image::RgbaImage> = skylines
+            .iter()
+            .map(|s| image::RgbaImage::new(s.used[0], s.used[1]))
+            .collect();
+        let mut sprites = Vec::with_capacity(self.images.len());
+
+        for ((name, image), placement) in self.images.iter().zip(placements) {
+            let (page, x, y) = placement.expect("Every image is placed");
+            let origin = [x + self.config.extrude, y + self.config.extrude];
+
+            blit_extruded(&mut pages[page], image, origin, self.config.extrude);
+            sprites.push(PackedSprite {
+                name: name.clone(),
+                page,
+                rect: [origin[0], origin[1], image.width(), image.height()],
+            });
+        }
+
+        return Ok(PackedAtlas { pages, sprites });
+    }
+}
+
+/// Where an image ended up in the pages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedSprite {
+    pub name: String,
+    pub page: usize,
+
+    /// `[x, y, width, height]` in pixels, without padding or extrusion
+    pub rect: [u32; 4],
+}
+
+pub struct PackedAtlas {
+    pub pages: Vec<image::RgbaImage>,
+    pub sprites: Vec<PackedSprite>,
+}
+
+impl PackedAtlas {
+    pub fn uv(&self, sprite: &PackedSprite) -> UvRect {
+        let page = &self.pages[sprite.page];
+        let [x, y, w, h] = sprite.rect.map(|v| Pixels::new(v as f32));
+
+        return UvRect::from_pixels(
+            [x, y],
+            [w, h],
+            [
+                Pixels::new(page.width() as f32),
+                Pixels::new(page.height() as f32),
+            ],
+        );
+    }
+
+    /// Sprites of the images, with `textures` the ids the pages are uploaded as
+    pub fn sprites(&self, textures: &[AssetId]) -> PackedSprites {
+        assert_eq!(
+            textures.len(),
+            self.pages.len(),
+            "Every page needs its own texture"
+        );
+
+        let sprites = self
+            .sprites
+            .iter()
+            .map(|s| {
+                let sprite = Sprite::new(textures[s.page], self.uv(s));
+                return (s.name.clone(), sprite);
+            })
+            .collect();
+
+        return PackedSprites {
+            textures: textures.to_vec(),
+            sprites,
+        };
+    }
+
+    /// Writes `<name>.png` and `<name>.ron` in `directory`, then `<name>_1.png`... for
+    /// the next pages. Each description is an `AtlasFile` with one `Rect` per image
+    pub fn bake(&self, directory: impl AsRef<Path>, name: &str) -> Result<Vec<PathBuf>, PackError> {
+        let directory = directory.as_ref();
+        std::fs::create_dir_all(directory).map_err(PackError::Io)?;
+
+        let mut written = Vec::new();
+        for (index, page) in self.pages.iter().enumerate() {
+            let stem = match index {
+                0 => name.to_string(),
+                i => format!("{}_{}", name, i),
+            };
+
+            let texture = directory.join(format!("{}.png", stem));
+            page.save(&texture)
+                .map_err(|e| PackError::Write(texture.clone(), Box::new(e)))?;
+
+            let description = directory.join(format!("{}.ron", stem));
+            std::fs::write(&description, self.description(index, &stem)).map_err(PackError::Io)?;
+
+            written.push(texture);
+            written.push(description);
+        }
+
+        return Ok(written);
+    }
+
+    fn description(&self, page: usize, stem: &str) -> String {
+        let mut ron = String::new();
+        ron.push_str("// Baked by AtlasPacker, regions are in pixels\n(\n");
+        ron.push_str(&format!("    texture: \"{}.png\",\n", stem));
+        ron.push_str("    tile_size: (1, 1),\n    regions: [\n");
+
+        for sprite in self.sprites.iter().filter(|s| s.page == page) {
+            let [x, y, w, h] = sprite.rect;
+            ron.push_str(&format!(
+                "        (name: {:?}, at: Rect(x: {}, y: {}, w: {}, h: {})),\n",
+                sprite.name, x, y, w, h
+            ));
+        }
+
+        ron.push_str("    ],\n)\n");
+        return ron;
+    }
+}
+
+/// Sprites packed at runtime, by the name of their image
+pub struct PackedSprites {
+    textures: Vec<AssetId>,
+    sprites: HashMap<String, Sprite>,
+}
+
+impl Resource for PackedSprites {}
+
+impl PackedSprites {
+    /// One texture per page
+    pub fn textures(&self) -> &[AssetId] {
+        return &self.textures;
+    }
+
+    pub fn sprite(&self, name: &str) -> Option<Sprite> {
+        return self.sprites.get(name).cloned();
+    }
+}
+
+/// Top edge of the placed rectangles, as segments from left to right
+struct Skyline {
+    width: u32,
+    height: u32,
+
+    /// `(x, y, width)` of each segment
+    segments: Vec<(u32, u32, u32)>,
+
+    /// Right and bottom edges of the placed rectangles
+    used: [u32; 2],
+}
+
+impl Skyline {
+    fn new(width: u32, height: u32) -> Self {
+        return Self {
+            width,
+            height,
+            segments: vec![(0, 0, width)],
+            used: [0, 0],
+        };
+    }
+
+    /// Places the rectangle where its bottom is the highest, the narrowest segment
+    /// breaking ties
+    fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let mut best: Option<(usize, u32, u32, u32)> = None;
+
+        for i in 0..self.segments.len() {
+            let Some(y) = self.fit(i, width, height) else {
+                continue;
+            };
+
+            let bottom = y + height;
+            let segment_width = self.segments[i].2;
+            let better = match best {
+                None => true,
+                Some((_, _, best_bottom, best_width)) => {
+                    bottom < best_bottom || (bottom == best_bottom && segment_width < best_width)
+                }
+            };
+            if better {
+                best = Some((i, y, bottom, segment_width));
+            }
+        }
+
+        let (index, y, _, _) = best?;
+        let x = self.segments[index].0;
+        self.place(index, x, y, width, height);
+
+        return Some((x, y));
+    }
+
+    /// Top of the rectangle when its left edge is on the segment
+    fn fit(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.segments[index].0;
+        if x + width > self.width {
+            return None;
+        }
+
+        let mut y = 0;
+        let mut remaining = width as i64;
+        for &(_, segment_y, segment_width) in &self.segments[index..] {
+            if remaining <= 0 {
+                break;
+            }
+
+            y = y.max(segment_y);
+            if y + height > self.height {
+                return None;
+            }
+            remaining -= segment_width as i64;
+        }
+
+        return Some(y);
+    }
+
+    fn place(&mut self, index: usize, x: u32, y: u32, width: u32, height: u32) {
+        self.segments.insert(index, (x, y + height, width));
+
+        // Segments under the new one shrink or disappear
+        let right = x + width;
+        let i = index + 1;
+        while i < self.segments.len() {
+            let (segment_x, segment_y, segment_width) = self.segments[i];
+            if segment_x >= right {
+                break;
+            }
+
+            let segment_right = segment_x + segment_width;
+            if segment_right <= right {
+                self.segments.remove(i);
+            } else {
+                self.segments[i] = (right, segment_y, segment_right - right);
+                break;
+            }
+        }
+
+        // Neighbours at the same height become one segment
+        let mut i = 0;
+        while i + 1 < self.segments.len() {
+            if self.segments[i].1 == self.segments[i + 1].1 {
+                self.segments[i].2 += self.segments[i + 1].2;
+                self.segments.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+
+        self.used = [self.used[0].max(right), self.used[1].max(y + height)];
+    }
+}
+
+/// Copies the image at `origin` and repeats its border pixels `extrude` times around it
+fn blit_extruded(
+    page: &mut image::RgbaImage,
+    image: &image::RgbaImage,
+    origin: [u32; 2],
+    extrude: u32,
+) {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let extrude = extrude as i64;
+    for y in -extrude..height as i64 + extrude {
+        for x in -extrude..width as i64 + extrude {
+            let source_x = x.clamp(0, width as i64 - 1) as u32;
+            let source_y = y.clamp(0, height as i64 - 1) as u32;
+            let target_x = (origin[0] as i64 + x) as u32;
+            let target_y = (origin[1] as i64 + y) as u32;
+
+            page.put_pixel(target_x, target_y, *image.get_pixel(source_x, source_y));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::atlas::AtlasFile;
+
+    fn solid(width: u32, height: u32, value: u8) -> image::RgbaImage {
+        return image::RgbaImage::from_pixel(width, height, image::Rgba([value, value, 0, 255]));
+    }
+
+    fn overlaps(a: &PackedSprite, b: &PackedSprite, border: u32) -> bool {
+        let [ax, ay, aw, ah] = a.rect;
+        let [bx, by, bw, bh] = b.rect;
+        return a.page == b.page
+            && ax < bx + bw + border
+            && bx < ax + aw + border
+            && ay < by + bh + border
+            && by < ay + ah + border;
+    }
+
+    #[test]
+    fn test_images_do_not_overlap() {
+        let config = PackerConfig::new()
+            .with_page_size(64, 64)
+            .with_padding(1)
+            .with_extrude(1);
+        let mut packer = AtlasPacker::new(config);
+        for i in 0..20u32 {
+            packer
+                .add(
+                    &format!("image_{}", i),
+                    solid(4 + i % 5, 3 + i % 7, i as u8),
+                )
+                .unwrap();
+        }
+
+        let atlas = packer.pack().unwrap();
+        assert_eq!(atlas.pages.len(), 1);
+
+        for (i, a) in atlas.sprites.iter().enumerate() {
+            for b in &atlas.sprites[i + 1..] {
+                // Padding plus the extrusion of both images
+                assert!(!overlaps(a, b, 2), "{} overlaps {}", a.name, b.name);
+            }
+
+            let [x, y, w, h] = a.rect;
+            let page = &atlas.pages[a.page];
+            assert!(x >= 1 && y >= 1);
+            assert!(x + w < page.width() && y + h < page.height());
+            assert_eq!(page.get_pixel(x, y), packer.images[i].1.get_pixel(0, 0));
+        }
+    }
+
+    #[test]
+    fn test_extrusion_repeats_borders() {
+        let mut image = solid(2, 2, 10);
+        image.put_pixel(1, 1, image::Rgba([200, 0, 0, 255]));
+
+        let mut packer = AtlasPacker::new(PackerConfig::new().with_padding(0).with_extrude(2));
+        packer.add("a", image).unwrap();
+        let atlas = packer.pack().unwrap();
+
+        let page = &atlas.pages[0];
+        assert_eq!(page.dimensions(), (6, 6));
+        assert_eq!(atlas.sprites[0].rect, [2, 2, 2, 2]);
+        assert_eq!(page.get_pixel(0, 0).0, [10, 10, 0, 255]);
+        assert_eq!(page.get_pixel(5, 5).0, [200, 0, 0, 255]);
+        assert_eq!(page.get_pixel(5, 0).0, [10, 10, 0, 255]);
+    }
+
+    #[test]
+    fn test_overflow_goes_to_new_pages() {
+        let config = PackerConfig::new()
+            .with_page_size(16, 16)
+            .with_padding(0)
+            .with_extrude(0);
+        let mut packer = AtlasPacker::new(config);
+        for i in 0..5 {
+            packer.add(&i.to_string(), solid(8, 8, i)).unwrap();
+        }
+
+        let atlas = packer.pack().unwrap();
+        assert_eq!(atlas.pages.len(), 2);
+        assert_eq!(atlas.sprites.iter().filter(|s| s.page == 1).count(), 1);
+        assert_eq!(atlas.pages[1].dimensions(), (8, 8));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut packer = AtlasPacker::new(PackerConfig::new().with_page_size(8, 8));
+        packer.add("a", solid(2, 2, 0)).unwrap();
+        assert!(matches!(
+            packer.add("a", solid(2, 2, 0)),
+            Err(PackError::DuplicateName(_))
+        ));
+
+        // Fits alone, but not with its padding and extrusion
+        packer.add("b", solid(8, 8, 0)).unwrap();
+        assert!(matches!(packer.pack(), Err(PackError::TooLarge(name)) if name == "b"));
+    }
+
+    #[test]
+    fn test_add_directory() {
+        let dir = std::env::temp_dir().join(format!("engine_packer_dir_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        solid(2, 2, 1).save(dir.join("lower.png")).unwrap();
+        solid(2, 2, 2)
+            .save_with_format(dir.join("UPPER.PNG"), image::ImageFormat::Png)
+            .unwrap();
+        std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+
+        let mut packer = AtlasPacker::new(PackerConfig::new());
+        packer.add_directory(&dir).unwrap();
+        let names: Vec<&str> = packer.images.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["UPPER", "lower"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sprites_uv() {
+        let mut packer = AtlasPacker::new(PackerConfig::new().with_padding(0).with_extrude(1));
+        packer.add("a", solid(2, 4, 0)).unwrap();
+        let atlas = packer.pack().unwrap();
+
+        let sprites = atlas.sprites(&[AssetId::new(7)]);
+        let sprite = sprites.sprite("a").unwrap();
+        assert_eq!(sprite.texture_id(), AssetId::new(7));
+        assert_eq!(sprite.uv().to_array(), [0.25, 1.0 / 6.0, 0.5, 4.0 / 6.0]);
+        assert!(sprites.sprite("b").is_none());
+    }
+
+    #[test]
+    fn test_bake_reloads_as_atlas() {
+        let dir = std::env::temp_dir().join(format!("engine_packer_{}", std::process::id()));
+        let mut packer = AtlasPacker::new(PackerConfig::new());
+        packer.add("soil", solid(16, 16, 1)).unwrap();
+        packer.add("tall tree", solid(16, 32, 2)).unwrap();
+        let atlas = packer.pack().unwrap();
+
+        let written = atlas.bake(&dir, "baked").unwrap();
+        assert_eq!(written, vec![dir.join("baked.png"), dir.join("baked.ron")]);
+
+        let loaded = AtlasFile::load(dir.join("baked.ron"))
+            .unwrap()
+            .build(AssetId::new(0))
+            .unwrap();
+        let tree = atlas
+            .sprites
+            .iter()
+            .find(|s| s.name == "tall tree")
+            .unwrap();
+        assert_eq!(
+            loaded.sprite_by_name("tall tree").unwrap().uv(),
+            atlas.uv(tree)
+        );
+
+        // A directory in place of the page makes the save fail
+        std::fs::create_dir_all(dir.join("blocked.png")).unwrap();
+        let error = atlas.bake(&dir, "blocked").unwrap_err();
+        assert!(matches!(&error, PackError::Write(path, _) if *path == dir.join("blocked.png")));
+        assert!(std::error::Error::source(&error).is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -10,4 +10,12 @@ impl Sprite {
     pub fn new(texture_id: AssetId, uv: UvRect) -> Self {
         return Self { texture_id, uv };
     }
+
+    pub fn texture_id(&self) -> AssetId {
+        return self.texture_id;
+    }
+
+    pub fn uv(&self) -> UvRect {
+        return self.uv;
+    }
 }
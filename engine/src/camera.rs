@@ -46,6 +46,12 @@ impl Camera2D {
         self.position = position;
     }
 
+    /// Bottom-left and top-right corners of the visible area, in world space
+    pub fn view_bounds(&self) -> (glam::Vec2, glam::Vec2) {
+        let half = 0.5 * self.viewport_size / self.zoom;
+        return (self.position - half, self.position + half);
+    }
+
     pub fn view_matrix(&self) -> glam::Mat4 {
         return glam::Mat4::from_translation((-self.position).extend(0.0));
     }
@@ -107,4 +113,12 @@ impl CameraUniform {
             view_proj: camera.view_projection().to_cols_array_2d(),
         };
     }
+
+    /// Maps viewport pixels, from the top-left corner with y going down, to clip space
+    pub fn screen(width: f32, height: f32) -> Self {
+        let projection = Mat4::orthographic_rh_gl(0.0, width, height, 0.0, -1.0, 1.0);
+        return Self {
+            view_proj: projection.to_cols_array_2d(),
+        };
+    }
 }
@@ -0,0 +1,116 @@
+use std::{any::TypeId, collections::HashSet};
+
+use crate::ecs::{component::Component, event::Event, event::Events, resource::Resource};
+
+/// Component types and resources a system reads or writes
+///
+/// The scheduler runs systems whose access does not conflict at the same time, so the
+/// declaration must cover everything the system touches. Systems that do not declare
+/// anything are treated as exclusive and always run alone
+#[derive(Clone, Debug, Default)]
+pub struct SystemAccess {
+    reads: HashSet<TypeId>,
+    writes: HashSet<TypeId>,
+
+    exclusive: bool,
+}
+
+impl SystemAccess {
+    /// Access to nothing, extended through the builder methods
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Conflicts with every other system
+    pub fn exclusive() -> Self {
+        return Self {
+            exclusive: true,
+            ..Self::default()
+        };
+    }
+
+    pub fn read<C: Component>(mut self) -> Self {
+        self.reads.insert(TypeId::of::<C>());
+        return self;
+    }
+
+    pub fn write<C: Component>(mut self) -> Self {
+        self.writes.insert(TypeId::of::<C>());
+        return self;
+    }
+
+    pub fn read_resource<R: Resource>(mut self) -> Self {
+        self.reads.insert(TypeId::of::<R>());
+        return self;
+    }
+
+    pub fn write_resource<R: Resource>(mut self) -> Self {
+        self.writes.insert(TypeId::of::<R>());
+        return self;
+    }
+
+    /// Reading events borrows their `Events` channel immutably
+    pub fn read_events<E: Event>(self) -> Self {
+        return self.read_resource::<Events<E>>();
+    }
+
+    /// Sending events borrows their `Events` channel mutably
+    pub fn write_events<E: Event>(self) -> Self {
+        return self.write_resource::<Events<E>>();
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        return self.exclusive;
+    }
+
+    /// Whether both systems can safely run at the same time
+    pub fn is_compatible(&self, other: &SystemAccess) -> bool {
+        if self.exclusive || other.exclusive {
+            return false;
+        }
+
+        return self.writes.is_disjoint(&other.writes)
+            && self.writes.is_disjoint(&other.reads)
+            && self.reads.is_disjoint(&other.writes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Position;
+    impl Component for Position {}
+
+    struct Velocity;
+    impl Component for Velocity {}
+
+    struct Clock;
+    impl Resource for Clock {}
+
+    #[test]
+    fn test_compatibility() {
+        let read_position = SystemAccess::new().read::<Position>();
+        let write_position = SystemAccess::new().write::<Position>();
+        let write_velocity = SystemAccess::new().read::<Position>().write::<Velocity>();
+        let read_clock = SystemAccess::new().read_resource::<Clock>();
+        let write_clock = SystemAccess::new().write_resource::<Clock>();
+
+        assert!(read_position.is_compatible(&read_position));
+        assert!(read_position.is_compatible(&write_velocity));
+        assert!(read_clock.is_compatible(&write_position));
+
+        assert!(!read_position.is_compatible(&write_position));
+        assert!(!write_position.is_compatible(&write_position));
+        assert!(!write_position.is_compatible(&write_velocity));
+        assert!(!read_clock.is_compatible(&write_clock));
+    }
+
+    #[test]
+    fn test_exclusive() {
+        let exclusive = SystemAccess::exclusive();
+
+        assert!(!exclusive.is_compatible(&SystemAccess::new()));
+        assert!(!SystemAccess::new().is_compatible(&exclusive));
+    }
+}
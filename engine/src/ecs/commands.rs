@@ -1 +1,165 @@
-struct Commands {}
+use crate::ecs::{component::Component, entity::Entity, world::World};
+
+/// A deferred operation over the world
+pub(crate) type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+/// Records spawn, despawn, insert and remove operations while the world is borrowed
+///
+/// Nothing changes immediately: the operations are queued in the `World` and applied
+/// by the `Scheduler` at the next sync point, once every system of the stage has finished
+pub struct Commands<'w> {
+    world: &'w World,
+}
+
+impl<'w> Commands<'w> {
+    pub(crate) fn new(world: &'w World) -> Self {
+        return Self { world };
+    }
+
+    /// Reserves a new entity
+    ///
+    /// The entity id is valid right away, so components can be inserted on it
+    /// through the same `Commands`
+    pub fn spawn(&self) -> Entity {
+        return self.world.reserve_entity();
+    }
+
+    /// Despawns the entity, ignored if it was already despawned when applied
+    pub fn despawn(&self, entity: Entity) {
+        self.add(move |world| {
+            if world.is_alive(entity) {
+                world.despawn(entity);
+            }
+        });
+    }
+
+    /// Inserts the component, replacing any previous one
+    /// Ignored if the entity was despawned when applied
+    pub fn insert<C: Component>(&self, entity: Entity, component: C) {
+        self.add(move |world| {
+            if world.is_alive(entity) {
+                world.insert(entity, component);
+            }
+        });
+    }
+
+    /// Removes the component, ignored if the entity was despawned when applied
+    pub fn remove<C: Component>(&self, entity: Entity) {
+        self.add(move |world| {
+            if world.is_alive(entity) {
+                world.remove::<C>(entity);
+            }
+        });
+    }
+
+    /// Queues a custom operation with exclusive access to the world
+    pub fn add<F>(&self, command: F)
+    where
+        F: FnOnce(&mut World) + Send + 'static,
+    {
+        self.world.push_command(Box::new(command));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::scheduler::{Scheduler, System};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Plant(u32);
+    impl Component for Plant {}
+
+    #[derive(Debug, PartialEq)]
+    struct ItemDrop(u32);
+    impl Component for ItemDrop {}
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.add_component::<Plant>();
+        world.add_component::<ItemDrop>();
+
+        return world;
+    }
+
+    #[test]
+    fn test_commands_are_deferred() {
+        let mut world = world();
+        let e = world.spawn();
+
+        world.commands().insert(e, Plant(1));
+        assert!(!world.has::<Plant>(e));
+
+        world.apply_commands();
+        assert_eq!(world.get::<Plant>(e).as_deref(), Some(&Plant(1)));
+
+        world.commands().remove::<Plant>(e);
+        world.apply_commands();
+        assert!(!world.has::<Plant>(e));
+    }
+
+    #[test]
+    fn test_commands_while_iterating() {
+        let mut world = world();
+
+        let plant = world.spawn();
+        world.insert(plant, Plant(7));
+
+        let commands = world.commands();
+        let mut q = world.query::<(Entity, &Plant)>();
+        for (entity, plant) in q.iter() {
+            let drop = commands.spawn();
+            commands.insert(drop, ItemDrop(plant.0));
+            commands.despawn(entity);
+        }
+        drop(q);
+
+        world.apply_commands();
+
+        assert!(!world.is_alive(plant));
+
+        let mut q = world.query::<&ItemDrop>();
+        let drops: Vec<u32> = q.iter().map(|d| d.0).collect();
+        assert_eq!(drops, vec![7]);
+    }
+
+    #[test]
+    fn test_commands_despawn_twice() {
+        let mut world = world();
+        let e = world.spawn();
+        world.insert(e, Plant(1));
+
+        world.commands().despawn(e);
+        world.commands().despawn(e);
+        world.commands().insert(e, Plant(2));
+        world.apply_commands();
+
+        assert!(!world.is_alive(e));
+    }
+
+    struct HarvestSystem {}
+    impl System for HarvestSystem {
+        fn run(&mut self, world: &World, _dt: std::time::Duration) {
+            let commands = world.commands();
+            let mut q = world.query::<(Entity, &Plant)>();
+
+            for (entity, _) in q.iter() {
+                commands.despawn(entity);
+            }
+        }
+    }
+
+    #[test]
+    fn test_scheduler_applies_commands() {
+        let mut world = world();
+        let e = world.spawn();
+        world.insert(e, Plant(1));
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add_system(HarvestSystem {});
+        scheduler.run_systems(&mut world, std::time::Duration::ZERO);
+
+        assert!(!world.is_alive(e));
+    }
+}
@@ -1,12 +1,59 @@
-use std::any::Any;
+use std::{any::Any, marker::PhantomData, ops::Deref, sync::RwLockReadGuard};
 
-use crate::ecs::entity::Entity;
+use crate::ecs::{entity::Entity, storage::SparseSet};
 
 pub trait Component: 'static + Send + Sync {}
 
-pub trait ComponentStore {
+pub trait ComponentStore: Send + Sync {
     fn as_any_ref(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 
-    fn remove(&mut self, entity: Entity);
+    /// Returns whether the entity had a component to remove
+    fn remove(&mut self, entity: Entity) -> bool;
+
+    fn has(&self, entity: Entity) -> bool;
+
+    /// Entities that currently own a component in this store
+    fn entities(&self) -> &[Entity];
+}
+
+/// Shared borrow of an entity's component, see `World::get`
+///
+/// Holds the store for reading, so the component cannot be written while it lives
+pub struct Ref<'w, C: Component> {
+    guard: RwLockReadGuard<'w, Box<dyn ComponentStore>>,
+    entity: Entity,
+    marker: PhantomData<C>,
+}
+
+impl<'w, C: Component> Ref<'w, C> {
+    /// `None` when the entity has no `C`
+    pub(crate) fn new(
+        guard: RwLockReadGuard<'w, Box<dyn ComponentStore>>,
+        entity: Entity,
+    ) -> Option<Self> {
+        if !guard.has(entity) {
+            return None;
+        }
+
+        return Some(Self {
+            guard,
+            entity,
+            marker: PhantomData,
+        });
+    }
+}
+
+impl<C: Component> Deref for Ref<'_, C> {
+    type Target = C;
+
+    fn deref(&self) -> &Self::Target {
+        return self
+            .guard
+            .as_any_ref()
+            .downcast_ref::<SparseSet<C>>()
+            .expect("internal error: component store type mismatch")
+            .get(self.entity)
+            .expect("internal error: borrowed entity is missing the component");
+    }
 }
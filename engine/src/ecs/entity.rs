@@ -56,6 +56,29 @@ impl EntityAllocator {
         return Entity::new(slot, generation);
     }
 
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let idx = entity.idx();
+
+        if idx >= self.generations.len() {
+            return false;
+        }
+
+        return self.generations[idx] == entity.generation();
+    }
+
+    /// Every allocated entity that has not been freed, in slot order
+    pub fn alive(&self) -> Vec<Entity> {
+        let mut free = vec![false; self.generations.len()];
+        for slot in &self.recycled {
+            free[*slot as usize] = true;
+        }
+
+        return (0..self.next)
+            .filter(|slot| !free[*slot as usize])
+            .map(|slot| Entity::new(slot, self.generations[slot as usize]))
+            .collect();
+    }
+
     pub fn free(&mut self, entity: Entity) {
         let idx = entity.idx();
 
@@ -108,4 +131,34 @@ mod tests {
         assert_eq!(e2.slot(), 0);
         assert_eq!(e2.generation(), 1);
     }
+
+    #[test]
+    fn test_is_alive() {
+        let mut a = EntityAllocator::new();
+
+        let e1 = a.alloc();
+        assert!(a.is_alive(e1));
+
+        a.free(e1);
+        assert!(!a.is_alive(e1));
+
+        let e2 = a.alloc();
+        assert!(a.is_alive(e2));
+        assert!(!a.is_alive(e1));
+    }
+
+    #[test]
+    fn test_alive() {
+        let mut a = EntityAllocator::new();
+
+        let e1 = a.alloc();
+        let e2 = a.alloc();
+        let e3 = a.alloc();
+        a.free(e2);
+
+        assert_eq!(a.alive(), vec![e1, e3]);
+
+        let e4 = a.alloc();
+        assert_eq!(a.alive(), vec![e1, e4, e3]);
+    }
 }
@@ -0,0 +1,236 @@
+use std::marker::PhantomData;
+
+use crate::ecs::{
+    resource::{ResMut, Resource},
+    world::World,
+};
+
+/// Message broadcast from one system to the others, like a harvested crop or a market trade
+pub trait Event: 'static + Send + Sync {}
+
+struct EventInstance<E> {
+    id: usize,
+    event: E,
+}
+
+/// Double-buffered channel of events of a single type, stored as a resource in the `World`
+///
+/// Every `update` drops the events of the previous frame and moves the current ones to
+/// the previous buffer, so an event can be read during the frame it was sent and the next one
+pub struct Events<E: Event> {
+    previous: Vec<EventInstance<E>>,
+    current: Vec<EventInstance<E>>,
+
+    next_id: usize,
+}
+
+impl<E: Event> Resource for Events<E> {}
+
+impl<E: Event> Events<E> {
+    pub fn new() -> Self {
+        return Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            next_id: 0,
+        };
+    }
+
+    pub fn send(&mut self, event: E) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.current.push(EventInstance { id, event });
+    }
+
+    /// Swaps the buffers, called by the `Scheduler` once per run
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.previous.len() + self.current.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+}
+
+impl<E: Event> Default for Events<E> {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// Sends events of a single type, see `World::event_writer`
+pub struct EventWriter<'w, E: Event> {
+    events: ResMut<'w, Events<E>>,
+}
+
+impl<'w, E: Event> EventWriter<'w, E> {
+    pub(crate) fn new(events: ResMut<'w, Events<E>>) -> Self {
+        return Self { events };
+    }
+
+    pub fn send(&mut self, event: E) {
+        self.events.send(event);
+    }
+}
+
+/// Cursor over the events of a single type
+///
+/// Readers are owned by the systems, so that each system sees every event exactly once
+/// no matter where it runs in relation to the sender
+///
+/// ```ignore
+/// struct MarketSystem {
+///     trades: EventReader<Trade>,
+/// }
+///
+/// for trade in self.trades.read(&world.events::<Trade>()) {}
+/// ```
+pub struct EventReader<E: Event> {
+    last_read: usize,
+    marker: PhantomData<E>,
+}
+
+impl<E: Event> EventReader<E> {
+    pub fn new() -> Self {
+        return Self {
+            last_read: 0,
+            marker: PhantomData,
+        };
+    }
+
+    /// Iterates over the events sent since the last read, oldest first
+    pub fn read<'a>(&mut self, events: &'a Events<E>) -> impl Iterator<Item = &'a E> + 'a {
+        let from = self.last_read;
+        self.last_read = events.next_id;
+
+        return events
+            .previous
+            .iter()
+            .chain(events.current.iter())
+            .filter(move |instance| instance.id >= from)
+            .map(|instance| &instance.event);
+    }
+}
+
+impl<E: Event> Default for EventReader<E> {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// Swaps the buffers of a registered event type, see `World::add_event`
+pub(crate) fn update_events<E: Event>(world: &World) {
+    world.resource_mut::<Events<E>>().update();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::scheduler::{Scheduler, System};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct CropHarvested(u32);
+    impl Event for CropHarvested {}
+
+    #[test]
+    fn test_reader_sees_each_event_once() {
+        let mut events = Events::<CropHarvested>::new();
+        let mut reader = EventReader::new();
+
+        events.send(CropHarvested(1));
+        events.send(CropHarvested(2));
+
+        let read: Vec<u32> = reader.read(&events).map(|e| e.0).collect();
+        assert_eq!(read, vec![1, 2]);
+
+        events.send(CropHarvested(3));
+
+        let read: Vec<u32> = reader.read(&events).map(|e| e.0).collect();
+        assert_eq!(read, vec![3]);
+    }
+
+    #[test]
+    fn test_events_live_for_one_update() {
+        let mut events = Events::<CropHarvested>::new();
+
+        events.send(CropHarvested(1));
+        events.update();
+
+        let mut reader = EventReader::new();
+        assert_eq!(reader.read(&events).count(), 1);
+
+        events.update();
+
+        let mut reader = EventReader::new();
+        assert_eq!(reader.read(&events).count(), 0);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_world_events() {
+        let mut world = World::new();
+        world.add_event::<CropHarvested>();
+
+        world.send_event(CropHarvested(1));
+        world.event_writer::<CropHarvested>().send(CropHarvested(2));
+
+        let mut reader = EventReader::<CropHarvested>::new();
+        let read: Vec<u32> = reader.read(&world.events()).map(|e| e.0).collect();
+        assert_eq!(read, vec![1, 2]);
+
+        world.update_events();
+        world.update_events();
+        assert!(world.events::<CropHarvested>().is_empty());
+    }
+
+    struct Harvester {}
+    impl System for Harvester {
+        fn run(&mut self, world: &World, _dt: std::time::Duration) {
+            world.send_event(CropHarvested(1));
+        }
+    }
+
+    struct Counter {
+        reader: EventReader<CropHarvested>,
+        seen: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+    impl System for Counter {
+        fn run(&mut self, world: &World, _dt: std::time::Duration) {
+            let n = self.reader.read(&world.events()).count();
+            self.seen.fetch_add(n, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_scheduler_clears_events() {
+        let seen = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let mut world = World::new();
+        world.add_event::<CropHarvested>();
+
+        // The reader runs before the sender, so it only sees the event on the next run
+        let mut scheduler = Scheduler::new();
+        scheduler.add_system(Counter {
+            reader: EventReader::new(),
+            seen: seen.clone(),
+        });
+        scheduler.add_system(Harvester {});
+
+        scheduler.run_systems(&mut world, std::time::Duration::ZERO);
+        assert_eq!(seen.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        scheduler.run_systems(&mut world, std::time::Duration::ZERO);
+        assert_eq!(seen.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        scheduler.run_systems(&mut world, std::time::Duration::ZERO);
+        assert_eq!(seen.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // Only the events sent during the last run are kept
+        assert_eq!(world.events::<CropHarvested>().len(), 1);
+    }
+}
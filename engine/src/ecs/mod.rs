@@ -1,12 +1,21 @@
 use crate::ecs::{
-    scheduler::{Scheduler, System},
+    component::Component,
+    event::Event,
+    resource::Resource,
+    scheduler::{ScheduleError, Scheduler, System, SystemConfig},
     world::World,
 };
 
+pub mod access;
+pub mod commands;
 pub mod component;
 pub mod entity;
+pub mod event;
+pub mod query;
+pub mod resource;
 pub mod scheduler;
 pub mod storage;
+pub mod tick;
 pub mod world;
 
 pub struct ECS {
@@ -29,7 +38,51 @@ impl ECS {
         self.scheduler.add_system(system);
     }
 
+    pub fn add_system_with_config<S>(&mut self, system: S, config: SystemConfig)
+    where
+        S: System + 'static,
+    {
+        self.scheduler.add_system_with_config(system, config);
+    }
+
+    /// Checks the ordering constraints of the systems, see `Scheduler::build`
+    pub fn build(&mut self) -> Result<(), ScheduleError> {
+        return self.scheduler.build();
+    }
+
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) -> Option<R> {
+        return self.world.insert_resource(resource);
+    }
+
+    pub fn add_component<C: Component>(&mut self) {
+        self.world.add_component::<C>();
+    }
+
+    pub fn add_event<E: Event>(&mut self) {
+        self.world.add_event::<E>();
+    }
+
+    pub fn world(&self) -> &World {
+        return &self.world;
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        return &mut self.world;
+    }
+
     pub fn run_systems(&mut self, dt: std::time::Duration) {
         self.scheduler.run_systems(&mut self.world, dt);
     }
+
+    pub fn run_startup(&mut self) {
+        self.scheduler.run_startup(&mut self.world);
+    }
+
+    pub fn run_simulation_step(&mut self, dt: std::time::Duration) {
+        self.scheduler.run_simulation_step(&mut self.world, dt);
+    }
+
+    pub fn run_render_extract(&mut self, dt: std::time::Duration) {
+        self.scheduler.run_render_extract(&mut self.world, dt);
+    }
 }
@@ -0,0 +1,726 @@
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::{RwLockReadGuard, RwLockWriteGuard},
+};
+
+use crate::ecs::{
+    component::{Component, ComponentStore},
+    entity::Entity,
+    storage::SparseSet,
+    tick::ComponentTicks,
+    world::World,
+};
+
+/// Describes what a query fetches for every matched entity, e.g. `&Position`,
+/// `&mut Velocity`, `Entity` or a tuple of them
+pub trait WorldQuery {
+    /// Borrowed state kept alive while the query exists, usually the store locks
+    type Fetch<'w>;
+
+    /// Value yielded for each matched entity
+    type Item<'f>;
+
+    fn fetch(world: &World) -> Self::Fetch<'_>;
+
+    /// Entities of the backing store, if any
+    /// Used to drive the iteration from the smallest set
+    fn entities<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]>;
+
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool;
+
+    /// Gets the item of an entity
+    ///
+    /// # Safety
+    /// `entity` must match the query, and callers must never hold two items of the
+    /// same entity at the same time since mutable fetches hand out `&mut` references
+    unsafe fn item<'f>(fetch: &'f Self::Fetch<'_>, entity: Entity) -> Self::Item<'f>;
+}
+
+/// Narrows the entities of a query without fetching any data, e.g. `With<T>` or `Without<T>`
+pub trait QueryFilter {
+    type Fetch<'w>;
+
+    fn fetch(world: &World) -> Self::Fetch<'_>;
+
+    fn entities<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]>;
+
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool;
+}
+
+/// Matches entities that have a `T` component, without borrowing it
+pub struct With<T>(PhantomData<T>);
+
+/// Matches entities that do not have a `T` component
+pub struct Without<T>(PhantomData<T>);
+
+/// Matches entities whose `T` component was added since the system last ran
+///
+/// The filter borrows the store for reading, so it cannot be combined with `&mut T`
+pub struct Added<T>(PhantomData<T>);
+
+/// Matches entities whose `T` component was added or mutated since the system last ran
+///
+/// The filter borrows the store for reading, so it cannot be combined with `&mut T`
+pub struct Changed<T>(PhantomData<T>);
+
+/// Mutable access to a component that stamps it as changed when written through
+pub struct Mut<'f, T> {
+    value: &'f mut T,
+    ticks: &'f mut ComponentTicks,
+    this_run: u64,
+}
+
+impl<T> Mut<'_, T> {
+    pub fn ticks(&self) -> ComponentTicks {
+        return *self.ticks;
+    }
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        return self.value;
+    }
+}
+
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.ticks.changed = self.this_run;
+        return self.value;
+    }
+}
+
+pub struct Query<'w, Q: WorldQuery, F: QueryFilter = ()> {
+    fetch: Q::Fetch<'w>,
+    filter: F::Fetch<'w>,
+
+    /// Live entities of the world, only collected when no store can drive the
+    /// iteration, e.g. `Query<Entity>` or `Query<Entity, Without<T>>`
+    all: Option<Vec<Entity>>,
+}
+
+impl<'w, Q: WorldQuery, F: QueryFilter> Query<'w, Q, F> {
+    pub(crate) fn new(world: &'w World) -> Self {
+        let fetch = Q::fetch(world);
+        let filter = F::fetch(world);
+
+        let mut all = None;
+        if Q::entities(&fetch).is_none() && F::entities(&filter).is_none() {
+            all = Some(world.alive_entities());
+        }
+
+        return Self { fetch, filter, all };
+    }
+
+    fn matches(&self, entity: Entity) -> bool {
+        return Q::matches(&self.fetch, entity) && F::matches(&self.filter, entity);
+    }
+
+    /// Smallest set of candidate entities, every matched entity is part of it
+    fn candidates(&self) -> &[Entity] {
+        let candidates = match (Q::entities(&self.fetch), F::entities(&self.filter)) {
+            (Some(a), Some(b)) => Some(smallest(a, b)),
+            (a, b) => a.or(b),
+        };
+
+        return candidates
+            .or(self.all.as_deref())
+            .expect("internal error: query without candidate entities");
+    }
+
+    pub fn iter(&mut self) -> QueryIter<'_, 'w, Q, F> {
+        return QueryIter {
+            entities: self.candidates().iter(),
+            query: self,
+        };
+    }
+
+    /// Gets the item of a single entity, if it matches the query
+    pub fn get(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        if !self.matches(entity) {
+            return None;
+        }
+
+        // SAFETY: the entity matches and `&mut self` prevents any other item from being alive
+        return Some(unsafe { Q::item(&self.fetch, entity) });
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        return self.matches(entity);
+    }
+
+    pub fn count(&self) -> usize {
+        return self
+            .candidates()
+            .iter()
+            .filter(|e| self.matches(**e))
+            .count();
+    }
+}
+
+pub struct QueryIter<'q, 'w, Q: WorldQuery, F: QueryFilter> {
+    query: &'q Query<'w, Q, F>,
+    entities: std::slice::Iter<'q, Entity>,
+}
+
+impl<'q, 'w, Q: WorldQuery, F: QueryFilter> Iterator for QueryIter<'q, 'w, Q, F> {
+    type Item = Q::Item<'q>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for entity in self.entities.by_ref() {
+            if !self.query.matches(*entity) {
+                continue;
+            }
+
+            // SAFETY: the candidate entities are unique so every item is handed out once,
+            // and the iterator borrows the query mutably for its whole lifetime
+            return Some(unsafe { Q::item(&self.query.fetch, *entity) });
+        }
+
+        return None;
+    }
+}
+
+fn smallest<'a>(a: &'a [Entity], b: &'a [Entity]) -> &'a [Entity] {
+    if a.len() <= b.len() {
+        return a;
+    }
+
+    return b;
+}
+
+impl WorldQuery for Entity {
+    type Fetch<'w> = ();
+    type Item<'f> = Entity;
+
+    fn fetch(_world: &World) -> Self::Fetch<'_> {}
+
+    fn entities<'f>(_fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        return None;
+    }
+
+    fn matches(_fetch: &Self::Fetch<'_>, _entity: Entity) -> bool {
+        return true;
+    }
+
+    unsafe fn item<'f>(_fetch: &'f Self::Fetch<'_>, entity: Entity) -> Self::Item<'f> {
+        return entity;
+    }
+}
+
+impl<T: Component> WorldQuery for &T {
+    type Fetch<'w> = RwLockReadGuard<'w, Box<dyn ComponentStore>>;
+    type Item<'f> = &'f T;
+
+    fn fetch(world: &World) -> Self::Fetch<'_> {
+        return world.read_store::<T>();
+    }
+
+    fn entities<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        return Some(fetch.entities());
+    }
+
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        return fetch.has(entity);
+    }
+
+    unsafe fn item<'f>(fetch: &'f Self::Fetch<'_>, entity: Entity) -> Self::Item<'f> {
+        return fetch
+            .as_any_ref()
+            .downcast_ref::<SparseSet<T>>()
+            .expect("internal error: component store type mismatch")
+            .get(entity)
+            .expect("internal error: queried entity is missing the component");
+    }
+}
+
+pub struct WriteFetch<'w, T> {
+    _guard: RwLockWriteGuard<'w, Box<dyn ComponentStore>>,
+    set: *const SparseSet<T>,
+    data: *mut T,
+    ticks: *mut ComponentTicks,
+    this_run: u64,
+}
+
+impl<T: Component> WorldQuery for &mut T {
+    type Fetch<'w> = WriteFetch<'w, T>;
+    type Item<'f> = Mut<'f, T>;
+
+    fn fetch(world: &World) -> Self::Fetch<'_> {
+        let mut guard = world.write_store::<T>();
+
+        let set = guard
+            .as_any_mut()
+            .downcast_mut::<SparseSet<T>>()
+            .expect("internal error: component store type mismatch");
+        let data = set.data_ptr();
+        let ticks = set.ticks_ptr();
+        let set = set as *const SparseSet<T>;
+
+        return WriteFetch {
+            _guard: guard,
+            set,
+            data,
+            ticks,
+            this_run: world.system_ticks().this_run,
+        };
+    }
+
+    fn entities<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        // SAFETY: the pointer targets the boxed store, which the guard keeps locked and alive
+        return Some(unsafe { &*fetch.set }.entities());
+    }
+
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        // SAFETY: see `entities`
+        return unsafe { &*fetch.set }.has(entity);
+    }
+
+    unsafe fn item<'f>(fetch: &'f Self::Fetch<'_>, entity: Entity) -> Self::Item<'f> {
+        let idx = unsafe { &*fetch.set }
+            .index_of(entity)
+            .expect("internal error: queried entity is missing the component");
+
+        // SAFETY: the caller guarantees that no other reference to this component is alive
+        return unsafe {
+            Mut {
+                value: &mut *fetch.data.add(idx),
+                ticks: &mut *fetch.ticks.add(idx),
+                this_run: fetch.this_run,
+            }
+        };
+    }
+}
+
+impl QueryFilter for () {
+    type Fetch<'w> = ();
+
+    fn fetch(_world: &World) -> Self::Fetch<'_> {}
+
+    fn entities<'f>(_fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        return None;
+    }
+
+    fn matches(_fetch: &Self::Fetch<'_>, _entity: Entity) -> bool {
+        return true;
+    }
+}
+
+impl<T: Component> QueryFilter for With<T> {
+    type Fetch<'w> = RwLockReadGuard<'w, Box<dyn ComponentStore>>;
+
+    fn fetch(world: &World) -> Self::Fetch<'_> {
+        return world.read_store::<T>();
+    }
+
+    fn entities<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        return Some(fetch.entities());
+    }
+
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        return fetch.has(entity);
+    }
+}
+
+impl<T: Component> QueryFilter for Without<T> {
+    type Fetch<'w> = RwLockReadGuard<'w, Box<dyn ComponentStore>>;
+
+    fn fetch(world: &World) -> Self::Fetch<'_> {
+        return world.read_store::<T>();
+    }
+
+    fn entities<'f>(_fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        return None;
+    }
+
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        return !fetch.has(entity);
+    }
+}
+
+pub struct TicksFetch<'w, T> {
+    guard: RwLockReadGuard<'w, Box<dyn ComponentStore>>,
+    last_run: u64,
+    marker: PhantomData<T>,
+}
+
+impl<T: Component> TicksFetch<'_, T> {
+    fn new(world: &World) -> TicksFetch<'_, T> {
+        return TicksFetch {
+            guard: world.read_store::<T>(),
+            last_run: world.system_ticks().last_run,
+            marker: PhantomData,
+        };
+    }
+
+    fn ticks(&self, entity: Entity) -> Option<ComponentTicks> {
+        return self
+            .guard
+            .as_any_ref()
+            .downcast_ref::<SparseSet<T>>()
+            .expect("internal error: component store type mismatch")
+            .ticks(entity);
+    }
+}
+
+impl<T: Component> QueryFilter for Added<T> {
+    type Fetch<'w> = TicksFetch<'w, T>;
+
+    fn fetch(world: &World) -> Self::Fetch<'_> {
+        return TicksFetch::new(world);
+    }
+
+    fn entities<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        return Some(fetch.guard.entities());
+    }
+
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        return fetch
+            .ticks(entity)
+            .is_some_and(|ticks| ticks.is_added(fetch.last_run));
+    }
+}
+
+impl<T: Component> QueryFilter for Changed<T> {
+    type Fetch<'w> = TicksFetch<'w, T>;
+
+    fn fetch(world: &World) -> Self::Fetch<'_> {
+        return TicksFetch::new(world);
+    }
+
+    fn entities<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        return Some(fetch.guard.entities());
+    }
+
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        return fetch
+            .ticks(entity)
+            .is_some_and(|ticks| ticks.is_changed(fetch.last_run));
+    }
+}
+
+macro_rules! impl_tuple_query {
+    ($($name:ident),+) => {
+        impl<$($name: WorldQuery),+> WorldQuery for ($($name,)+) {
+            type Fetch<'w> = ($($name::Fetch<'w>,)+);
+            type Item<'f> = ($($name::Item<'f>,)+);
+
+            fn fetch(world: &World) -> Self::Fetch<'_> {
+                return ($($name::fetch(world),)+);
+            }
+
+            #[allow(non_snake_case)]
+            fn entities<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+                let ($($name,)+) = fetch;
+                let mut result: Option<&'f [Entity]> = None;
+                $(
+                    if let Some(entities) = $name::entities($name) {
+                        result = Some(match result {
+                            Some(current) => smallest(current, entities),
+                            None => entities,
+                        });
+                    }
+                )+
+                return result;
+            }
+
+            #[allow(non_snake_case)]
+            fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+                let ($($name,)+) = fetch;
+                return $($name::matches($name, entity))&&+;
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn item<'f>(fetch: &'f Self::Fetch<'_>, entity: Entity) -> Self::Item<'f> {
+                let ($($name,)+) = fetch;
+                return ($(unsafe { $name::item($name, entity) },)+);
+            }
+        }
+
+        impl<$($name: QueryFilter),+> QueryFilter for ($($name,)+) {
+            type Fetch<'w> = ($($name::Fetch<'w>,)+);
+
+            fn fetch(world: &World) -> Self::Fetch<'_> {
+                return ($($name::fetch(world),)+);
+            }
+
+            #[allow(non_snake_case)]
+            fn entities<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+                let ($($name,)+) = fetch;
+                let mut result: Option<&'f [Entity]> = None;
+                $(
+                    if let Some(entities) = $name::entities($name) {
+                        result = Some(match result {
+                            Some(current) => smallest(current, entities),
+                            None => entities,
+                        });
+                    }
+                )+
+                return result;
+            }
+
+            #[allow(non_snake_case)]
+            fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+                let ($($name,)+) = fetch;
+                return $($name::matches($name, entity))&&+;
+            }
+        }
+    };
+}
+
+impl_tuple_query!(A);
+impl_tuple_query!(A, B);
+impl_tuple_query!(A, B, C);
+impl_tuple_query!(A, B, C, D);
+impl_tuple_query!(A, B, C, D, E);
+impl_tuple_query!(A, B, C, D, E, F);
+impl_tuple_query!(A, B, C, D, E, F, G);
+impl_tuple_query!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+    impl Component for Position {}
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+    impl Component for Velocity {}
+
+    struct Crop;
+    impl Component for Crop {}
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.add_component::<Position>();
+        world.add_component::<Velocity>();
+        world.add_component::<Crop>();
+
+        return world;
+    }
+
+    #[test]
+    fn test_query_multiple_components() {
+        let mut world = world();
+
+        let moving = world.spawn();
+        world.insert(moving, Position(0));
+        world.insert(moving, Velocity(2));
+
+        let still = world.spawn();
+        world.insert(still, Position(10));
+
+        let mut q = world.query::<(&mut Position, &Velocity)>();
+        for (mut position, velocity) in q.iter() {
+            position.0 += velocity.0;
+        }
+        drop(q);
+
+        assert_eq!(world.get::<Position>(moving).as_deref(), Some(&Position(2)));
+        assert_eq!(world.get::<Position>(still).as_deref(), Some(&Position(10)));
+    }
+
+    #[test]
+    fn test_query_entity() {
+        let mut world = world();
+
+        let e1 = world.spawn();
+        world.insert(e1, Position(1));
+        let e2 = world.spawn();
+        world.insert(e2, Position(2));
+
+        let mut q = world.query::<(Entity, &Position)>();
+        let mut found: Vec<(Entity, i32)> = q.iter().map(|(e, p)| (e, p.0)).collect();
+        found.sort_by_key(|(_, p)| *p);
+
+        assert_eq!(found, vec![(e1, 1), (e2, 2)]);
+    }
+
+    #[test]
+    fn test_query_all_entities() {
+        let mut world = world();
+
+        let e1 = world.spawn();
+        world.insert(e1, Position(1));
+        let e2 = world.spawn();
+        let e3 = world.spawn();
+        world.despawn(e2);
+
+        let mut q = world.query::<Entity>();
+        assert_eq!(q.iter().collect::<Vec<_>>(), vec![e1, e3]);
+        assert_eq!(q.count(), 2);
+        drop(q);
+
+        let mut q = world.query_filtered::<Entity, Without<Position>>();
+        assert_eq!(q.iter().collect::<Vec<_>>(), vec![e3]);
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let mut world = world();
+
+        let crop = world.spawn();
+        world.insert(crop, Position(1));
+        world.insert(crop, Crop);
+
+        let moving_crop = world.spawn();
+        world.insert(moving_crop, Position(2));
+        world.insert(moving_crop, Velocity(1));
+        world.insert(moving_crop, Crop);
+
+        let villager = world.spawn();
+        world.insert(villager, Position(3));
+
+        let mut q = world.query_filtered::<Entity, (With<Crop>, Without<Velocity>)>();
+        let found: Vec<Entity> = q.iter().collect();
+        assert_eq!(found, vec![crop]);
+
+        let mut q = world.query_filtered::<&Position, Without<Crop>>();
+        let found: Vec<i32> = q.iter().map(|p| p.0).collect();
+        assert_eq!(found, vec![3]);
+    }
+
+    #[test]
+    fn test_query_get() {
+        let mut world = world();
+
+        let e1 = world.spawn();
+        world.insert(e1, Position(1));
+        world.insert(e1, Velocity(1));
+
+        let e2 = world.spawn();
+        world.insert(e2, Position(2));
+
+        let mut q = world.query::<(&Position, &Velocity)>();
+        assert!(q.get(e1).is_some());
+        assert!(q.get(e2).is_none());
+        assert!(q.contains(e1));
+        assert_eq!(q.count(), 1);
+    }
+
+    #[test]
+    fn test_query_drives_from_smallest_set() {
+        let mut world = world();
+
+        for i in 0..10 {
+            let e = world.spawn();
+            world.insert(e, Position(i));
+        }
+
+        let e = world.spawn();
+        world.insert(e, Position(42));
+        world.insert(e, Velocity(0));
+
+        let q = world.query::<(&Position, &Velocity)>();
+        assert_eq!(q.candidates(), &[e]);
+    }
+
+    #[test]
+    fn test_query_conflicting_borrows() {
+        let result = panic::catch_unwind(|| {
+            let world = world();
+            let _q = world.query::<(&mut Position, &Position)>();
+        });
+        assert!(result.is_err());
+
+        let result = panic::catch_unwind(|| {
+            let world = world();
+            let _q1 = world.query::<&mut Position>();
+            let _q2 = world.query::<&Position>();
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_query_shared_borrows() {
+        let world = world();
+        let _q1 = world.query::<&Position>();
+        let _q2 = world.query::<(&Position, &mut Velocity)>();
+    }
+
+    #[test]
+    fn test_added_filter() {
+        let mut world = world();
+
+        let e1 = world.spawn();
+        world.insert(e1, Position(1));
+        world.clear_trackers();
+
+        let e2 = world.spawn();
+        world.insert(e2, Position(2));
+
+        let mut q = world.query_filtered::<Entity, Added<Position>>();
+        assert_eq!(q.iter().collect::<Vec<_>>(), vec![e2]);
+        drop(q);
+
+        world.clear_trackers();
+
+        let mut q = world.query_filtered::<Entity, Added<Position>>();
+        assert_eq!(q.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_changed_filter() {
+        let mut world = world();
+
+        let e1 = world.spawn();
+        world.insert(e1, Position(1));
+        world.insert(e1, Velocity(1));
+
+        let e2 = world.spawn();
+        world.insert(e2, Position(2));
+        world.insert(e2, Velocity(2));
+
+        world.clear_trackers();
+
+        // Only writing through `Mut` marks the component as changed
+        let mut q = world.query::<(Entity, &mut Velocity)>();
+        for (entity, mut velocity) in q.iter() {
+            if entity == e1 {
+                velocity.0 += 1;
+            } else {
+                assert_eq!(velocity.0, 2);
+            }
+        }
+        drop(q);
+
+        let mut q = world.query_filtered::<Entity, Changed<Velocity>>();
+        assert_eq!(q.iter().collect::<Vec<_>>(), vec![e1]);
+        drop(q);
+
+        world.clear_trackers();
+        world.get_mut::<Velocity>(e2).unwrap().0 = 0;
+
+        let mut q = world.query_filtered::<Entity, Changed<Velocity>>();
+        assert_eq!(q.iter().collect::<Vec<_>>(), vec![e2]);
+    }
+
+    #[test]
+    fn test_removed() {
+        let mut world = world();
+
+        let e1 = world.spawn();
+        world.insert(e1, Position(1));
+        world.insert(e1, Velocity(1));
+
+        let e2 = world.spawn();
+        world.insert(e2, Position(2));
+
+        world.clear_trackers();
+
+        world.remove::<Velocity>(e1);
+        world.despawn(e2);
+
+        assert_eq!(world.removed::<Velocity>().collect::<Vec<_>>(), vec![e1]);
+        assert_eq!(world.removed::<Position>().collect::<Vec<_>>(), vec![e2]);
+
+        world.clear_trackers();
+        assert_eq!(world.removed::<Position>().count(), 0);
+    }
+}
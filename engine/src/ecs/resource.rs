@@ -0,0 +1,152 @@
+use std::{
+    any::Any,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::{RwLockReadGuard, RwLockWriteGuard},
+};
+
+/// Singleton data shared by the systems, like the game clock or the active season
+///
+/// Unlike a `Component`, a resource is not attached to any entity and the `World`
+/// holds at most one value of each resource type
+pub trait Resource: 'static + Send + Sync {}
+
+pub(crate) type BoxedResource = Box<dyn Any + Send + Sync>;
+
+/// Shared borrow of a resource, see `World::resource`
+pub struct Res<'w, R: Resource> {
+    guard: RwLockReadGuard<'w, BoxedResource>,
+    marker: PhantomData<R>,
+}
+
+impl<'w, R: Resource> Res<'w, R> {
+    pub(crate) fn new(guard: RwLockReadGuard<'w, BoxedResource>) -> Self {
+        return Self {
+            guard,
+            marker: PhantomData,
+        };
+    }
+}
+
+impl<R: Resource> Deref for Res<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &Self::Target {
+        return self
+            .guard
+            .downcast_ref::<R>()
+            .expect("internal error: resource type mismatch");
+    }
+}
+
+/// Exclusive borrow of a resource, see `World::resource_mut`
+pub struct ResMut<'w, R: Resource> {
+    guard: RwLockWriteGuard<'w, BoxedResource>,
+    marker: PhantomData<R>,
+}
+
+impl<'w, R: Resource> ResMut<'w, R> {
+    pub(crate) fn new(guard: RwLockWriteGuard<'w, BoxedResource>) -> Self {
+        return Self {
+            guard,
+            marker: PhantomData,
+        };
+    }
+}
+
+impl<R: Resource> Deref for ResMut<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &Self::Target {
+        return self
+            .guard
+            .downcast_ref::<R>()
+            .expect("internal error: resource type mismatch");
+    }
+}
+
+impl<R: Resource> DerefMut for ResMut<'_, R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        return self
+            .guard
+            .downcast_mut::<R>()
+            .expect("internal error: resource type mismatch");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use crate::ecs::world::World;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Clock {
+        day: u32,
+    }
+    impl Resource for Clock {}
+
+    #[test]
+    fn test_insert_resource() {
+        let mut world = World::new();
+
+        let old = world.insert_resource(Clock { day: 1 });
+        assert!(old.is_none());
+        assert!(world.has_resource::<Clock>());
+        assert_eq!(world.resource::<Clock>().day, 1);
+
+        let old = world.insert_resource(Clock { day: 2 });
+        assert_eq!(old, Some(Clock { day: 1 }));
+        assert_eq!(world.resource::<Clock>().day, 2);
+    }
+
+    #[test]
+    fn test_resource_mut() {
+        let mut world = World::new();
+        world.insert_resource(Clock { day: 1 });
+
+        world.resource_mut::<Clock>().day += 1;
+        assert_eq!(world.resource::<Clock>().day, 2);
+
+        // Shared borrows can coexist
+        let a = world.resource::<Clock>();
+        let b = world.resource::<Clock>();
+        assert_eq!(a.day, b.day);
+    }
+
+    #[test]
+    fn test_remove_resource() {
+        let mut world = World::new();
+        world.insert_resource(Clock { day: 3 });
+
+        let removed = world.remove_resource::<Clock>();
+        assert_eq!(removed, Some(Clock { day: 3 }));
+        assert!(!world.has_resource::<Clock>());
+        assert!(world.remove_resource::<Clock>().is_none());
+    }
+
+    #[test]
+    fn test_missing_resource() {
+        let result = panic::catch_unwind(|| {
+            let world = World::new();
+            world.resource::<Clock>();
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_conflicting_resource_borrows() {
+        let result = panic::catch_unwind(|| {
+            let mut world = World::new();
+            world.insert_resource(Clock { day: 1 });
+
+            let _a = world.resource_mut::<Clock>();
+            let _b = world.resource::<Clock>();
+        });
+
+        assert!(result.is_err());
+    }
+}
@@ -1,30 +1,1032 @@
-use crate::ecs::world::World;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::ecs::{
+    access::SystemAccess,
+    resource::Resource,
+    tick::{with_system_ticks, SystemTicks},
+    world::World,
+};
 
 pub trait System: Send {
-    fn run(&mut self, world: &mut World, dt: std::time::Duration);
+    /// Systems share the world, so structural changes go through `World::commands`
+    fn run(&mut self, world: &World, dt: std::time::Duration);
+
+    /// What the system touches, used to run non-conflicting systems in parallel
+    fn access(&self) -> SystemAccess {
+        return SystemAccess::exclusive();
+    }
+}
+
+/// Groups of systems executed one after the other on every run
+///
+/// Commands recorded during a stage are applied at its end, so the next stage already
+/// sees the spawned and despawned entities
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    /// Runs only once, before the first run of the other stages
+    Startup,
+    PreUpdate,
+    Update,
+    PostUpdate,
+    /// Copies the simulation state into what the renderer consumes
+    RenderExtract,
+}
+
+impl Stage {
+    /// Stages executed on every simulation step, in order
+    pub const SIMULATION: [Stage; 3] = [Stage::PreUpdate, Stage::Update, Stage::PostUpdate];
+}
+
+/// Decides whether a system runs on a given run of its stage
+pub type RunCondition = Box<dyn FnMut(&World) -> bool + Send>;
+
+pub struct SystemConfig {
+    pub stage: Stage,
+
+    /// Name other systems of the same stage use to order themselves around this one
+    /// Several systems may share a label, in which case the constraints apply to all of them
+    pub label: Option<&'static str>,
+
+    /// Labels of the systems that must run after this one
+    pub before: Vec<&'static str>,
+
+    /// Labels of the systems that must run before this one
+    pub after: Vec<&'static str>,
+
+    pub run_if: Option<RunCondition>,
+}
+
+impl Default for SystemConfig {
+    fn default() -> Self {
+        return Self {
+            stage: Stage::Update,
+            label: None,
+            before: Vec::new(),
+            after: Vec::new(),
+            run_if: None,
+        };
+    }
+}
+
+/// Why the systems of a stage cannot be ordered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// A system is ordered around a label that no system of its stage has
+    UnknownLabel {
+        stage: Stage,
+        system: &'static str,
+        label: &'static str,
+    },
+
+    /// Systems whose `before` and `after` constraints loop back to themselves
+    ///
+    /// Each system is given with its registration index in the stage, since several
+    /// systems can share the same type name
+    Cycle {
+        stage: Stage,
+        systems: Vec<(usize, &'static str)>,
+    },
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ScheduleError::UnknownLabel {
+                stage,
+                system,
+                label,
+            } => write!(
+                f,
+                "system {} is ordered around unknown label \"{}\" in stage {:?}",
+                system, label, stage
+            ),
+            ScheduleError::Cycle { stage, systems } => {
+                let systems: Vec<String> = systems
+                    .iter()
+                    .map(|(i, name)| format!("#{} {}", i, name))
+                    .collect();
+
+                write!(
+                    f,
+                    "ordering cycle between systems of stage {:?}: {}",
+                    stage,
+                    systems.join(", ")
+                )
+            }
+        };
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+struct SystemEntry {
+    name: &'static str,
+    system: Box<dyn System>,
+    access: SystemAccess,
+    config: SystemConfig,
+
+    /// Change tick of the last run, used by change detection
+    last_run: u64,
+}
+
+impl SystemEntry {
+    fn run(&mut self, world: &World, dt: std::time::Duration) {
+        let ticks = SystemTicks {
+            last_run: self.last_run,
+            this_run: world.increment_change_tick(),
+        };
+
+        with_system_ticks(ticks, || self.system.run(world, dt));
+        self.last_run = ticks.this_run;
+    }
+}
+
+#[derive(Default)]
+struct StageSystems {
+    /// Systems in registration order
+    entries: Vec<SystemEntry>,
+
+    /// Indices of `entries` in execution order, grouped in batches of systems
+    /// that can run at the same time
+    batches: Vec<Vec<usize>>,
+
+    /// Whether `order` must be computed again before the next run
+    dirty: bool,
+}
+
+impl StageSystems {
+    fn sort(&mut self, stage: Stage) -> Result<(), ScheduleError> {
+        let mut labels: HashMap<&'static str, Vec<usize>> = HashMap::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            if let Some(label) = entry.config.label {
+                labels.entry(label).or_default().push(i);
+            }
+        }
+
+        let resolve = |label: &'static str, name: &'static str| {
+            return labels.get(label).ok_or(ScheduleError::UnknownLabel {
+                stage,
+                system: name,
+                label,
+            });
+        };
+
+        // Edges go from the system that runs first to the one that runs later
+        let mut edges: Vec<Vec<usize>> = vec![Vec::new(); self.entries.len()];
+        let mut in_degree: Vec<usize> = vec![0; self.entries.len()];
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            for label in entry.config.before.iter() {
+                for &j in resolve(label, entry.name)? {
+                    edges[i].push(j);
+                    in_degree[j] += 1;
+                }
+            }
+
+            for label in entry.config.after.iter() {
+                for &j in resolve(label, entry.name)? {
+                    edges[j].push(i);
+                    in_degree[i] += 1;
+                }
+            }
+        }
+
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); self.entries.len()];
+        for (i, targets) in edges.iter().enumerate() {
+            for &j in targets.iter() {
+                predecessors[j].push(i);
+            }
+        }
+
+        // Kahn's algorithm, always picking the earliest registered system that is ready
+        // so that unconstrained systems keep their registration order
+        let mut order = Vec::with_capacity(self.entries.len());
+        let mut done = vec![false; self.entries.len()];
+
+        while order.len() < self.entries.len() {
+            let next = (0..self.entries.len()).find(|&i| !done[i] && in_degree[i] == 0);
+
+            let i = match next {
+                Some(i) => i,
+                None => {
+                    let systems = find_cycle(&edges, &predecessors, &done)
+                        .into_iter()
+                        .map(|i| (i, self.entries[i].name))
+                        .collect();
+
+                    return Err(ScheduleError::Cycle { stage, systems });
+                }
+            };
+
+            done[i] = true;
+            order.push(i);
+
+            for &j in edges[i].iter() {
+                in_degree[j] -= 1;
+            }
+        }
+
+        // A system joins the last batch when it is compatible with all of its systems and
+        // none of them must run before it. Since systems are visited in topological order,
+        // every predecessor is either in the last batch or in an earlier one
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+
+        for i in order {
+            let joins = batches.last().is_some_and(|batch| {
+                return batch.iter().all(|&j| {
+                    return !predecessors[i].contains(&j)
+                        && self.entries[i]
+                            .access
+                            .is_compatible(&self.entries[j].access);
+                });
+            });
+
+            match batches.last_mut() {
+                Some(batch) if joins => batch.push(i),
+                _ => batches.push(vec![i]),
+            }
+        }
+
+        self.batches = batches;
+        self.dirty = false;
+
+        return Ok(());
+    }
+
+    fn run(
+        &mut self,
+        stage: Stage,
+        world: &World,
+        pool: &rayon::ThreadPool,
+        dt: std::time::Duration,
+    ) {
+        // Systems added after `Scheduler::build` are ordered on the next run
+        if self.dirty {
+            if let Err(e) = self.sort(stage) {
+                panic!("{}", e);
+            }
+        }
+
+        for batch in self.batches.iter() {
+            // Conditions are checked right before the batch so they see the effects of the previous ones
+            let mut runnable: Vec<&mut SystemEntry> = Vec::with_capacity(batch.len());
+            for (i, entry) in self.entries.iter_mut().enumerate() {
+                if !batch.contains(&i) {
+                    continue;
+                }
+
+                if let Some(condition) = entry.config.run_if.as_mut() {
+                    if !condition(world) {
+                        continue;
+                    }
+                }
+
+                runnable.push(entry);
+            }
+
+            if runnable.len() == 1 {
+                runnable[0].run(world, dt);
+                continue;
+            }
+
+            pool.scope(|scope| {
+                for entry in runnable {
+                    scope.spawn(move |_| entry.run(world, dt));
+                }
+            });
+        }
+    }
+}
+
+/// Systems of one ordering cycle among the systems left unsorted, in registration order
+///
+/// Every unsorted system has an unsorted predecessor, so walking back through them
+/// must loop. The cycle is the set of systems both reachable from and leading to
+/// the system where the walk looped, other unsorted systems merely depend on it
+fn find_cycle(edges: &[Vec<usize>], predecessors: &[Vec<usize>], done: &[bool]) -> Vec<usize> {
+    let mut visited = vec![false; done.len()];
+    let mut i = (0..done.len())
+        .find(|&i| !done[i])
+        .expect("internal error: no unsorted system");
+
+    while !visited[i] {
+        visited[i] = true;
+        i = *predecessors[i]
+            .iter()
+            .find(|&&j| !done[j])
+            .expect("internal error: unsorted system without unsorted predecessor");
+    }
+
+    let reachable = |start: usize, next: &[Vec<usize>]| {
+        let mut seen = vec![false; done.len()];
+        let mut stack = vec![start];
+        seen[start] = true;
+
+        while let Some(k) = stack.pop() {
+            for &j in next[k].iter() {
+                if !done[j] && !seen[j] {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+
+        return seen;
+    };
+
+    let forward = reachable(i, edges);
+    let backward = reachable(i, predecessors);
+
+    return (0..done.len())
+        .filter(|&k| forward[k] && backward[k])
+        .collect();
 }
 
 pub struct Scheduler {
-    systems: Vec<Box<dyn System>>,
+    stages: BTreeMap<Stage, StageSystems>,
+    startup_done: bool,
+
+    /// Threads running the batches of non-conflicting systems
+    pool: rayon::ThreadPool,
 }
 
 impl Scheduler {
+    /// Creates a scheduler with one worker thread per logical CPU
     pub fn new() -> Self {
+        return Self::with_thread_count(0);
+    }
+
+    /// Creates a scheduler with a fixed number of worker threads, `0` picks one per logical CPU
+    pub fn with_thread_count(threads: usize) -> Self {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("ecs-worker-{}", i))
+            .build()
+            .expect("Could not create the scheduler thread pool");
+
         return Scheduler {
-            systems: Vec::new(),
+            stages: BTreeMap::new(),
+            startup_done: false,
+            pool,
         };
     }
 
+    /// Adds a system to the `Update` stage without ordering constraints
     pub fn add_system<S>(&mut self, system: S)
     where
         S: System + 'static,
     {
-        self.systems.push(Box::new(system));
+        self.add_system_with_config(system, SystemConfig::default());
+    }
+
+    pub fn add_system_with_config<S>(&mut self, system: S, config: SystemConfig)
+    where
+        S: System + 'static,
+    {
+        let stage = self.stages.entry(config.stage).or_default();
+
+        stage.entries.push(SystemEntry {
+            name: std::any::type_name::<S>(),
+            access: system.access(),
+            system: Box::new(system),
+            config,
+            last_run: 0,
+        });
+        stage.dirty = true;
+    }
+
+    /// Orders the systems of every stage, reporting unknown labels and ordering cycles
+    ///
+    /// Called once every system is added, so a bad configuration is caught before the
+    /// first run instead of panicking in the middle of a frame
+    pub fn build(&mut self) -> Result<(), ScheduleError> {
+        for (stage, systems) in self.stages.iter_mut() {
+            if systems.dirty {
+                systems.sort(*stage)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Runs the systems of a single stage, then applies the commands they recorded
+    pub fn run_stage(&mut self, stage: Stage, world: &mut World, dt: std::time::Duration) {
+        if let Some(systems) = self.stages.get_mut(&stage) {
+            systems.run(stage, world, &self.pool, dt);
+        }
+
+        // Sync point: the structural changes recorded by the stage are visible to the next one
+        world.apply_commands();
+    }
+
+    /// Runs the startup stage, only the first call has any effect
+    pub fn run_startup(&mut self, world: &mut World) {
+        if self.startup_done {
+            return;
+        }
+
+        self.run_stage(Stage::Startup, world, std::time::Duration::ZERO);
+        self.startup_done = true;
+    }
+
+    /// Runs one simulation step: the startup stage if it did not run yet, then the
+    /// `PreUpdate`, `Update` and `PostUpdate` stages
+    pub fn run_simulation_step(&mut self, world: &mut World, dt: std::time::Duration) {
+        self.run_startup(world);
+
+        for stage in Stage::SIMULATION {
+            self.run_stage(stage, world, dt);
+        }
+
+        // Events of the previous step are dropped, the ones sent in this step stay readable for the next one
+        world.update_events();
+        world.clear_trackers();
     }
 
+    /// Runs the `RenderExtract` stage, once per rendered frame
+    pub fn run_render_extract(&mut self, world: &mut World, dt: std::time::Duration) {
+        self.run_stage(Stage::RenderExtract, world, dt);
+    }
+
+    /// Runs a simulation step followed by the render extraction
     pub fn run_systems(&mut self, world: &mut World, dt: std::time::Duration) {
-        for system in self.systems.iter_mut() {
-            system.run(world, dt);
+        self.run_simulation_step(world, dt);
+        self.run_render_extract(world, dt);
+    }
+}
+
+/// Runs the system once every `n` runs of its stage, starting on the first one
+pub fn every_n_ticks(n: u32) -> RunCondition {
+    assert!(n > 0, "every_n_ticks requires a positive interval");
+
+    let mut tick = 0;
+
+    return Box::new(move |_| {
+        let run = tick % n == 0;
+        tick += 1;
+        return run;
+    });
+}
+
+/// Runs the system only while the resource equals `value`, e.g. the game state is `Playing`
+pub fn resource_equals<R>(value: R) -> RunCondition
+where
+    R: Resource + PartialEq,
+{
+    return Box::new(move |world| {
+        return world.has_resource::<R>() && *world.resource::<R>() == value;
+    });
+}
+
+/// Runs the system only if the resource exists
+pub fn resource_exists<R: Resource>() -> RunCondition {
+    return Box::new(|world| world.has_resource::<R>());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        any::TypeId,
+        collections::HashMap,
+        panic,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use crate::ecs::component::Component;
+
+    use super::*;
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    struct Record {
+        name: &'static str,
+        log: Log,
+    }
+    impl System for Record {
+        fn run(&mut self, _world: &World, _dt: std::time::Duration) {
+            self.log.lock().unwrap().push(self.name);
         }
     }
+
+    fn record(name: &'static str, log: &Log) -> Record {
+        return Record {
+            name,
+            log: log.clone(),
+        };
+    }
+
+    fn run(scheduler: &mut Scheduler, world: &mut World) {
+        scheduler.run_systems(world, std::time::Duration::ZERO);
+    }
+
+    #[test]
+    fn test_stage_order() {
+        let log = Log::default();
+        let mut world = World::new();
+        let mut scheduler = Scheduler::new();
+
+        let stages = [
+            ("extract", Stage::RenderExtract),
+            ("post", Stage::PostUpdate),
+            ("update", Stage::Update),
+            ("pre", Stage::PreUpdate),
+            ("startup", Stage::Startup),
+        ];
+
+        for (name, stage) in stages {
+            scheduler.add_system_with_config(
+                record(name, &log),
+                SystemConfig {
+                    stage,
+                    ..Default::default()
+                },
+            );
+        }
+
+        run(&mut scheduler, &mut world);
+        run(&mut scheduler, &mut world);
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["startup", "pre", "update", "post", "extract", "pre", "update", "post", "extract"]
+        );
+    }
+
+    #[test]
+    fn test_ordering_constraints() {
+        let log = Log::default();
+        let mut world = World::new();
+        let mut scheduler = Scheduler::new();
+
+        scheduler.add_system_with_config(
+            record("render", &log),
+            SystemConfig {
+                label: Some("render"),
+                after: vec!["movement"],
+                ..Default::default()
+            },
+        );
+        scheduler.add_system(record("unordered", &log));
+        scheduler.add_system_with_config(
+            record("movement", &log),
+            SystemConfig {
+                label: Some("movement"),
+                after: vec!["input"],
+                ..Default::default()
+            },
+        );
+        scheduler.add_system_with_config(
+            record("input", &log),
+            SystemConfig {
+                label: Some("input"),
+                before: vec!["render"],
+                ..Default::default()
+            },
+        );
+
+        run(&mut scheduler, &mut world);
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["unordered", "input", "movement", "render"]
+        );
+    }
+
+    #[test]
+    fn test_ordering_cycle() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+
+        scheduler.add_system_with_config(
+            record("a", &log),
+            SystemConfig {
+                label: Some("a"),
+                after: vec!["b"],
+                ..Default::default()
+            },
+        );
+        scheduler.add_system_with_config(
+            record("b", &log),
+            SystemConfig {
+                label: Some("b"),
+                after: vec!["a"],
+                ..Default::default()
+            },
+        );
+
+        // Only depends on the cycle, so it is not part of it
+        scheduler.add_system_with_config(
+            record("c", &log),
+            SystemConfig {
+                after: vec!["a"],
+                ..Default::default()
+            },
+        );
+
+        let error = scheduler.build().unwrap_err();
+        let name = std::any::type_name::<Record>();
+        assert_eq!(
+            error,
+            ScheduleError::Cycle {
+                stage: Stage::Update,
+                systems: vec![(0, name), (1, name)],
+            }
+        );
+        assert!(error
+            .to_string()
+            .ends_with(&format!("#0 {}, #1 {}", name, name)));
+
+        // Running without building still reports the cycle
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            run(&mut scheduler, &mut World::new());
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_unknown_label() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+
+        scheduler.add_system_with_config(
+            record("a", &log),
+            SystemConfig {
+                after: vec!["missing"],
+                ..Default::default()
+            },
+        );
+
+        assert!(matches!(
+            scheduler.build(),
+            Err(ScheduleError::UnknownLabel {
+                stage: Stage::Update,
+                label: "missing",
+                ..
+            })
+        ));
+
+        // The label can be added later, the stage is ordered again on the next build
+        scheduler.add_system_with_config(
+            record("b", &log),
+            SystemConfig {
+                label: Some("missing"),
+                ..Default::default()
+            },
+        );
+        assert!(scheduler.build().is_ok());
+
+        run(&mut scheduler, &mut World::new());
+        assert_eq!(*log.lock().unwrap(), vec!["b", "a"]);
+    }
+
+    #[derive(PartialEq)]
+    enum GameState {
+        Menu,
+        Playing,
+    }
+    impl Resource for GameState {}
+
+    #[test]
+    fn test_run_conditions() {
+        let log = Log::default();
+        let mut world = World::new();
+        world.insert_resource(GameState::Menu);
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add_system_with_config(
+            record("playing", &log),
+            SystemConfig {
+                run_if: Some(resource_equals(GameState::Playing)),
+                ..Default::default()
+            },
+        );
+        scheduler.add_system_with_config(
+            record("every_3", &log),
+            SystemConfig {
+                run_if: Some(every_n_ticks(3)),
+                ..Default::default()
+            },
+        );
+
+        run(&mut scheduler, &mut world);
+        run(&mut scheduler, &mut world);
+
+        world.insert_resource(GameState::Playing);
+
+        run(&mut scheduler, &mut world);
+        run(&mut scheduler, &mut world);
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["every_3", "playing", "playing", "every_3"]
+        );
+    }
+
+    struct Position;
+    impl Component for Position {}
+
+    struct Velocity;
+    impl Component for Velocity {}
+
+    struct Crop;
+    impl Component for Crop {}
+
+    /// Does nothing, only declares its access
+    struct Declared {
+        access: SystemAccess,
+    }
+    impl System for Declared {
+        fn run(&mut self, _world: &World, _dt: std::time::Duration) {}
+
+        fn access(&self) -> SystemAccess {
+            return self.access.clone();
+        }
+    }
+
+    fn batches(scheduler: &mut Scheduler, stage: Stage) -> Vec<Vec<usize>> {
+        let systems = scheduler.stages.get_mut(&stage).unwrap();
+        systems.sort(stage).unwrap();
+        return systems.batches.clone();
+    }
+
+    #[test]
+    fn test_batches() {
+        let mut scheduler = Scheduler::new();
+
+        let systems = [
+            SystemAccess::new().read::<Position>(),
+            SystemAccess::new().read::<Position>().write::<Velocity>(),
+            SystemAccess::new().write::<Position>(),
+            SystemAccess::new().write::<Crop>(),
+            SystemAccess::exclusive(),
+            SystemAccess::new().read::<Crop>(),
+        ];
+
+        for access in systems {
+            scheduler.add_system(Declared { access });
+        }
+
+        assert_eq!(
+            batches(&mut scheduler, Stage::Update),
+            vec![vec![0, 1], vec![2, 3], vec![4], vec![5]]
+        );
+    }
+
+    #[test]
+    fn test_batches_respect_ordering() {
+        let mut scheduler = Scheduler::new();
+
+        scheduler.add_system_with_config(
+            Declared {
+                access: SystemAccess::new().read::<Position>(),
+            },
+            SystemConfig {
+                label: Some("first"),
+                ..Default::default()
+            },
+        );
+        scheduler.add_system_with_config(
+            Declared {
+                access: SystemAccess::new().read::<Position>(),
+            },
+            SystemConfig {
+                after: vec!["first"],
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            batches(&mut scheduler, Stage::Update),
+            vec![vec![0], vec![1]]
+        );
+    }
+
+    /// Tracks which component types are being read or written at any moment
+    #[derive(Default)]
+    struct Tracker {
+        readers: HashMap<TypeId, AtomicUsize>,
+        writers: HashMap<TypeId, AtomicBool>,
+
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+        conflicts: AtomicUsize,
+    }
+
+    impl Tracker {
+        fn new(types: &[TypeId]) -> Self {
+            let mut tracker = Self::default();
+            for t in types {
+                tracker.readers.insert(*t, AtomicUsize::new(0));
+                tracker.writers.insert(*t, AtomicBool::new(false));
+            }
+
+            return tracker;
+        }
+    }
+
+    struct Tracked {
+        reads: Vec<TypeId>,
+        writes: Vec<TypeId>,
+        access: SystemAccess,
+        tracker: Arc<Tracker>,
+    }
+
+    impl System for Tracked {
+        fn run(&mut self, _world: &World, _dt: std::time::Duration) {
+            let t = &self.tracker;
+
+            let running = t.running.fetch_add(1, Ordering::SeqCst) + 1;
+            t.max_running.fetch_max(running, Ordering::SeqCst);
+
+            for r in self.reads.iter() {
+                t.readers[r].fetch_add(1, Ordering::SeqCst);
+                if t.writers[r].load(Ordering::SeqCst) {
+                    t.conflicts.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+
+            for w in self.writes.iter() {
+                let busy = t.writers[w].swap(true, Ordering::SeqCst);
+                if busy || t.readers[w].load(Ordering::SeqCst) > 0 {
+                    t.conflicts.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+
+            std::thread::sleep(Duration::from_millis(2));
+
+            for w in self.writes.iter() {
+                t.writers[w].store(false, Ordering::SeqCst);
+            }
+
+            for r in self.reads.iter() {
+                t.readers[r].fetch_sub(1, Ordering::SeqCst);
+            }
+
+            t.running.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        fn access(&self) -> SystemAccess {
+            return self.access.clone();
+        }
+    }
+
+    #[test]
+    fn test_conflicting_systems_never_overlap() {
+        let position = TypeId::of::<Position>();
+        let velocity = TypeId::of::<Velocity>();
+        let crop = TypeId::of::<Crop>();
+
+        let tracker = Arc::new(Tracker::new(&[position, velocity, crop]));
+        let mut scheduler = Scheduler::with_thread_count(4);
+
+        let systems: Vec<(Vec<TypeId>, Vec<TypeId>, SystemAccess)> = vec![
+            (
+                vec![position],
+                vec![],
+                SystemAccess::new().read::<Position>(),
+            ),
+            (
+                vec![position],
+                vec![velocity],
+                SystemAccess::new().read::<Position>().write::<Velocity>(),
+            ),
+            (
+                vec![],
+                vec![position],
+                SystemAccess::new().write::<Position>(),
+            ),
+            (vec![], vec![crop], SystemAccess::new().write::<Crop>()),
+            (
+                vec![crop, velocity],
+                vec![],
+                SystemAccess::new().read::<Crop>().read::<Velocity>(),
+            ),
+            (
+                vec![position],
+                vec![],
+                SystemAccess::new().read::<Position>(),
+            ),
+            (
+                vec![],
+                vec![velocity],
+                SystemAccess::new().write::<Velocity>(),
+            ),
+        ];
+
+        for (reads, writes, access) in systems {
+            scheduler.add_system(Tracked {
+                reads,
+                writes,
+                access,
+                tracker: tracker.clone(),
+            });
+        }
+
+        let mut world = World::new();
+        for _ in 0..20 {
+            run(&mut scheduler, &mut world);
+        }
+
+        assert_eq!(tracker.conflicts.load(Ordering::SeqCst), 0);
+        assert!(tracker.max_running.load(Ordering::SeqCst) > 1);
+    }
+
+    #[test]
+    fn test_parallel_queries() {
+        struct Move {}
+        impl System for Move {
+            fn run(&mut self, world: &World, _dt: std::time::Duration) {
+                let mut q = world.query::<&mut Position>();
+                assert_eq!(q.iter().count(), 1);
+            }
+
+            fn access(&self) -> SystemAccess {
+                return SystemAccess::new().write::<Position>();
+            }
+        }
+
+        struct Grow {}
+        impl System for Grow {
+            fn run(&mut self, world: &World, _dt: std::time::Duration) {
+                let mut q = world.query::<&mut Crop>();
+                assert_eq!(q.iter().count(), 1);
+            }
+
+            fn access(&self) -> SystemAccess {
+                return SystemAccess::new().write::<Crop>();
+            }
+        }
+
+        let mut world = World::new();
+        world.add_component::<Position>();
+        world.add_component::<Crop>();
+
+        let e = world.spawn();
+        world.insert(e, Position);
+        world.insert(e, Crop);
+
+        let mut scheduler = Scheduler::with_thread_count(2);
+        scheduler.add_system(Move {});
+        scheduler.add_system(Grow {});
+
+        for _ in 0..10 {
+            run(&mut scheduler, &mut world);
+        }
+    }
+
+    #[test]
+    fn test_change_detection_per_system() {
+        struct Plant(u32);
+        impl Component for Plant {}
+
+        struct Grow {}
+        impl System for Grow {
+            fn run(&mut self, world: &World, _dt: std::time::Duration) {
+                let mut q = world.query::<&mut Plant>();
+                for mut plant in q.iter() {
+                    if plant.0 < 2 {
+                        plant.0 += 1;
+                    }
+                }
+            }
+        }
+
+        /// Counts the plants that changed since it last ran
+        struct Watch {
+            changed: Arc<Mutex<Vec<usize>>>,
+        }
+        impl System for Watch {
+            fn run(&mut self, world: &World, _dt: std::time::Duration) {
+                let q = world.query_filtered::<&Plant, crate::ecs::query::Changed<Plant>>();
+                self.changed.lock().unwrap().push(q.count());
+            }
+        }
+
+        let changed = Arc::new(Mutex::new(Vec::new()));
+
+        let mut world = World::new();
+        world.add_component::<Plant>();
+
+        let e = world.spawn();
+        world.insert(e, Plant(0));
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add_system(Grow {});
+        scheduler.add_system(Watch {
+            changed: changed.clone(),
+        });
+
+        for _ in 0..4 {
+            run(&mut scheduler, &mut world);
+        }
+
+        assert_eq!(*changed.lock().unwrap(), vec![1, 1, 0, 0]);
+    }
 }
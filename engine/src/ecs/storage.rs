@@ -3,6 +3,7 @@ use std::any::Any;
 use crate::ecs::{
     component::{Component, ComponentStore},
     entity::Entity,
+    tick::ComponentTicks,
 };
 
 /// A sparse set is a data structure for storing components
@@ -16,6 +17,9 @@ pub struct SparseSet<T> {
 
     /// Packed array of components matching dense array
     data: Vec<T>,
+
+    /// Packed array of change ticks matching dense array
+    ticks: Vec<ComponentTicks>,
 }
 
 impl<T> SparseSet<T> {
@@ -27,10 +31,17 @@ impl<T> SparseSet<T> {
             sparse: vec![None; capacity],
             dense,
             data: Vec::new(),
+            ticks: Vec::new(),
         };
     }
 
     pub fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        return self.insert_with_tick(entity, component, 0);
+    }
+
+    /// Inserts the component, stamping it as added at `tick`
+    /// Replacing an existing component only stamps it as changed
+    pub fn insert_with_tick(&mut self, entity: Entity, component: T, tick: u64) -> Option<T> {
         let id = entity.idx();
 
         if id >= self.capacity() {
@@ -42,6 +53,7 @@ impl<T> SparseSet<T> {
         if sparse.is_none() {
             self.dense.push(entity);
             self.data.push(component);
+            self.ticks.push(ComponentTicks::new(tick));
 
             self.sparse[id] = Some(self.dense.len() - 1);
             return None;
@@ -49,6 +61,12 @@ impl<T> SparseSet<T> {
 
         let idx = sparse.unwrap();
 
+        if self.dense[idx] == entity {
+            self.ticks[idx].changed = tick;
+        } else {
+            self.ticks[idx] = ComponentTicks::new(tick);
+        }
+
         self.dense[idx] = entity;
         let old = std::mem::replace(&mut self.data[idx], component);
 
@@ -65,6 +83,7 @@ impl<T> SparseSet<T> {
 
         self.dense.swap(idx, last_idx);
         self.data.swap(idx, last_idx);
+        self.ticks.swap(idx, last_idx);
 
         if idx != last_idx {
             let swapped = self.dense[idx].idx();
@@ -73,6 +92,7 @@ impl<T> SparseSet<T> {
 
         self.sparse[entity.idx()] = None;
         self.dense.pop();
+        self.ticks.pop();
 
         return Some(self.data.pop().unwrap());
     }
@@ -81,6 +101,51 @@ impl<T> SparseSet<T> {
         return self.sparse.len();
     }
 
+    pub fn len(&self) -> usize {
+        return self.dense.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.dense.is_empty();
+    }
+
+    /// Entities stored in the set, in the same order as the components
+    pub fn entities(&self) -> &[Entity] {
+        return &self.dense;
+    }
+
+    /// Position of the entity component in the dense array
+    pub(crate) fn index_of(&self, entity: Entity) -> Option<usize> {
+        if !self.has(entity) {
+            return None;
+        }
+
+        return self.sparse[entity.idx()];
+    }
+
+    pub fn ticks(&self, entity: Entity) -> Option<ComponentTicks> {
+        let idx = self.index_of(entity)?;
+        return Some(self.ticks[idx]);
+    }
+
+    /// Stamps the component of the entity as changed at `tick`
+    pub fn set_changed(&mut self, entity: Entity, tick: u64) {
+        if let Some(idx) = self.index_of(entity) {
+            self.ticks[idx].changed = tick;
+        }
+    }
+
+    /// Raw pointer to the packed change ticks, see `data_ptr`
+    pub(crate) fn ticks_ptr(&mut self) -> *mut ComponentTicks {
+        return self.ticks.as_mut_ptr();
+    }
+
+    /// Raw pointer to the packed components, used by queries to hand out
+    /// mutable references to distinct entities at the same time
+    pub(crate) fn data_ptr(&mut self) -> *mut T {
+        return self.data.as_mut_ptr();
+    }
+
     /// Checks if an entity has a component
     pub fn has(&self, entity: Entity) -> bool {
         let v = entity.idx();
@@ -139,8 +204,16 @@ impl<T> SparseSet<T> {
 }
 
 impl<T: Component> ComponentStore for SparseSet<T> {
-    fn remove(&mut self, entity: Entity) {
-        self.remove(entity);
+    fn remove(&mut self, entity: Entity) -> bool {
+        return self.remove(entity).is_some();
+    }
+
+    fn has(&self, entity: Entity) -> bool {
+        return self.has(entity);
+    }
+
+    fn entities(&self) -> &[Entity] {
+        return self.entities();
     }
 
     fn as_any_ref(&self) -> &dyn Any {
@@ -242,4 +315,34 @@ mod tests {
         assert_eq!(v.1, value);
         assert!(iter.next().is_none());
     }
+
+    #[test]
+    fn test_ticks() {
+        let mut s = SparseSet::<String>::with_capacity(10);
+        let e1 = Entity::new(1, 0);
+        let e2 = Entity::new(2, 0);
+
+        s.insert_with_tick(e1, "a".to_string(), 1);
+        s.insert_with_tick(e2, "b".to_string(), 2);
+        assert_eq!(s.ticks(e1), Some(ComponentTicks::new(1)));
+
+        // Replacing only changes the component
+        s.insert_with_tick(e1, "c".to_string(), 3);
+        assert_eq!(
+            s.ticks(e1),
+            Some(ComponentTicks {
+                added: 1,
+                changed: 3
+            })
+        );
+
+        // Ticks follow their component when the dense array is reordered
+        s.remove(e1);
+        assert_eq!(s.ticks(e1), None);
+        assert_eq!(s.ticks(e2), Some(ComponentTicks::new(2)));
+
+        s.set_changed(e2, 4);
+        assert!(s.ticks(e2).unwrap().is_changed(3));
+        assert!(!s.ticks(e2).unwrap().is_added(3));
+    }
 }
@@ -0,0 +1,55 @@
+use std::cell::Cell;
+
+/// When a component was added and last changed, in world change ticks
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: u64,
+    pub changed: u64,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: u64) -> Self {
+        return Self {
+            added: tick,
+            changed: tick,
+        };
+    }
+
+    pub fn is_added(&self, last_run: u64) -> bool {
+        return self.added > last_run;
+    }
+
+    /// Newly added components count as changed too
+    pub fn is_changed(&self, last_run: u64) -> bool {
+        return self.changed > last_run;
+    }
+}
+
+/// Ticks of the system currently running
+///
+/// Anything stamped after `last_run` happened since the system last ran,
+/// and changes made by the system are stamped with `this_run`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SystemTicks {
+    pub last_run: u64,
+    pub this_run: u64,
+}
+
+thread_local! {
+    /// Systems share the `World` and may run on any worker thread, so the ticks of the
+    /// running system are tracked per thread instead of inside the world
+    static CURRENT: Cell<Option<SystemTicks>> = const { Cell::new(None) };
+}
+
+/// Runs `f` with `ticks` as the ticks of the current system
+pub(crate) fn with_system_ticks<T>(ticks: SystemTicks, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.with(|c| c.replace(Some(ticks)));
+    let result = f();
+    CURRENT.with(|c| c.set(previous));
+
+    return result;
+}
+
+pub(crate) fn current_system_ticks() -> Option<SystemTicks> {
+    return CURRENT.with(|c| c.get());
+}
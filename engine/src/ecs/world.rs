@@ -1,34 +1,117 @@
-use std::{any::TypeId, collections::HashMap};
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
+    },
+};
 
 use crate::ecs::{
-    component::{Component, ComponentStore},
+    commands::{Command, Commands},
+    component::{Component, ComponentStore, Ref},
     entity::{Entity, EntityAllocator},
+    event::{update_events, Event, EventWriter, Events},
+    query::{Query, QueryFilter, WorldQuery},
+    resource::{BoxedResource, Res, ResMut, Resource},
     storage::SparseSet,
+    tick::{current_system_ticks, SystemTicks},
 };
 
 pub struct World {
-    entity_allocator: EntityAllocator,
-    stores: HashMap<TypeId, Box<dyn ComponentStore>>,
+    /// Locked so that `Commands` can reserve entities while the world is shared
+    entity_allocator: Mutex<EntityAllocator>,
+
+    /// Every store lives behind its own lock so a query can borrow some component
+    /// types mutably while other types are being read elsewhere
+    stores: HashMap<TypeId, RwLock<Box<dyn ComponentStore>>>,
+
+    /// Singleton values keyed by their type, locked the same way as the stores
+    resources: HashMap<TypeId, RwLock<BoxedResource>>,
+
+    /// Buffer swaps of every registered event type
+    event_updaters: Vec<fn(&World)>,
+
+    /// Operations recorded through `Commands`, waiting for the next sync point
+    command_queue: Mutex<Vec<Command>>,
+
+    /// Latest change tick, advanced every time a system runs
+    change_tick: AtomicU64,
+
+    /// Change tick of the last `clear_trackers` call, used as the last run
+    /// when the world is accessed outside of a system
+    last_change_tick: u64,
+
+    /// Entities that lost a component, with the tick of the removal
+    removed: HashMap<TypeId, Vec<(Entity, u64)>>,
 }
 
 impl World {
     pub fn new() -> Self {
         return Self {
-            entity_allocator: EntityAllocator::new(),
+            entity_allocator: Mutex::new(EntityAllocator::new()),
             stores: HashMap::new(),
+            resources: HashMap::new(),
+            event_updaters: Vec::new(),
+            command_queue: Mutex::new(Vec::new()),
+            change_tick: AtomicU64::new(1),
+            last_change_tick: 0,
+            removed: HashMap::new(),
         };
     }
 
     pub fn spawn(&mut self) -> Entity {
-        let e = self.entity_allocator.alloc();
+        let e = self
+            .entity_allocator
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .alloc();
         return e;
     }
 
-    pub fn despawn(&mut self, entity: Entity) {
-        self.entity_allocator.free(entity);
+    /// Allocates an entity through a shared reference, used by `Commands::spawn`
+    pub(crate) fn reserve_entity(&self) -> Entity {
+        return self
+            .entity_allocator
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .alloc();
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        return self
+            .entity_allocator
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_alive(entity);
+    }
+
+    /// Every live entity, used by queries that have no component type to iterate
+    pub(crate) fn alive_entities(&self) -> Vec<Entity> {
+        return self
+            .entity_allocator
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .alive();
+    }
 
-        for (_, store) in &mut self.stores {
-            store.remove(entity);
+    pub fn despawn(&mut self, entity: Entity) {
+        self.entity_allocator
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .free(entity);
+
+        let tick = self.change_tick();
+
+        for (t, store) in self.stores.iter_mut() {
+            let removed = store
+                .get_mut()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(entity);
+
+            if removed {
+                self.removed.entry(*t).or_default().push((entity, tick));
+            }
         }
     }
 
@@ -40,22 +123,17 @@ impl World {
             return;
         }
 
-        let b = Box::new(SparseSet::<C>::with_capacity(10));
-        self.stores.insert(t, b);
+        let b: Box<dyn ComponentStore> = Box::new(SparseSet::<C>::with_capacity(10));
+        self.stores.insert(t, RwLock::new(b));
     }
 
-    fn get_downcasted_store_ref<C: Component>(&self) -> &SparseSet<C> {
-        let s = self.stores.get(&TypeId::of::<C>()).unwrap_or_else(|| {
+    fn get_store_lock<C: Component>(&self) -> &RwLock<Box<dyn ComponentStore>> {
+        return self.stores.get(&TypeId::of::<C>()).unwrap_or_else(|| {
             panic!(
-                "tried to insert on unregistered component: {}",
+                "tried to access unregistered component: {}",
                 std::any::type_name::<C>()
             )
         });
-
-        return s
-            .as_any_ref()
-            .downcast_ref::<SparseSet<C>>()
-            .expect("internal error: component store type mismatch");
     }
 
     fn get_downcasted_store_mut<C: Component>(&mut self) -> &mut SparseSet<C> {
@@ -67,34 +145,276 @@ impl World {
         });
 
         return s
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
             .as_any_mut()
             .downcast_mut::<SparseSet<C>>()
             .expect("internal error: component store type mismatch");
     }
 
+    /// Borrows the store of `C` for reading
+    ///
+    /// Panics if the store is currently borrowed mutably, e.g. by a live query
+    pub(crate) fn read_store<C: Component>(&self) -> RwLockReadGuard<'_, Box<dyn ComponentStore>> {
+        return match self.get_store_lock::<C>().try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => panic!(
+                "component {} is already borrowed mutably",
+                std::any::type_name::<C>()
+            ),
+        };
+    }
+
+    /// Borrows the store of `C` for writing
+    ///
+    /// Panics if the store is currently borrowed, e.g. by a live query
+    pub(crate) fn write_store<C: Component>(
+        &self,
+    ) -> RwLockWriteGuard<'_, Box<dyn ComponentStore>> {
+        return match self.get_store_lock::<C>().try_write() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => panic!(
+                "component {} is already borrowed",
+                std::any::type_name::<C>()
+            ),
+        };
+    }
+
     pub fn insert<C: Component>(&mut self, entity: Entity, component: C) -> Option<C> {
+        let tick = self.change_tick();
         let store = self.get_downcasted_store_mut::<C>();
-        return store.insert(entity, component);
+        return store.insert_with_tick(entity, component, tick);
     }
     pub fn remove<C: Component>(&mut self, entity: Entity) -> Option<C> {
+        let tick = self.change_tick();
         let store = self.get_downcasted_store_mut::<C>();
-        return store.remove(entity);
+        let old = store.remove(entity);
+
+        if old.is_some() {
+            self.removed
+                .entry(TypeId::of::<C>())
+                .or_default()
+                .push((entity, tick));
+        }
+
+        return old;
     }
 
-    pub fn get<C: Component>(&self, entity: Entity) -> Option<&C> {
-        let store = self.get_downcasted_store_ref::<C>();
-        return store.get(entity);
+    /// Borrows the component for reading, the store stays locked while the `Ref` lives
+    pub fn get<C: Component>(&self, entity: Entity) -> Option<Ref<'_, C>> {
+        return Ref::new(self.read_store::<C>(), entity);
     }
 
+    /// Gets the component mutably, stamping it as changed
     pub fn get_mut<C: Component>(&mut self, entity: Entity) -> Option<&mut C> {
+        let tick = self.change_tick();
         let store = self.get_downcasted_store_mut::<C>();
+        store.set_changed(entity, tick);
         return store.get_mut(entity);
     }
 
     pub fn has<C: Component>(&self, entity: Entity) -> bool {
-        let store = self.get_downcasted_store_ref::<C>();
+        let store = self.read_store::<C>();
         return store.has(entity);
     }
+
+    /// Iterates over every entity matching `Q`
+    ///
+    /// ```ignore
+    /// let mut q = world.query::<(Entity, &Position, &mut Velocity)>();
+    /// for (entity, position, velocity) in q.iter() {}
+    /// ```
+    pub fn query<Q: WorldQuery>(&self) -> Query<'_, Q> {
+        return Query::new(self);
+    }
+
+    /// Same as [`World::query`] but only yields entities that also pass the filter `F`,
+    /// e.g. `With<Crop>` or `(With<Villager>, Without<Sleeping>)`
+    pub fn query_filtered<Q: WorldQuery, F: QueryFilter>(&self) -> Query<'_, Q, F> {
+        return Query::new(self);
+    }
+
+    /// Inserts a resource, returning the previous value if any
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) -> Option<R> {
+        let old = self
+            .resources
+            .insert(TypeId::of::<R>(), RwLock::new(Box::new(resource)))?;
+
+        let old = old
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .downcast::<R>()
+            .expect("internal error: resource type mismatch");
+
+        return Some(*old);
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+        let old = self.resources.remove(&TypeId::of::<R>())?;
+
+        let old = old
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .downcast::<R>()
+            .expect("internal error: resource type mismatch");
+
+        return Some(*old);
+    }
+
+    pub fn has_resource<R: Resource>(&self) -> bool {
+        return self.resources.contains_key(&TypeId::of::<R>());
+    }
+
+    fn get_resource_lock<R: Resource>(&self) -> &RwLock<BoxedResource> {
+        return self.resources.get(&TypeId::of::<R>()).unwrap_or_else(|| {
+            panic!(
+                "tried to access missing resource: {}",
+                std::any::type_name::<R>()
+            )
+        });
+    }
+
+    /// Borrows a resource for reading
+    ///
+    /// Panics if the resource is missing or currently borrowed mutably
+    pub fn resource<R: Resource>(&self) -> Res<'_, R> {
+        let guard = match self.get_resource_lock::<R>().try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => panic!(
+                "resource {} is already borrowed mutably",
+                std::any::type_name::<R>()
+            ),
+        };
+
+        return Res::new(guard);
+    }
+
+    /// Borrows a resource for writing
+    ///
+    /// Panics if the resource is missing or currently borrowed
+    pub fn resource_mut<R: Resource>(&self) -> ResMut<'_, R> {
+        let guard = match self.get_resource_lock::<R>().try_write() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => panic!(
+                "resource {} is already borrowed",
+                std::any::type_name::<R>()
+            ),
+        };
+
+        return ResMut::new(guard);
+    }
+
+    /// Registers an event type, storing its `Events` channel as a resource
+    pub fn add_event<E: Event>(&mut self) {
+        if self.has_resource::<Events<E>>() {
+            return;
+        }
+
+        self.insert_resource(Events::<E>::new());
+        self.event_updaters.push(update_events::<E>);
+    }
+
+    pub fn send_event<E: Event>(&self, event: E) {
+        self.resource_mut::<Events<E>>().send(event);
+    }
+
+    pub fn event_writer<E: Event>(&self) -> EventWriter<'_, E> {
+        return EventWriter::new(self.resource_mut::<Events<E>>());
+    }
+
+    /// Borrows the events of a type, to be read through an `EventReader`
+    pub fn events<E: Event>(&self) -> Res<'_, Events<E>> {
+        return self.resource::<Events<E>>();
+    }
+
+    /// Swaps the buffers of every registered event type, dropping the events
+    /// that were sent before the previous update
+    pub fn update_events(&mut self) {
+        for update in self.event_updaters.iter() {
+            update(self);
+        }
+    }
+
+    pub fn change_tick(&self) -> u64 {
+        return self.change_tick.load(Ordering::Acquire);
+    }
+
+    /// Advances the change tick, returning the new one
+    pub(crate) fn increment_change_tick(&self) -> u64 {
+        return self.change_tick.fetch_add(1, Ordering::AcqRel) + 1;
+    }
+
+    /// Ticks used by change detection
+    ///
+    /// Inside a system run by the `Scheduler` these are the ticks of that system,
+    /// otherwise changes are tracked since the last `clear_trackers` call
+    pub fn system_ticks(&self) -> SystemTicks {
+        return current_system_ticks().unwrap_or(SystemTicks {
+            last_run: self.last_change_tick,
+            this_run: self.change_tick(),
+        });
+    }
+
+    /// Entities that lost their `C` component since the current system last ran,
+    /// either through `remove` or `despawn`
+    pub fn removed<C: Component>(&self) -> impl Iterator<Item = Entity> + '_ {
+        let last_run = self.system_ticks().last_run;
+
+        return self
+            .removed
+            .get(&TypeId::of::<C>())
+            .into_iter()
+            .flatten()
+            .filter(move |(_, tick)| *tick > last_run)
+            .map(|(entity, _)| *entity);
+    }
+
+    /// Marks the end of a frame for change detection
+    ///
+    /// Removals recorded before the previous call are dropped, so like events they
+    /// stay visible for the frame they happened in and the next one
+    pub fn clear_trackers(&mut self) {
+        let cutoff = self.last_change_tick;
+        for entries in self.removed.values_mut() {
+            entries.retain(|(_, tick)| *tick > cutoff);
+        }
+
+        self.last_change_tick = self.change_tick();
+        self.increment_change_tick();
+    }
+
+    /// Records structural changes that are applied at the next sync point,
+    /// making it possible to spawn or despawn while iterating a query
+    pub fn commands(&self) -> Commands<'_> {
+        return Commands::new(self);
+    }
+
+    pub(crate) fn push_command(&self, command: Command) {
+        self.command_queue
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(command);
+    }
+
+    /// Applies every recorded command in the order they were recorded
+    pub fn apply_commands(&mut self) {
+        // Changes made by the commands happen after every system that recorded them
+        self.increment_change_tick();
+
+        let queue = std::mem::take(
+            self.command_queue
+                .get_mut()
+                .unwrap_or_else(|e| e.into_inner()),
+        );
+
+        for command in queue {
+            command(self);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -108,6 +428,31 @@ mod tests {
     }
     impl Component for TestComponent {}
 
+    #[test]
+    fn test_get_through_shared_world() {
+        let mut world = World::new();
+        world.add_component::<TestComponent>();
+        let e = world.spawn();
+        world.insert(
+            e,
+            TestComponent {
+                value: "shared".to_string(),
+            },
+        );
+
+        let shared: &World = &world;
+        let first = shared.get::<TestComponent>(e).unwrap();
+        let second = shared.get::<TestComponent>(e).unwrap();
+
+        // Reads share the store, writing to it while they live is a conflict
+        assert_eq!(first.value, "shared");
+        assert_eq!(second.value, "shared");
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            drop(shared.write_store::<TestComponent>());
+        }))
+        .is_err());
+    }
+
     #[test]
     fn test_spawn() {
         let mut world = World::new();
@@ -200,8 +545,7 @@ mod tests {
         assert!(!has);
 
         // Ensure that `get` is working properly
-        let val = world.get::<TestComponent>(e);
-        assert!(val.is_none());
+        assert!(world.get::<TestComponent>(e).is_none());
 
         // Ensure that `get_mut` is working properly
         let val = world.get_mut::<TestComponent>(e);
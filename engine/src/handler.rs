@@ -8,35 +8,46 @@ use winit::{
     window::Window,
 };
 
-use crate::internal::Internal;
+use crate::{internal::Internal, setup::Setup};
 
 pub const PIXELS_PER_LINE: f32 = 120.0;
 pub const MAX_SCROLL_LINES: f32 = 3.0;
 
 pub struct Handler {
     internal: Option<Internal>,
+
+    /// Registrations waiting for the window, consumed when `Internal` is created
+    setup: Option<Setup>,
 }
 
 impl Handler {
-    pub fn new() -> Self {
-        return Self { internal: None };
-    }
-
-    pub fn internal(&self) -> &Internal {
-        return self.internal.as_ref().unwrap();
+    pub fn new(setup: Setup) -> Self {
+        return Self {
+            internal: None,
+            setup: Some(setup),
+        };
     }
 
-    pub fn internal_mut(&mut self) -> &mut Internal {
-        return self.internal.as_mut().unwrap();
+    /// Setup of an engine that did not start yet
+    pub fn setup_mut(&mut self) -> &mut Setup {
+        return self
+            .setup
+            .as_mut()
+            .expect("the engine already started, register everything before calling run");
     }
 }
 
 impl ApplicationHandler<Internal> for Handler {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let setup = match self.setup.take() {
+            Some(setup) => setup,
+            None => return,
+        };
+
         let window_attrs = Window::default_attributes();
 
         let window = Arc::new(event_loop.create_window(window_attrs).unwrap());
-        self.internal = Some(pollster::block_on(Internal::new(window)).unwrap());
+        self.internal = Some(pollster::block_on(Internal::new(window, setup)).unwrap());
     }
 
     fn user_event(&mut self, event_loop: &ActiveEventLoop, event: Internal) {
@@ -80,6 +91,14 @@ impl ApplicationHandler<Internal> for Handler {
                     },
                 ..
             } => internal.handle_key(event_loop, code, key_state.is_pressed()),
+            WindowEvent::MouseInput { state, button, .. } => {
+                internal.handle_mouse_button(button, state.is_pressed())
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                internal.handle_cursor(Some(glam::Vec2::new(position.x as f32, position.y as f32)))
+            }
+            WindowEvent::CursorLeft { .. } => internal.handle_cursor(None),
+            WindowEvent::Focused(false) => internal.handle_focus_lost(),
             WindowEvent::MouseWheel { delta, .. } => {
                 let normalized_lines = match delta {
                     winit::event::MouseScrollDelta::LineDelta(_, y) => y,
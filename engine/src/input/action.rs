@@ -0,0 +1,199 @@
+use std::{collections::HashMap, fmt, hash::Hash};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use crate::{ecs::resource::Resource, input::Input};
+
+/// A gameplay action, usually a fieldless enum defined by the game
+pub trait Action: 'static + Send + Sync + Copy + Eq + Hash + DeserializeOwned {}
+
+impl<A> Action for A where A: 'static + Send + Sync + Copy + Eq + Hash + DeserializeOwned {}
+
+/// Physical input an action can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+#[derive(Debug)]
+pub enum ActionMapError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl fmt::Display for ActionMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ActionMapError::Io(e) => write!(f, "could not read action map: {}", e),
+            ActionMapError::Parse(e) => write!(f, "could not parse action map: {}", e),
+        };
+    }
+}
+
+impl std::error::Error for ActionMapError {}
+
+/// Rebindable mapping from actions to keys and mouse buttons
+///
+/// Gameplay systems ask the map whether an action is pressed instead of checking
+/// raw key codes, so the bindings can change without touching them. Loaded from a
+/// RON file of the form `{ Plow: [Key(KeyE), Mouse(Left)] }`
+pub struct ActionMap<A: Action> {
+    bindings: HashMap<A, Vec<Binding>>,
+}
+
+impl<A: Action> Resource for ActionMap<A> {}
+
+impl<A: Action> ActionMap<A> {
+    pub fn new() -> Self {
+        return Self {
+            bindings: HashMap::new(),
+        };
+    }
+
+    pub fn load(path: &str) -> Result<Self, ActionMapError> {
+        let source = std::fs::read_to_string(path).map_err(ActionMapError::Io)?;
+        return Self::from_ron(&source);
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, ActionMapError> {
+        let bindings = ron::from_str(source).map_err(ActionMapError::Parse)?;
+        return Ok(Self { bindings });
+    }
+
+    /// Adds a binding to the action, keeping the existing ones
+    pub fn bind(&mut self, action: A, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Replaces every binding of the action
+    pub fn rebind(&mut self, action: A, bindings: Vec<Binding>) {
+        self.bindings.insert(action, bindings);
+    }
+
+    pub fn unbind(&mut self, action: A, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|b| *b != binding);
+        }
+    }
+
+    pub fn bindings(&self, action: A) -> &[Binding] {
+        return match self.bindings.get(&action) {
+            Some(bindings) => bindings,
+            None => &[],
+        };
+    }
+
+    /// Whether any binding of the action is held
+    pub fn pressed(&self, input: &Input, action: A) -> bool {
+        return self.bindings(action).iter().any(|binding| match binding {
+            Binding::Key(key) => input.keys.pressed(*key),
+            Binding::Mouse(button) => input.mouse_buttons.pressed(*button),
+        });
+    }
+
+    /// Whether the action started this step, holding a second binding does not retrigger it
+    pub fn just_pressed(&self, input: &Input, action: A) -> bool {
+        let bindings = self.bindings(action);
+        let any_just_pressed = bindings.iter().any(|binding| match binding {
+            Binding::Key(key) => input.keys.just_pressed(*key),
+            Binding::Mouse(button) => input.mouse_buttons.just_pressed(*button),
+        });
+        let any_held_before = bindings.iter().any(|binding| match binding {
+            Binding::Key(key) => input.keys.pressed(*key) && !input.keys.just_pressed(*key),
+            Binding::Mouse(button) => {
+                input.mouse_buttons.pressed(*button) && !input.mouse_buttons.just_pressed(*button)
+            }
+        });
+
+        return any_just_pressed && !any_held_before;
+    }
+
+    /// Whether the last held binding of the action was released this step
+    pub fn just_released(&self, input: &Input, action: A) -> bool {
+        let bindings = self.bindings(action);
+        let any_just_released = bindings.iter().any(|binding| match binding {
+            Binding::Key(key) => input.keys.just_released(*key),
+            Binding::Mouse(button) => input.mouse_buttons.just_released(*button),
+        });
+
+        return any_just_released && !self.pressed(input, action);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+    enum GameAction {
+        Plow,
+        Water,
+    }
+
+    #[test]
+    fn test_from_ron() {
+        let map =
+            ActionMap::<GameAction>::from_ron("{ Plow: [Key(KeyE), Mouse(Left)], Water: [] }")
+                .unwrap();
+
+        assert_eq!(
+            map.bindings(GameAction::Plow),
+            &[
+                Binding::Key(KeyCode::KeyE),
+                Binding::Mouse(MouseButton::Left)
+            ]
+        );
+        assert!(map.bindings(GameAction::Water).is_empty());
+
+        assert!(ActionMap::<GameAction>::from_ron("{ Dig: [] }").is_err());
+    }
+
+    #[test]
+    fn test_action_state() {
+        let mut map = ActionMap::new();
+        map.bind(GameAction::Plow, Binding::Key(KeyCode::KeyE));
+        map.bind(GameAction::Plow, Binding::Mouse(MouseButton::Left));
+
+        let mut input = Input::new();
+        input.keys.press(KeyCode::KeyE);
+
+        assert!(map.pressed(&input, GameAction::Plow));
+        assert!(map.just_pressed(&input, GameAction::Plow));
+        assert!(!map.pressed(&input, GameAction::Water));
+
+        input.clear_just();
+        input.mouse_buttons.press(MouseButton::Left);
+        assert!(map.pressed(&input, GameAction::Plow));
+        assert!(!map.just_pressed(&input, GameAction::Plow));
+
+        input.clear_just();
+        input.keys.release(KeyCode::KeyE);
+        assert!(!map.just_released(&input, GameAction::Plow));
+
+        input.clear_just();
+        input.mouse_buttons.release(MouseButton::Left);
+        assert!(map.just_released(&input, GameAction::Plow));
+    }
+
+    #[test]
+    fn test_rebind() {
+        let mut map = ActionMap::new();
+        map.bind(GameAction::Water, Binding::Key(KeyCode::KeyQ));
+        map.rebind(GameAction::Water, vec![Binding::Key(KeyCode::KeyR)]);
+
+        let mut input = Input::new();
+        input.keys.press(KeyCode::KeyQ);
+        assert!(!map.pressed(&input, GameAction::Water));
+
+        input.keys.press(KeyCode::KeyR);
+        assert!(map.pressed(&input, GameAction::Water));
+
+        map.unbind(GameAction::Water, Binding::Key(KeyCode::KeyR));
+        assert!(!map.pressed(&input, GameAction::Water));
+    }
+}
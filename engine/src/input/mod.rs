@@ -0,0 +1,168 @@
+use std::{collections::HashSet, hash::Hash};
+
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use crate::ecs::resource::Resource;
+
+pub mod action;
+
+/// Held, just pressed and just released state of a set of buttons
+pub struct ButtonInput<T> {
+    pressed: HashSet<T>,
+    just_pressed: HashSet<T>,
+    just_released: HashSet<T>,
+}
+
+impl<T> ButtonInput<T>
+where
+    T: Copy + Eq + Hash,
+{
+    pub fn new() -> Self {
+        return Self {
+            pressed: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+        };
+    }
+
+    pub fn press(&mut self, button: T) {
+        // Key repeat sends press events for a button already held
+        if self.pressed.insert(button) {
+            self.just_pressed.insert(button);
+        }
+    }
+
+    pub fn release(&mut self, button: T) {
+        if self.pressed.remove(&button) {
+            self.just_released.insert(button);
+        }
+    }
+
+    /// Releases every held button, used when the window loses focus
+    pub fn release_all(&mut self) {
+        self.just_released.extend(self.pressed.drain());
+    }
+
+    pub fn pressed(&self, button: T) -> bool {
+        return self.pressed.contains(&button);
+    }
+
+    pub fn just_pressed(&self, button: T) -> bool {
+        return self.just_pressed.contains(&button);
+    }
+
+    pub fn just_released(&self, button: T) -> bool {
+        return self.just_released.contains(&button);
+    }
+
+    pub fn clear_just(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+/// Keyboard, mouse and cursor state, inserted as a resource by the engine
+///
+/// The `just_*` states and the wheel delta are cleared after each simulation step,
+/// when a frame runs no step they are kept until the next one so no press is lost
+pub struct Input {
+    pub keys: ButtonInput<KeyCode>,
+    pub mouse_buttons: ButtonInput<MouseButton>,
+
+    cursor_screen: Option<glam::Vec2>,
+    cursor_world: Option<glam::Vec2>,
+    wheel_delta: f32,
+}
+
+impl Resource for Input {}
+
+impl Input {
+    pub fn new() -> Self {
+        return Self {
+            keys: ButtonInput::new(),
+            mouse_buttons: ButtonInput::new(),
+            cursor_screen: None,
+            cursor_world: None,
+            wheel_delta: 0.0,
+        };
+    }
+
+    /// Cursor position in pixels from the top left corner of the window,
+    /// `None` when the cursor is outside of the window
+    pub fn cursor_screen(&self) -> Option<glam::Vec2> {
+        return self.cursor_screen;
+    }
+
+    /// Cursor position in world space, as seen through the camera
+    pub fn cursor_world(&self) -> Option<glam::Vec2> {
+        return self.cursor_world;
+    }
+
+    /// Wheel movement since the last simulation step, in lines
+    pub fn wheel_delta(&self) -> f32 {
+        return self.wheel_delta;
+    }
+
+    pub(crate) fn set_cursor(&mut self, screen: Option<glam::Vec2>) {
+        self.cursor_screen = screen;
+    }
+
+    pub(crate) fn set_cursor_world(&mut self, world: Option<glam::Vec2>) {
+        self.cursor_world = world;
+    }
+
+    pub(crate) fn scroll(&mut self, delta: f32) {
+        self.wheel_delta += delta;
+    }
+
+    pub(crate) fn release_all(&mut self) {
+        self.keys.release_all();
+        self.mouse_buttons.release_all();
+    }
+
+    pub(crate) fn clear_just(&mut self) {
+        self.keys.clear_just();
+        self.mouse_buttons.clear_just();
+        self.wheel_delta = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_button_input() {
+        let mut keys = ButtonInput::new();
+
+        keys.press(KeyCode::KeyE);
+        assert!(keys.pressed(KeyCode::KeyE));
+        assert!(keys.just_pressed(KeyCode::KeyE));
+
+        keys.clear_just();
+        keys.press(KeyCode::KeyE);
+        assert!(keys.pressed(KeyCode::KeyE));
+        assert!(!keys.just_pressed(KeyCode::KeyE));
+
+        keys.release(KeyCode::KeyE);
+        assert!(!keys.pressed(KeyCode::KeyE));
+        assert!(keys.just_released(KeyCode::KeyE));
+
+        keys.clear_just();
+        assert!(!keys.just_released(KeyCode::KeyE));
+    }
+
+    #[test]
+    fn test_release_all() {
+        let mut input = Input::new();
+        input.keys.press(KeyCode::KeyW);
+        input.mouse_buttons.press(MouseButton::Left);
+        input.clear_just();
+
+        input.release_all();
+
+        assert!(!input.keys.pressed(KeyCode::KeyW));
+        assert!(input.keys.just_released(KeyCode::KeyW));
+        assert!(input.mouse_buttons.just_released(MouseButton::Left));
+    }
+}
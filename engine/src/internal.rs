@@ -1,15 +1,28 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
-use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
+use winit::{event::MouseButton, event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
 
 use crate::{
-    assets::{texture::Texture, AssetId, AssetsRegistry},
-    ecs::{scheduler::System, ECS},
+    assets::{
+        error::{read_image, AssetError},
+        texture::Texture,
+        AssetId, AssetsRegistry,
+    },
+    ecs::ECS,
+    input::Input,
     render::{
         self,
+        capture::{self, FrameCapture},
+        debug::DebugDraw,
         renderer::{Renderer2D, Renderer2DConfig},
-        texture::GpuTextureManager,
+        sprite::SpriteBatch,
+        text::{TextBatch, TextRenderer},
+        texture::{missing_texture_image, GpuTextureManager},
     },
+    setup::Setup,
+    tilemap::TileMap,
+    time::{FixedTimestep, Time},
+    ui::{self, Ui},
 };
 
 pub struct Internal {
@@ -20,13 +33,20 @@ pub struct Internal {
     texture_manager: render::texture::GpuTextureManager,
     assets_registry: AssetsRegistry,
     renderer: Renderer2D,
+    text_renderer: TextRenderer,
+
+    /// 1x1 white texture, tinted to draw solid UI backgrounds
+    white_texture: AssetId,
     camera: crate::camera::Camera2D,
 
     ecs: ECS,
+
+    timestep: FixedTimestep,
+    last_frame: Instant,
 }
 
 impl Internal {
-    pub async fn new(window: Arc<Window>) -> Result<Self, ()> {
+    pub async fn new(window: Arc<Window>, setup: Setup) -> Result<Self, ()> {
         let size = window.inner_size();
         let camera = crate::camera::Camera2D::new(crate::camera::Camera2DConfig {
             position: glam::Vec2::new(0.0, 0.0),
@@ -36,8 +56,24 @@ impl Internal {
 
         let renderer = Renderer2D::new(window.clone(), Renderer2DConfig { camera: &camera }).await;
 
-        return Ok(Self {
-            assets_registry: AssetsRegistry::new(),
+        let timestep = FixedTimestep::new(setup.config.timestep);
+
+        let mut ecs = setup.ecs;
+        ecs.insert_resource(Time::new(timestep.step()));
+        ecs.insert_resource(Input::new());
+        ecs.insert_resource(SpriteBatch::new());
+        ecs.insert_resource(TextBatch::new());
+        ecs.insert_resource(DebugDraw::new());
+        ecs.insert_resource(Ui::new());
+        ecs.insert_resource(FrameCapture::new());
+
+        let mut assets_registry = setup.assets_registry;
+        let glyph_atlas = assets_registry.insert_texture(Texture::new());
+        let white_texture = assets_registry.insert_texture(Texture::new());
+        let missing_texture = assets_registry.insert_texture(Texture::new());
+
+        let mut internal = Self {
+            assets_registry,
             texture_manager: GpuTextureManager::new(),
 
             window,
@@ -45,10 +81,49 @@ impl Internal {
             is_surface_configured: false,
 
             renderer,
+            text_renderer: TextRenderer::new(glyph_atlas),
+            white_texture,
             camera,
 
-            ecs: ECS::new(),
-        });
+            ecs,
+
+            timestep,
+            last_frame: Instant::now(),
+        };
+
+        let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        internal.texture_manager.load(
+            white_texture,
+            internal.renderer.device(),
+            internal.renderer.queue(),
+            internal.renderer.texture_bind_group_layout(),
+            &white,
+        );
+        internal.texture_manager.load_placeholder(
+            missing_texture,
+            internal.renderer.device(),
+            internal.renderer.queue(),
+            internal.renderer.texture_bind_group_layout(),
+        );
+
+        // The ids are already handed out, so failures are drawn with the placeholder
+        for (id, path) in setup.pending_textures {
+            if let Err(e) = internal.upload_texture(id, &path) {
+                eprintln!("{}, drawing the missing texture instead", e);
+                internal.upload_image(id, &missing_texture_image());
+            }
+        }
+
+        for (id, image) in setup.pending_images {
+            internal.upload_image(id, &image);
+        }
+
+        internal.ecs.run_startup();
+
+        // Startup work must not count as simulated time
+        internal.last_frame = Instant::now();
+
+        return Ok(internal);
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
@@ -60,47 +135,197 @@ impl Internal {
         }
     }
 
-    pub fn update(&mut self) {}
+    /// Runs as many fixed simulation steps as the elapsed time requires,
+    /// then extracts the state to render with the interpolation alpha
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let frame_delta = now - self.last_frame;
+        self.last_frame = now;
+
+        self.update_cursor_world();
+
+        let steps = self.timestep.advance(frame_delta);
+        let step = self.timestep.step();
+
+        for _ in 0..steps {
+            self.ecs.world().resource_mut::<Time>().advance_step();
+            self.ecs.run_simulation_step(step);
+            self.ecs.world().resource_mut::<Input>().clear_just();
+        }
+
+        self.ecs
+            .world()
+            .resource_mut::<Time>()
+            .set_frame(frame_delta, self.timestep.alpha());
+        self.ecs.run_render_extract(frame_delta);
+        self.ecs.world().resource_mut::<Ui>().end_frame();
+
+        for id in self.assets_registry.collect_unused() {
+            self.texture_manager.remove(id);
+        }
+    }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         self.window.request_redraw();
-        return self.renderer.render(&self.camera);
+        let world = self.ecs.world();
+        let mut sprites = world.resource_mut::<SpriteBatch>().take();
+        let mut texts = world.resource_mut::<TextBatch>().take();
+        let mut debug = world.resource_mut::<DebugDraw>();
+        texts.append(&mut debug.take_labels());
+
+        let mut ui = world.resource_mut::<Ui>();
+        let roots = ui.take_roots();
+        let frame = ui::layout::build(
+            &roots,
+            &ui,
+            self.renderer.viewport_size().as_vec2(),
+            &self.assets_registry,
+            self.white_texture,
+        );
+        sprites.extend(frame.sprites);
+        texts.extend(frame.texts);
+        ui.set_layout(frame.regions, frame.blocking);
+
+        self.text_renderer.prepare(
+            &texts,
+            &self.assets_registry,
+            &mut self.texture_manager,
+            &self.renderer,
+            &mut sprites,
+        );
+
+        let mut tile_map = match world.has_resource::<TileMap>() {
+            true => Some(world.resource_mut::<TileMap>()),
+            false => None,
+        };
+
+        let frame_delta = world.resource::<Time>().frame_delta();
+        let capture_paths = world
+            .resource_mut::<FrameCapture>()
+            .frame_paths(frame_delta);
+        if !capture_paths.is_empty() {
+            match self.renderer.can_capture() {
+                true => self.renderer.request_capture(),
+                false => eprintln!("frame capture is not supported by this surface"),
+            }
+        }
+
+        let shapes = match debug.enabled {
+            true => Some(&*debug),
+            false => None,
+        };
+
+        let result = self.renderer.render(
+            &self.camera,
+            &self.texture_manager,
+            tile_map.as_deref_mut(),
+            &mut sprites,
+            shapes,
+        );
+        debug.clear();
+
+        if let Some(image) = self.renderer.take_capture() {
+            capture::save_in_background(image, capture_paths);
+        }
+
+        return result;
     }
 
     pub fn handle_key(&self, event_loop: &ActiveEventLoop, code: KeyCode, is_pressed: bool) {
+        let mut input = self.ecs.world().resource_mut::<Input>();
+
+        // Key repeat sends presses for a key already held, those must not toggle again
+        if is_pressed && !input.keys.pressed(code) {
+            let mut debug = self.ecs.world().resource_mut::<DebugDraw>();
+            if code == debug.toggle_key {
+                debug.toggle();
+            }
+
+            let mut capture = self.ecs.world().resource_mut::<FrameCapture>();
+            if code == capture.key {
+                capture.screenshot();
+            }
+        }
+
+        if is_pressed {
+            input.keys.press(code);
+        } else {
+            input.keys.release(code);
+        }
+
         match (code, is_pressed) {
             (KeyCode::Escape, true) => event_loop.exit(),
             _ => {}
         }
     }
 
+    /// Presses over the UI are consumed, releases always reach `Input` so no button
+    /// stays held
+    pub fn handle_mouse_button(&self, button: MouseButton, is_pressed: bool) {
+        let mut ui = self.ecs.world().resource_mut::<Ui>();
+        let mut input = self.ecs.world().resource_mut::<Input>();
+
+        match (button, is_pressed) {
+            (MouseButton::Left, true) if ui.pointer_pressed() => {}
+            (_, true) if ui.wants_pointer() => {}
+            (_, true) => input.mouse_buttons.press(button),
+            (MouseButton::Left, false) => {
+                ui.pointer_released();
+                input.mouse_buttons.release(button);
+            }
+            (_, false) => input.mouse_buttons.release(button),
+        }
+    }
+
+    pub fn handle_cursor(&self, position: Option<glam::Vec2>) {
+        self.ecs
+            .world()
+            .resource_mut::<Ui>()
+            .pointer_moved(position);
+        self.ecs
+            .world()
+            .resource_mut::<Input>()
+            .set_cursor(position);
+    }
+
+    /// Releases everything, the release events are not delivered to an unfocused window
+    pub fn handle_focus_lost(&self) {
+        self.ecs.world().resource_mut::<Input>().release_all();
+    }
+
     pub fn handle_wheel(&mut self, delta: f32) {
+        if self.ecs.world().resource::<Ui>().wants_pointer() {
+            return;
+        }
+
         self.camera.zoom_by(delta);
+        self.ecs.world().resource_mut::<Input>().scroll(delta);
     }
 
-    pub fn load_texture(&mut self, path: &str) -> AssetId {
-        let buffer = image::io::Reader::open(path)
-            .expect("Could not load texture image")
-            .decode()
-            .expect("Could not decode texture image")
-            .into_rgba8();
-        let texture = Texture::new();
-
-        let id = self.assets_registry.insert_texture(texture);
-        self.texture_manager
-            .load(id, &self.renderer.device(), &self.renderer.queue(), &buffer);
+    /// The camera can move without the cursor moving, so this runs every frame
+    fn update_cursor_world(&self) {
+        let mut input = self.ecs.world().resource_mut::<Input>();
+        let world = input
+            .cursor_screen()
+            .map(|screen| self.camera.screen_to_world(screen));
 
-        return id;
+        input.set_cursor_world(world);
     }
 
-    pub fn add_system<S>(&mut self, system: S)
-    where
-        S: System + 'static,
-    {
-        self.ecs.add_system(system);
+    fn upload_texture(&mut self, id: AssetId, path: &str) -> Result<(), AssetError> {
+        let buffer = read_image(path)?;
+        self.upload_image(id, &buffer);
+
+        return Ok(());
     }
 
-    pub fn run_systems(&mut self, dt: std::time::Duration) {
-        self.ecs.run_systems(dt);
+    fn upload_image(&mut self, id: AssetId, image: &image::RgbaImage) {
+        self.texture_manager.load(
+            id,
+            self.renderer.device(),
+            self.renderer.queue(),
+            self.renderer.texture_bind_group_layout(),
+            image,
+        );
     }
 }
@@ -1,21 +1,45 @@
 use winit::event_loop::EventLoop;
 
-use crate::{ecs::scheduler::System, handler::Handler};
+use crate::{
+    assets::{
+        aseprite::{AsepriteAsset, AsepriteError},
+        atlas::{Atlas, AtlasError},
+        error::AssetError,
+        font::BitmapFontConfig,
+        packer::{AtlasPacker, PackError, PackedSprites},
+        texture::Texture,
+        AssetId, Handle,
+    },
+    ecs::{
+        component::Component,
+        event::Event,
+        resource::Resource,
+        scheduler::{ScheduleError, System, SystemConfig},
+    },
+    handler::Handler,
+    setup::Setup,
+    time::TimestepConfig,
+};
 
-mod assets;
+pub mod assets;
 mod camera;
 pub mod ecs;
 mod handler;
+pub mod input;
 mod internal;
-mod math;
-mod render;
-mod tilemap;
+pub mod math;
+pub mod render;
+mod setup;
+pub mod tilemap;
+pub mod time;
+pub mod ui;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct Vertex {
     position: [f32; 3],
     tex_coords: [f32; 2],
+    color: [f32; 4],
 }
 
 impl Vertex {
@@ -34,55 +58,118 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         };
     }
 }
-const VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [-64.0, -64.0, 0.0],
-        tex_coords: [0.0, 1.0],
-    },
-    Vertex {
-        position: [64.0, -64.0, 0.0],
-        tex_coords: [1.0, 1.0],
-    },
-    Vertex {
-        position: [64.0, 64.0, 0.0],
-        tex_coords: [1.0, 0.0],
-    },
-    Vertex {
-        position: [-64.0, 64.0, 0.0],
-        tex_coords: [0.0, 0.0],
-    },
-];
 
-const INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
+#[derive(Default)]
+pub struct EngineConfig {
+    pub timestep: TimestepConfig,
+}
 
+/// Entry point of the engine
+///
+/// Systems, resources, events and textures are registered before calling `run`,
+/// they are applied once the window and the GPU are ready
 pub struct Engine {
     handler: Handler,
 }
 
 impl Engine {
     pub fn new() -> Self {
+        return Self::with_config(EngineConfig::default());
+    }
+
+    pub fn with_config(config: EngineConfig) -> Self {
         return Self {
-            handler: Handler::new(),
+            handler: Handler::new(Setup::new(config)),
         };
     }
 
-    pub fn run(&mut self) {
+    /// Opens the window and runs the game until it is closed
+    ///
+    /// Fails before opening the window when the systems cannot be ordered
+    pub fn run(&mut self) -> Result<(), ScheduleError> {
+        self.handler.setup_mut().ecs.build()?;
+
         let event_loop = EventLoop::with_user_event().build().unwrap();
         event_loop.run_app(&mut self.handler).unwrap();
+
+        return Ok(());
+    }
+
+    /// Queues a texture to be uploaded once the GPU is ready, a file that fails to
+    /// load is logged and drawn as a magenta checkerboard
+    ///
+    /// The texture is unloaded once the returned handle and its clones are dropped,
+    /// keep it alive for as long as the texture is drawn
+    pub fn load_texture(&mut self, path: &str) -> Handle<Texture> {
+        return self.handler.setup_mut().load_texture(path);
+    }
+
+    /// Loads an atlas description, see `assets::atlas::AtlasFile` for the format
+    pub fn load_atlas(&mut self, path: &str) -> Result<Atlas, AtlasError> {
+        return self.handler.setup_mut().load_atlas(path);
     }
 
-    pub fn load_texture(&mut self, path: &str) {
-        self.handler.internal_mut().load_texture(path);
+    /// Packs individual images into atlas pages, see `assets::packer::AtlasPacker`
+    pub fn pack_atlas(&mut self, packer: &AtlasPacker) -> Result<PackedSprites, PackError> {
+        return self.handler.setup_mut().pack_atlas(packer);
+    }
+
+    /// Loads the frames, tags and slices of an `.aseprite` file as sprites and
+    /// animation clips in the assets registry
+    pub fn load_aseprite(&mut self, path: &str) -> Result<AsepriteAsset, AsepriteError> {
+        return self.handler.setup_mut().load_aseprite(path);
+    }
+
+    /// Loads a TTF or OTF font
+    pub fn load_font(&mut self, path: &str) -> Result<AssetId, AssetError> {
+        return self.handler.setup_mut().load_font(path);
+    }
+
+    /// Loads a font drawn from a sheet of fixed size glyphs
+    pub fn load_bitmap_font(
+        &mut self,
+        path: &str,
+        config: BitmapFontConfig,
+    ) -> Result<AssetId, AssetError> {
+        return self.handler.setup_mut().load_bitmap_font(path, config);
     }
 
     pub fn add_system<S>(&mut self, system: S)
     where
         S: System + 'static,
     {
-        self.handler.internal_mut().add_system(system);
+        self.handler.setup_mut().ecs.add_system(system);
+    }
+
+    pub fn add_system_with_config<S>(&mut self, system: S, config: SystemConfig)
+    where
+        S: System + 'static,
+    {
+        self.handler
+            .setup_mut()
+            .ecs
+            .add_system_with_config(system, config);
+    }
+
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) {
+        self.handler.setup_mut().ecs.insert_resource(resource);
+    }
+
+    /// Registers the store of a component type, entities can then be given one
+    pub fn add_component<C: Component>(&mut self) {
+        self.handler.setup_mut().ecs.add_component::<C>();
+    }
+
+    pub fn add_event<E: Event>(&mut self) {
+        self.handler.setup_mut().ecs.add_event::<E>();
     }
 }
@@ -0,0 +1,189 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use winit::keyboard::KeyCode;
+
+use crate::ecs::resource::Resource;
+
+/// Numbered frames written to their own directory at a fixed interval
+struct Sequence {
+    directory: PathBuf,
+    interval: Duration,
+    since_last: Duration,
+    next_index: u32,
+}
+
+/// Saves frames to PNG files, inserted as a resource by the engine
+///
+/// `screenshot` saves the next frame, `key` does the same at runtime. A sequence
+/// saves a frame every `interval` until stopped, e.g. for a timelapse. The frames
+/// are read back from the GPU after rendering and written on a background thread
+pub struct FrameCapture {
+    pub key: KeyCode,
+
+    /// Where screenshots and sequence directories are created
+    pub directory: PathBuf,
+
+    screenshot_requested: bool,
+    sequence: Option<Sequence>,
+}
+
+impl Resource for FrameCapture {}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        return Self {
+            key: KeyCode::F12,
+            directory: PathBuf::from("screenshots"),
+            screenshot_requested: false,
+            sequence: None,
+        };
+    }
+
+    /// Saves the next rendered frame as `screenshot_<unix millis>.png`
+    pub fn screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    /// Saves the next frame, then one every `interval`, as `frame_00000.png`,
+    /// `frame_00001.png`... in a new `sequence_<unix millis>` directory
+    pub fn start_sequence(&mut self, interval: Duration) {
+        self.sequence = Some(Sequence {
+            directory: self.directory.join(format!("sequence_{}", unix_millis())),
+            interval,
+            since_last: interval,
+            next_index: 0,
+        });
+    }
+
+    pub fn stop_sequence(&mut self) {
+        self.sequence = None;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        return self.sequence.is_some();
+    }
+
+    /// Files the frame about to be rendered must be saved to, advancing the sequence
+    pub(crate) fn frame_paths(&mut self, frame_delta: Duration) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            paths.push(
+                self.directory
+                    .join(format!("screenshot_{}.png", unix_millis())),
+            );
+        }
+
+        if let Some(sequence) = &mut self.sequence {
+            sequence.since_last += frame_delta;
+
+            if sequence.since_last >= sequence.interval {
+                sequence.since_last = Duration::ZERO;
+                paths.push(
+                    sequence
+                        .directory
+                        .join(format!("frame_{:05}.png", sequence.next_index)),
+                );
+                sequence.next_index += 1;
+            }
+        }
+
+        return paths;
+    }
+}
+
+/// Writes the image to every path on a background thread, so encoding never stalls
+/// a frame
+pub(crate) fn save_in_background(image: image::RgbaImage, paths: Vec<PathBuf>) {
+    std::thread::spawn(move || {
+        for path in paths {
+            if let Err(e) = save(&image, &path) {
+                eprintln!("could not save frame {}: {}", path.display(), e);
+            }
+        }
+    });
+}
+
+fn save(image: &image::RgbaImage, path: &Path) -> image::ImageResult<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(image::ImageError::IoError)?;
+    }
+
+    return image.save(path);
+}
+
+fn unix_millis() -> u128 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    #[test]
+    fn test_screenshot_is_taken_once() {
+        let mut capture = FrameCapture::new();
+        assert!(capture.frame_paths(FRAME).is_empty());
+
+        capture.screenshot();
+        let paths = capture.frame_paths(FRAME);
+
+        assert_eq!(paths.len(), 1);
+        assert!(paths[0].starts_with("screenshots"));
+        assert!(capture.frame_paths(FRAME).is_empty());
+    }
+
+    #[test]
+    fn test_sequence_interval() {
+        let mut capture = FrameCapture::new();
+        capture.start_sequence(Duration::from_millis(40));
+
+        let names: Vec<Option<String>> = (0..6)
+            .map(|_| {
+                capture
+                    .frame_paths(FRAME)
+                    .first()
+                    .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            })
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                Some("frame_00000.png".to_string()),
+                None,
+                None,
+                Some("frame_00001.png".to_string()),
+                None,
+                None,
+            ]
+        );
+
+        capture.stop_sequence();
+        assert!(!capture.is_recording());
+        assert!(capture.frame_paths(Duration::from_secs(1)).is_empty());
+    }
+
+    #[test]
+    fn test_save_creates_directories() {
+        let dir = std::env::temp_dir().join(format!("engine_capture_{}", unix_millis()));
+        let path = dir.join("nested").join("frame.png");
+        let image = image::RgbaImage::from_pixel(2, 2, image::Rgba([10, 20, 30, 255]));
+
+        save(&image, &path).unwrap();
+
+        let loaded = image::open(&path).unwrap().into_rgba8();
+        assert_eq!(loaded, image);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
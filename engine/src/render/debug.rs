@@ -0,0 +1,291 @@
+use winit::keyboard::KeyCode;
+
+use crate::{assets::AssetId, ecs::resource::Resource, render::text::TextDraw};
+
+/// Segments used to approximate a circle
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Height of the debug labels, in world units
+const LABEL_SIZE: f32 = 12.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct DebugVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl DebugVertex {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        return wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        };
+    }
+}
+
+/// Immediate mode shapes drawn in world space over the scene, inserted as a resource
+/// by the engine
+///
+/// Shapes are submitted every frame from any stage and cleared once drawn. While
+/// disabled, submitting does nothing. `toggle_key` enables and disables it at runtime
+pub struct DebugDraw {
+    pub enabled: bool,
+    pub toggle_key: KeyCode,
+
+    /// Font of the labels, labels are dropped when `None`
+    pub font: Option<AssetId>,
+
+    /// Pairs of vertices, drawn as 1 pixel lines
+    lines: Vec<DebugVertex>,
+
+    /// Triples of vertices, drawn as filled triangles below the lines
+    triangles: Vec<DebugVertex>,
+    labels: Vec<TextDraw>,
+}
+
+impl Resource for DebugDraw {}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        return Self {
+            enabled: false,
+            toggle_key: KeyCode::F3,
+            font: None,
+            lines: Vec::new(),
+            triangles: Vec::new(),
+            labels: Vec::new(),
+        };
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.clear();
+    }
+
+    pub fn line(&mut self, from: glam::Vec2, to: glam::Vec2, color: [f32; 4]) {
+        if !self.enabled {
+            return;
+        }
+
+        self.lines.push(vertex(from, color));
+        self.lines.push(vertex(to, color));
+    }
+
+    /// Connected segments through the points, e.g. a path
+    pub fn polyline(&mut self, points: &[glam::Vec2], color: [f32; 4]) {
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], color);
+        }
+    }
+
+    /// Outline of the rectangle between two opposite corners
+    pub fn rect(&mut self, min: glam::Vec2, max: glam::Vec2, color: [f32; 4]) {
+        let corners = [
+            min,
+            glam::Vec2::new(max.x, min.y),
+            max,
+            glam::Vec2::new(min.x, max.y),
+        ];
+
+        for i in 0..corners.len() {
+            self.line(corners[i], corners[(i + 1) % corners.len()], color);
+        }
+    }
+
+    pub fn fill_rect(&mut self, min: glam::Vec2, max: glam::Vec2, color: [f32; 4]) {
+        if !self.enabled {
+            return;
+        }
+
+        let (min, max) = (min.min(max), min.max(max));
+        let corners = [
+            min,
+            glam::Vec2::new(max.x, min.y),
+            max,
+            glam::Vec2::new(min.x, max.y),
+        ];
+
+        for i in [0, 1, 2, 0, 2, 3] {
+            self.triangles.push(vertex(corners[i], color));
+        }
+    }
+
+    pub fn circle(&mut self, center: glam::Vec2, radius: f32, color: [f32; 4]) {
+        let points: Vec<glam::Vec2> = (0..=CIRCLE_SEGMENTS)
+            .map(|i| center + radius * circle_point(i))
+            .collect();
+
+        self.polyline(&points, color);
+    }
+
+    pub fn fill_circle(&mut self, center: glam::Vec2, radius: f32, color: [f32; 4]) {
+        if !self.enabled {
+            return;
+        }
+
+        for i in 0..CIRCLE_SEGMENTS {
+            self.triangles.push(vertex(center, color));
+            self.triangles
+                .push(vertex(center + radius * circle_point(i), color));
+            self.triangles
+                .push(vertex(center + radius * circle_point(i + 1), color));
+        }
+    }
+
+    /// Lines between `cells` cells of `cell_size`, starting at `origin`
+    pub fn grid(
+        &mut self,
+        origin: glam::Vec2,
+        cell_size: glam::Vec2,
+        cells: glam::UVec2,
+        color: [f32; 4],
+    ) {
+        let max = origin + cell_size * cells.as_vec2();
+
+        for x in 0..=cells.x {
+            let x = origin.x + x as f32 * cell_size.x;
+            self.line(
+                glam::Vec2::new(x, origin.y),
+                glam::Vec2::new(x, max.y),
+                color,
+            );
+        }
+
+        for y in 0..=cells.y {
+            let y = origin.y + y as f32 * cell_size.y;
+            self.line(
+                glam::Vec2::new(origin.x, y),
+                glam::Vec2::new(max.x, y),
+                color,
+            );
+        }
+    }
+
+    /// Text with its top-left corner at `position`, needs `font` to be set
+    pub fn label(&mut self, position: glam::Vec2, text: &str, color: [f32; 4]) {
+        let font = match (self.enabled, self.font) {
+            (true, Some(font)) => font,
+            _ => return,
+        };
+
+        self.labels
+            .push(TextDraw::new(text, font, LABEL_SIZE, position).with_color(color));
+    }
+
+    pub(crate) fn lines(&self) -> &[DebugVertex] {
+        return &self.lines;
+    }
+
+    pub(crate) fn triangles(&self) -> &[DebugVertex] {
+        return &self.triangles;
+    }
+
+    pub(crate) fn take_labels(&mut self) -> Vec<TextDraw> {
+        return std::mem::take(&mut self.labels);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.lines.clear();
+        self.triangles.clear();
+        self.labels.clear();
+    }
+}
+
+fn vertex(position: glam::Vec2, color: [f32; 4]) -> DebugVertex {
+    return DebugVertex {
+        position: position.to_array(),
+        color,
+    };
+}
+
+/// Point `i` of the unit circle, counter-clockwise from the right
+fn circle_point(i: usize) -> glam::Vec2 {
+    let angle = std::f32::consts::TAU * i as f32 / CIRCLE_SEGMENTS as f32;
+    return glam::Vec2::new(angle.cos(), angle.sin());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+
+    fn enabled() -> DebugDraw {
+        let mut debug = DebugDraw::new();
+        debug.enabled = true;
+        return debug;
+    }
+
+    #[test]
+    fn test_disabled_draws_nothing() {
+        let mut debug = DebugDraw::new();
+        debug.font = Some(AssetId::new(0));
+
+        debug.line(glam::Vec2::ZERO, glam::Vec2::ONE, RED);
+        debug.fill_rect(glam::Vec2::ZERO, glam::Vec2::ONE, RED);
+        debug.label(glam::Vec2::ZERO, "hidden", RED);
+
+        assert!(debug.lines().is_empty());
+        assert!(debug.triangles().is_empty());
+        assert!(debug.take_labels().is_empty());
+    }
+
+    #[test]
+    fn test_shapes() {
+        let mut debug = enabled();
+
+        debug.rect(glam::Vec2::ZERO, glam::Vec2::new(2.0, 1.0), RED);
+        assert_eq!(debug.lines().len(), 8);
+        assert_eq!(debug.lines()[2].position, [2.0, 0.0]);
+        assert_eq!(debug.lines()[3].position, [2.0, 1.0]);
+
+        debug.fill_rect(glam::Vec2::new(2.0, 1.0), glam::Vec2::ZERO, RED);
+        assert_eq!(debug.triangles().len(), 6);
+        assert_eq!(debug.triangles()[0].position, [0.0, 0.0]);
+
+        debug.fill_circle(glam::Vec2::ZERO, 1.0, RED);
+        assert_eq!(debug.triangles().len(), 6 + 3 * CIRCLE_SEGMENTS);
+    }
+
+    #[test]
+    fn test_grid() {
+        let mut debug = enabled();
+        debug.grid(
+            glam::Vec2::ZERO,
+            glam::Vec2::splat(16.0),
+            glam::UVec2::new(2, 3),
+            RED,
+        );
+
+        // 3 vertical and 4 horizontal lines
+        assert_eq!(debug.lines().len(), 2 * 7);
+        assert_eq!(debug.lines()[5].position, [32.0, 48.0]);
+    }
+
+    #[test]
+    fn test_toggle_clears() {
+        let mut debug = enabled();
+        debug.font = Some(AssetId::new(0));
+        debug.circle(glam::Vec2::ZERO, 4.0, RED);
+        debug.label(glam::Vec2::ZERO, "label", RED);
+
+        debug.toggle();
+
+        assert!(!debug.enabled);
+        assert!(debug.lines().is_empty());
+        assert!(debug.take_labels().is_empty());
+    }
+}
@@ -0,0 +1,356 @@
+//! Golden-image tests: scripted scenes rendered headless and compared to reference PNGs
+//!
+//! References live in `tests/golden`. Run the tests with `GOLDEN_BLESS=1` to write
+//! new references after an intended rendering change. When a comparison fails, the
+//! actual image and a diff highlighting the mismatched pixels in red are written
+//! to `tests/golden/failures`
+
+use std::path::PathBuf;
+
+use crate::{
+    assets::AssetId,
+    camera::{Camera2D, Camera2DConfig},
+    render::{
+        debug::DebugDraw,
+        renderer::{Renderer2D, Renderer2DConfig},
+        sprite::SpriteDraw,
+        texture::GpuTextureManager,
+    },
+    tilemap::TileMap,
+};
+
+const BLESS_VAR: &str = "GOLDEN_BLESS";
+
+pub struct GoldenScene {
+    pub width: u32,
+    pub height: u32,
+    pub camera: Camera2DConfig,
+
+    pub textures: Vec<(AssetId, image::RgbaImage)>,
+    pub tile_map: Option<TileMap>,
+    pub sprites: Vec<SpriteDraw>,
+    pub debug: Option<DebugDraw>,
+}
+
+/// Renders the scene on the fallback adapter
+///
+/// `None` when the machine has none and `ENGINE_SKIP_GPU_TESTS` is set, without the
+/// variable a missing adapter fails the test
+pub fn render_scene(mut scene: GoldenScene) -> Option<image::RgbaImage> {
+    let camera = Camera2D::new(scene.camera);
+    let renderer = pollster::block_on(Renderer2D::new_headless(
+        scene.width,
+        scene.height,
+        Renderer2DConfig { camera: &camera },
+    ));
+
+    let mut renderer = match renderer {
+        Ok(renderer) => renderer,
+        Err(e) if std::env::var_os("ENGINE_SKIP_GPU_TESTS").is_some() => {
+            eprintln!("skipping golden test: {}", e);
+            return None;
+        }
+        Err(e) => panic!(
+            "{}, set ENGINE_SKIP_GPU_TESTS=1 to skip the golden tests",
+            e
+        ),
+    };
+
+    let mut textures = GpuTextureManager::new();
+    for (id, image) in &scene.textures {
+        textures.load(
+            *id,
+            renderer.device(),
+            renderer.queue(),
+            renderer.texture_bind_group_layout(),
+            image,
+        );
+    }
+
+    renderer
+        .render(
+            &camera,
+            &textures,
+            scene.tile_map.as_mut(),
+            &mut scene.sprites,
+            scene.debug.as_ref(),
+        )
+        .expect("Headless rendering cannot lose its target");
+
+    return Some(renderer.read_pixels());
+}
+
+/// Panics when a channel of any pixel differs from the reference by more than `tolerance`
+pub fn assert_golden(name: &str, actual: &image::RgbaImage, tolerance: u8) {
+    let dir = golden_dir();
+    let reference_path = dir.join(format!("{}.png", name));
+
+    if std::env::var_os(BLESS_VAR).is_some() {
+        std::fs::create_dir_all(&dir).expect("Could not create the golden directory");
+        actual
+            .save(&reference_path)
+            .expect("Could not write the golden reference");
+        return;
+    }
+
+    let reference = match image::open(&reference_path) {
+        Ok(reference) => reference.into_rgba8(),
+        Err(e) => panic!(
+            "no golden reference {} ({}), run with {}=1 to create it",
+            reference_path.display(),
+            e,
+            BLESS_VAR
+        ),
+    };
+
+    if reference.dimensions() != actual.dimensions() {
+        write_failure(name, actual, None);
+        panic!(
+            "golden {}: size {:?} does not match the reference {:?}",
+            name,
+            actual.dimensions(),
+            reference.dimensions()
+        );
+    }
+
+    let (diff, mismatched) = diff_images(&reference, actual, tolerance);
+    if mismatched > 0 {
+        let dir = write_failure(name, actual, Some(&diff));
+        panic!(
+            "golden {}: {} pixels differ by more than {}, see {}",
+            name,
+            mismatched,
+            tolerance,
+            dir.display()
+        );
+    }
+}
+
+/// Mismatched pixels in red over a faded copy of the reference, and their count
+fn diff_images(
+    reference: &image::RgbaImage,
+    actual: &image::RgbaImage,
+    tolerance: u8,
+) -> (image::RgbaImage, u32) {
+    let mut mismatched = 0;
+    let mut diff = image::RgbaImage::new(reference.width(), reference.height());
+
+    for (x, y, expected) in reference.enumerate_pixels() {
+        let got = actual.get_pixel(x, y);
+        let differs = expected
+            .0
+            .iter()
+            .zip(got.0.iter())
+            .any(|(a, b)| a.abs_diff(*b) > tolerance);
+
+        let pixel = if differs {
+            mismatched += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            let luma = (expected.0[0] as u32 + expected.0[1] as u32 + expected.0[2] as u32) / 3;
+            let faded = (luma / 4) as u8;
+            image::Rgba([faded, faded, faded, 255])
+        };
+
+        diff.put_pixel(x, y, pixel);
+    }
+
+    return (diff, mismatched);
+}
+
+fn write_failure(
+    name: &str,
+    actual: &image::RgbaImage,
+    diff: Option<&image::RgbaImage>,
+) -> PathBuf {
+    let dir = golden_dir().join("failures");
+    std::fs::create_dir_all(&dir).expect("Could not create the golden failures directory");
+
+    actual
+        .save(dir.join(format!("{}.actual.png", name)))
+        .expect("Could not write the actual image");
+
+    if let Some(diff) = diff {
+        diff.save(dir.join(format!("{}.diff.png", name)))
+            .expect("Could not write the diff image");
+    }
+
+    return dir;
+}
+
+fn golden_dir() -> PathBuf {
+    return PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        math::{coords::Coords3D, uv::UvRect},
+        tilemap::{Chunk, Tile, TileAtlas, CHUNK_SIZE},
+    };
+
+    use super::*;
+
+    const GROUND: AssetId = AssetId::new(0);
+    const CROP: AssetId = AssetId::new(1);
+
+    /// Two 8x8 tiles side by side: green grass and brown soil
+    fn ground_texture() -> image::RgbaImage {
+        return image::RgbaImage::from_fn(16, 8, |x, _| match x < 8 {
+            true => image::Rgba([60, 160, 60, 255]),
+            false => image::Rgba([120, 80, 40, 255]),
+        });
+    }
+
+    /// Yellow square with a red center
+    fn crop_texture() -> image::RgbaImage {
+        return image::RgbaImage::from_fn(8, 8, |x, y| {
+            match (2..6).contains(&x) && (2..6).contains(&y) {
+                true => image::Rgba([220, 40, 40, 255]),
+                false => image::Rgba([230, 210, 60, 255]),
+            }
+        });
+    }
+
+    fn tile_map() -> TileMap {
+        let mut atlas = TileAtlas::new(GROUND, glam::Vec2::splat(8.0));
+        atlas.insert(1, UvRect::new(0.0, 0.0, 0.5, 1.0));
+        atlas.insert(2, UvRect::new(0.5, 0.0, 0.5, 1.0));
+
+        // Grass with a plowed field in the middle of the view
+        let tiles = std::array::from_fn(|i| {
+            let x = (i % CHUNK_SIZE) as i32;
+            let y = (i / CHUNK_SIZE) as i32;
+            let kind = match (2..6).contains(&x) && (2..6).contains(&y) {
+                true => 2,
+                false => 1,
+            };
+
+            return Tile::new(kind, Coords3D::new(x, y, 0));
+        });
+
+        let mut map = TileMap::new(atlas);
+        map.insert_chunk(Chunk::new(tiles));
+
+        return map;
+    }
+
+    fn scene() -> GoldenScene {
+        let crop = |x: f32, y: f32| {
+            return SpriteDraw::from_uv(
+                CROP,
+                UvRect::new(0.0, 0.0, 1.0, 1.0),
+                glam::Vec2::new(x, y),
+                glam::Vec2::splat(8.0),
+            );
+        };
+
+        return GoldenScene {
+            width: 64,
+            height: 64,
+            camera: Camera2DConfig {
+                position: glam::Vec2::new(32.0, 32.0),
+                zoom: 1.0,
+                viewport_size: glam::Vec2::new(64.0, 64.0),
+            },
+            textures: vec![(GROUND, ground_texture()), (CROP, crop_texture())],
+            tile_map: Some(tile_map()),
+            sprites: vec![crop(20.0, 20.0), crop(36.0, 28.0), crop(52.0, 52.0)],
+            debug: None,
+        };
+    }
+
+    #[test]
+    fn test_golden_tiles_and_sprites() {
+        if let Some(image) = render_scene(scene()) {
+            assert_golden("tiles_and_sprites", &image, 2);
+        }
+    }
+
+    #[test]
+    fn test_golden_zoomed_camera() {
+        let mut scene = scene();
+        scene.camera.zoom = 2.0;
+        scene.camera.position = glam::Vec2::new(24.0, 24.0);
+
+        if let Some(image) = render_scene(scene) {
+            assert_golden("zoomed_camera", &image, 2);
+        }
+    }
+
+    /// Round shape with transparent corners, tinted half transparent, magnified
+    #[test]
+    fn test_golden_alpha_blending() {
+        let shape = AssetId::new(2);
+        let texture = image::RgbaImage::from_fn(4, 4, |x, y| {
+            match (x == 0 || x == 3) && (y == 0 || y == 3) {
+                true => image::Rgba([0, 0, 0, 0]),
+                false => image::Rgba([255, 255, 255, 255]),
+            }
+        });
+
+        let mut scene = scene();
+        scene.textures.push((shape, texture));
+        scene.sprites = vec![
+            SpriteDraw::from_uv(
+                shape,
+                UvRect::new(0.0, 0.0, 1.0, 1.0),
+                glam::Vec2::new(20.0, 32.0),
+                glam::Vec2::splat(16.0),
+            ),
+            SpriteDraw::from_uv(
+                shape,
+                UvRect::new(0.0, 0.0, 1.0, 1.0),
+                glam::Vec2::new(44.0, 32.0),
+                glam::Vec2::splat(16.0),
+            )
+            .with_tint([0.2, 0.4, 1.0, 0.5]),
+        ];
+
+        if let Some(image) = render_scene(scene) {
+            assert_golden("alpha_blending", &image, 2);
+        }
+    }
+
+    /// Tile grid, a filled and an outlined shape over the scene
+    #[test]
+    fn test_golden_debug_shapes() {
+        let mut debug = DebugDraw::new();
+        debug.enabled = true;
+        debug.grid(
+            glam::Vec2::ZERO,
+            glam::Vec2::splat(8.0),
+            glam::UVec2::new(8, 8),
+            [1.0, 1.0, 1.0, 0.5],
+        );
+        debug.fill_rect(
+            glam::Vec2::new(16.0, 16.0),
+            glam::Vec2::new(48.0, 48.0),
+            [0.0, 0.0, 1.0, 0.5],
+        );
+        debug.circle(glam::Vec2::new(32.0, 32.0), 12.0, [1.0, 0.0, 0.0, 1.0]);
+
+        let mut scene = scene();
+        scene.debug = Some(debug);
+
+        if let Some(image) = render_scene(scene) {
+            assert_golden("debug_shapes", &image, 2);
+        }
+    }
+
+    #[test]
+    fn test_diff_images() {
+        let reference = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+        let mut actual = reference.clone();
+        actual.put_pixel(1, 1, image::Rgba([102, 100, 100, 255]));
+        actual.put_pixel(2, 2, image::Rgba([110, 100, 100, 255]));
+
+        let (diff, mismatched) = diff_images(&reference, &actual, 2);
+
+        assert_eq!(mismatched, 1);
+        assert_eq!(diff.get_pixel(2, 2), &image::Rgba([255, 0, 0, 255]));
+        assert_eq!(diff.get_pixel(1, 1), &image::Rgba([25, 25, 25, 255]));
+    }
+}
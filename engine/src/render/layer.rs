@@ -0,0 +1,127 @@
+use std::cmp::Ordering;
+
+use crate::assets::AssetId;
+
+/// Named layers, drawn in declaration order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RenderLayer {
+    /// Floor decals and ground sprites, above the tile map
+    Ground,
+
+    /// Crops, villagers, trees and buildings
+    Objects,
+
+    /// Building roofs, covering the objects walking under them
+    Roofs,
+
+    Ui,
+}
+
+/// How the sprites of a layer are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerOrdering {
+    /// Grouped by texture, for layers whose sprites do not overlap
+    Texture,
+
+    /// Lower sprites drawn later, so they appear in front of the ones behind them
+    YSort,
+
+    /// Submission order, for layers where the caller controls the overlap
+    Submission,
+}
+
+impl RenderLayer {
+    pub fn ordering(self) -> LayerOrdering {
+        return match self {
+            RenderLayer::Ground => LayerOrdering::Texture,
+            RenderLayer::Objects | RenderLayer::Roofs => LayerOrdering::YSort,
+            RenderLayer::Ui => LayerOrdering::Submission,
+        };
+    }
+}
+
+/// Position of a sprite in the draw order, lower keys are drawn first
+///
+/// Compares the layer, then the elevation within the layer, then the depth given by
+/// the layer ordering. Equal keys keep their submission order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SortKey {
+    pub layer: RenderLayer,
+    pub elevation: i32,
+
+    /// Negated y of the base of the sprite for y-sorted layers, 0 otherwise
+    pub depth: f32,
+
+    /// Used to group sprites sharing a texture, `None` when the order must be kept
+    pub texture: Option<AssetId>,
+}
+
+impl SortKey {
+    /// `base_y` is the y the sprite stands on, usually the bottom of the sprite
+    pub fn new(layer: RenderLayer, elevation: i32, base_y: f32, texture: AssetId) -> Self {
+        let (depth, texture) = match layer.ordering() {
+            LayerOrdering::Texture => (0.0, Some(texture)),
+            LayerOrdering::YSort => (-base_y, Some(texture)),
+            LayerOrdering::Submission => (0.0, None),
+        };
+
+        return Self {
+            layer,
+            elevation,
+            depth,
+            texture,
+        };
+    }
+}
+
+impl Eq for SortKey {}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self
+            .layer
+            .cmp(&other.layer)
+            .then(self.elevation.cmp(&other.elevation))
+            .then(self.depth.total_cmp(&other.depth))
+            .then(self.texture.cmp(&other.texture));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers_before_elevation() {
+        let ground = SortKey::new(RenderLayer::Ground, 3, 0.0, AssetId::new(0));
+        let object = SortKey::new(RenderLayer::Objects, 0, 0.0, AssetId::new(0));
+        let raised = SortKey::new(RenderLayer::Objects, 1, -100.0, AssetId::new(0));
+
+        assert!(ground < object);
+        assert!(object < raised);
+    }
+
+    #[test]
+    fn test_y_sort() {
+        let tree = SortKey::new(RenderLayer::Objects, 0, 10.0, AssetId::new(0));
+        let villager_behind = SortKey::new(RenderLayer::Objects, 0, 12.0, AssetId::new(1));
+        let villager_in_front = SortKey::new(RenderLayer::Objects, 0, 8.0, AssetId::new(1));
+
+        assert!(villager_behind < tree);
+        assert!(tree < villager_in_front);
+    }
+
+    #[test]
+    fn test_submission_order() {
+        let first = SortKey::new(RenderLayer::Ui, 0, 50.0, AssetId::new(1));
+        let second = SortKey::new(RenderLayer::Ui, 0, 0.0, AssetId::new(0));
+
+        assert_eq!(first.cmp(&second), Ordering::Equal);
+    }
+}
@@ -1,3 +1,10 @@
+pub mod capture;
+pub mod debug;
+#[cfg(test)]
+mod golden;
+pub mod layer;
 pub mod renderer;
+pub mod sprite;
+pub mod text;
 pub mod texture;
 pub mod tiles;
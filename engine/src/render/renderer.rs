@@ -3,23 +3,95 @@ use std::sync::Arc;
 use wgpu::{util::DeviceExt, SurfaceError};
 use winit::window::Window;
 
-use crate::Vertex;
+use crate::{
+    render::{
+        debug::{DebugDraw, DebugVertex},
+        sprite::{DrawSpace, SpriteDraw, SpriteMesh},
+        texture::GpuTextureManager,
+        tiles::TileMeshCache,
+    },
+    tilemap::TileMap,
+    Vertex,
+};
+
+/// Sprites the dynamic buffers can hold before growing
+const INITIAL_SPRITE_CAPACITY: u64 = 1024;
+
+/// Debug vertices the debug buffer can hold before growing
+const INITIAL_DEBUG_CAPACITY: u64 = 4096;
+
+/// Format of the headless render target, the one read back as `image::RgbaImage`
+#[cfg(feature = "headless")]
+const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Where the frames are drawn
+enum RenderTarget {
+    /// The window, presented after every frame
+    Surface {
+        surface: wgpu::Surface<'static>,
+        config: wgpu::SurfaceConfiguration,
+        is_configured: bool,
+    },
+
+    /// A texture that can be read back, used when there is no window
+    #[cfg(feature = "headless")]
+    Offscreen { texture: wgpu::Texture },
+}
+
+#[cfg(feature = "headless")]
+#[derive(Debug)]
+pub enum HeadlessError {
+    Adapter(wgpu::RequestAdapterError),
+    Device(wgpu::RequestDeviceError),
+}
+
+#[cfg(feature = "headless")]
+impl std::fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            HeadlessError::Adapter(e) => write!(f, "no fallback adapter available: {}", e),
+            HeadlessError::Device(e) => write!(f, "could not create the device: {}", e),
+        };
+    }
+}
+
+#[cfg(feature = "headless")]
+impl std::error::Error for HeadlessError {}
 
 pub struct Renderer2D {
-    surface: wgpu::Surface<'static>,
+    target: RenderTarget,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-
-    is_surface_configured: bool,
 
     pipeline: wgpu::RenderPipeline,
 
+    /// Untextured pipelines of the debug shapes, drawn over the scene
+    debug_line_pipeline: wgpu::RenderPipeline,
+    debug_triangle_pipeline: wgpu::RenderPipeline,
+
+    /// Layout shared by the bind groups of every texture, matching `@group(1)` of the shader
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+
     camera_bind_group: wgpu::BindGroup,
     camera_buffer: wgpu::Buffer,
 
+    /// Projection of the screen space sprites, in viewport pixels
+    screen_bind_group: wgpu::BindGroup,
+    screen_buffer: wgpu::Buffer,
+
+    /// Grown when a frame submits more sprites than they can hold
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
+
+    /// Debug triangles followed by debug lines
+    debug_vertex_buffer: wgpu::Buffer,
+
+    sprite_mesh: SpriteMesh,
+    tile_meshes: TileMeshCache,
+
+    /// Set by `request_capture`, the next frame is then read back into `captured`
+    capture_requested: bool,
+    captured: Option<image::RgbaImage>,
 }
 
 pub struct Renderer2DConfig<'a> {
@@ -65,8 +137,14 @@ impl Renderer2D {
             .copied()
             .unwrap_or(surface_caps.formats[0]);
 
+        // Frames are copied out of the surface texture to capture them
+        let usage = match surface_caps.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+            true => wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            false => wgpu::TextureUsages::RENDER_ATTACHMENT,
+        };
+
         let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage,
             format: surface_format,
             width: size.width,
             height: size.height,
@@ -76,6 +154,69 @@ impl Renderer2D {
             desired_maximum_frame_latency: 2,
         };
 
+        let target = RenderTarget::Surface {
+            surface,
+            config: surface_config,
+            is_configured: false,
+        };
+
+        return Self::with_target(device, queue, target, surface_format, config);
+    }
+
+    /// Creates a renderer drawing into an offscreen texture, on the fallback adapter
+    ///
+    /// Needs neither a window nor a GPU, which makes the renderer usable from tests and
+    /// tools. The frames are read back with `read_pixels`. Built with the `headless` feature
+    #[cfg(feature = "headless")]
+    pub async fn new_headless(
+        width: u32,
+        height: u32,
+        config: Renderer2DConfig<'_>,
+    ) -> Result<Self, HeadlessError> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: true,
+            })
+            .await
+            .map_err(HeadlessError::Adapter)?;
+
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: None,
+                required_features: wgpu::Features::empty(),
+                required_limits: wgpu::Limits::downlevel_defaults(),
+                memory_hints: Default::default(),
+                trace: wgpu::Trace::Off,
+            })
+            .await
+            .map_err(HeadlessError::Device)?;
+
+        let texture = create_offscreen_texture(&device, width, height);
+        let target = RenderTarget::Offscreen { texture };
+
+        return Ok(Self::with_target(
+            device,
+            queue,
+            target,
+            OFFSCREEN_FORMAT,
+            config,
+        ));
+    }
+
+    fn with_target(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target: RenderTarget,
+        format: wgpu::TextureFormat,
+        config: Renderer2DConfig<'_>,
+    ) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("SHADER"),
             source: wgpu::ShaderSource::Wgsl(include_str!("../../shaders/shader.wgsl").into()),
@@ -114,9 +255,48 @@ impl Renderer2D {
             }],
         });
 
+        let screen_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SCREEN_BUFFER_UNIFORM"),
+            size: std::mem::size_of::<crate::camera::CameraUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SCREEN_BIND_GROUP"),
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: screen_buffer.as_entire_binding(),
+            }],
+        });
+
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some("TEXTURE_BIND_GROUP_LAYOUT"),
+            });
+
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("RENDER_PIPELINE_LAYOUT"),
-            bind_group_layouts: &[&camera_bind_group_layout],
+            bind_group_layouts: &[&camera_bind_group_layout, &texture_bind_group_layout],
             push_constant_ranges: &[],
         });
 
@@ -129,7 +309,16 @@ impl Renderer2D {
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 buffers: &[Vertex::desc()],
             },
-            fragment: None,
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
             primitive: wgpu::PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
@@ -149,44 +338,126 @@ impl Renderer2D {
             cache: None,
         });
 
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("VERTEX_BUFFER"),
-            contents: bytemuck::cast_slice(crate::VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
+        let debug_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("DEBUG_SHADER"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../../shaders/debug.wgsl").into()),
         });
 
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("INDEX_BUFFER"),
-            contents: bytemuck::cast_slice(crate::INDICES),
-            usage: wgpu::BufferUsages::INDEX,
+        let debug_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("DEBUG_PIPELINE_LAYOUT"),
+            bind_group_layouts: &[&camera_bind_group_layout],
+            push_constant_ranges: &[],
         });
 
+        let debug_line_pipeline = create_debug_pipeline(
+            &device,
+            &debug_layout,
+            &debug_shader,
+            format,
+            wgpu::PrimitiveTopology::LineList,
+        );
+        let debug_triangle_pipeline = create_debug_pipeline(
+            &device,
+            &debug_layout,
+            &debug_shader,
+            format,
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+
+        let debug_vertex_buffer = create_buffer(
+            &device,
+            "DEBUG_VERTEX_BUFFER",
+            INITIAL_DEBUG_CAPACITY * std::mem::size_of::<DebugVertex>() as u64,
+            wgpu::BufferUsages::VERTEX,
+        );
+
+        let vertex_buffer = create_buffer(
+            &device,
+            "VERTEX_BUFFER",
+            INITIAL_SPRITE_CAPACITY * 4 * std::mem::size_of::<Vertex>() as u64,
+            wgpu::BufferUsages::VERTEX,
+        );
+
+        let index_buffer = create_buffer(
+            &device,
+            "INDEX_BUFFER",
+            INITIAL_SPRITE_CAPACITY * 6 * std::mem::size_of::<u32>() as u64,
+            wgpu::BufferUsages::INDEX,
+        );
+
         return Self {
-            is_surface_configured: false,
+            target,
             device,
             queue,
-            config: surface_config,
-            surface,
 
             pipeline,
+            debug_line_pipeline,
+            debug_triangle_pipeline,
+            texture_bind_group_layout,
 
             camera_bind_group,
             camera_buffer,
 
+            screen_bind_group,
+            screen_buffer,
+
             index_buffer,
             vertex_buffer,
+            debug_vertex_buffer,
+
+            sprite_mesh: SpriteMesh::new(),
+            tile_meshes: TileMeshCache::new(),
+
+            capture_requested: false,
+            captured: None,
         };
     }
 
-    pub fn render(&self, camera: &crate::camera::Camera2D) -> Result<(), SurfaceError> {
-        if !self.is_surface_configured {
-            return Ok(());
+    /// Draws the visible tile chunks with one draw call each,
+    /// then the sprites on top with one draw call per texture,
+    /// then the debug shapes over everything
+    pub fn render(
+        &mut self,
+        camera: &crate::camera::Camera2D,
+        textures: &GpuTextureManager,
+        mut tile_map: Option<&mut TileMap>,
+        sprites: &mut [SpriteDraw],
+        debug: Option<&DebugDraw>,
+    ) -> Result<(), SurfaceError> {
+        let (output, view) = match &self.target {
+            RenderTarget::Surface {
+                is_configured: false,
+                ..
+            } => return Ok(()),
+            RenderTarget::Surface { surface, .. } => {
+                let output = surface.get_current_texture()?;
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+
+                (Some(output), view)
+            }
+            #[cfg(feature = "headless")]
+            RenderTarget::Offscreen { texture } => (
+                None,
+                texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+        };
+
+        if let Some(map) = tile_map.as_mut() {
+            self.tile_meshes.prepare(&self.device, map);
         }
 
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.sprite_mesh.build(sprites);
+        self.upload_sprite_mesh();
+
+        let (triangles, lines) = match debug {
+            Some(debug) => (debug.triangles(), debug.lines()),
+            None => (&[][..], &[][..]),
+        };
+        self.upload_debug_vertices(triangles, lines);
+        let triangle_count = triangles.len() as u32;
+        let line_count = lines.len() as u32;
 
         self.write_camera_uniform(&self.queue, camera);
 
@@ -218,26 +489,244 @@ impl Renderer2D {
                 timestamp_writes: None,
             });
 
-            let num_indices = crate::INDICES.len() as u32;
-
             render_pass.set_pipeline(&self.pipeline);
             render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+
+            let tile_atlas = tile_map
+                .as_ref()
+                .and_then(|map| textures.get_or_placeholder(map.atlas().texture()));
+            if let (Some(map), Some(atlas)) = (&tile_map, tile_atlas) {
+                render_pass.set_bind_group(1, &atlas.bind_group, &[]);
+
+                for mesh in self
+                    .tile_meshes
+                    .visible(map.atlas().tile_size(), camera.view_bounds())
+                {
+                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    render_pass
+                        .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+                }
+            }
+
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+            for call in &self.sprite_mesh.draw_calls {
+                // Without a placeholder, sprites of unknown textures are not drawn
+                let Some(texture) = textures.get_or_placeholder(call.texture) else {
+                    continue;
+                };
 
-            render_pass.draw_indexed(0..num_indices, 0, 0..1);
+                let camera_bind_group = match call.space {
+                    DrawSpace::World => &self.camera_bind_group,
+                    DrawSpace::Screen => &self.screen_bind_group,
+                };
+
+                render_pass.set_bind_group(0, camera_bind_group, &[]);
+                render_pass.set_bind_group(1, &texture.bind_group, &[]);
+                render_pass.draw_indexed(call.indices.clone(), 0, 0..1);
+            }
+
+            if triangle_count + line_count > 0 {
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.debug_vertex_buffer.slice(..));
+
+                render_pass.set_pipeline(&self.debug_triangle_pipeline);
+                render_pass.draw(0..triangle_count, 0..1);
+
+                render_pass.set_pipeline(&self.debug_line_pipeline);
+                render_pass.draw(triangle_count..triangle_count + line_count, 0..1);
+            }
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
 
-        output.present();
+        if self.capture_requested && self.can_capture() {
+            self.capture_requested = false;
+
+            let texture = match (&output, &self.target) {
+                (Some(output), _) => &output.texture,
+                #[cfg(feature = "headless")]
+                (None, RenderTarget::Offscreen { texture }) => texture,
+                (None, RenderTarget::Surface { .. }) => unreachable!(),
+            };
+            self.captured = Some(self.read_texture(texture));
+        }
+
+        if let Some(output) = output {
+            output.present();
+        }
 
         return Ok(());
     }
 
+    /// Copies the last frame of a headless renderer back to the CPU
+    #[cfg(feature = "headless")]
+    pub fn read_pixels(&self) -> image::RgbaImage {
+        return match &self.target {
+            RenderTarget::Offscreen { texture } => self.read_texture(texture),
+            RenderTarget::Surface { .. } => panic!("only headless renderers can read pixels"),
+        };
+    }
+
+    /// Reads the next rendered frame back, it is then returned by `take_capture`
+    pub fn request_capture(&mut self) {
+        self.capture_requested = true;
+    }
+
+    pub fn take_capture(&mut self) -> Option<image::RgbaImage> {
+        return self.captured.take();
+    }
+
+    /// Whether frames can be read back, some surfaces cannot be copied from
+    pub fn can_capture(&self) -> bool {
+        return match &self.target {
+            RenderTarget::Surface { config, .. } => {
+                config.usage.contains(wgpu::TextureUsages::COPY_SRC)
+                    && is_capture_format(config.format)
+            }
+            #[cfg(feature = "headless")]
+            RenderTarget::Offscreen { .. } => true,
+        };
+    }
+
+    /// Copies a render target back to the CPU, swizzling BGRA surfaces to RGBA
+    fn read_texture(&self, texture: &wgpu::Texture) -> image::RgbaImage {
+        let width = texture.width();
+        let height = texture.height();
+
+        // Rows of a texture copy must be aligned to 256 bytes
+        let unpadded_bytes_per_row = 4 * width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("READBACK_BUFFER"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("READBACK_ENCODER"),
+            });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("Could not map the readback buffer");
+        });
+        self.device
+            .poll(wgpu::PollType::Wait)
+            .expect("Could not wait for the readback");
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        buffer.unmap();
+
+        if is_bgra(texture.format()) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        return image::RgbaImage::from_raw(width, height, pixels)
+            .expect("Readback size does not match the texture");
+    }
+
+    /// Writes the sprite mesh into the dynamic buffers, growing them when too small
+    fn upload_sprite_mesh(&mut self) {
+        let vertices: &[u8] = bytemuck::cast_slice(&self.sprite_mesh.vertices);
+        let indices: &[u8] = bytemuck::cast_slice(&self.sprite_mesh.indices);
+
+        if vertices.len() as u64 > self.vertex_buffer.size() {
+            self.vertex_buffer = create_buffer(
+                &self.device,
+                "VERTEX_BUFFER",
+                (vertices.len() as u64).next_power_of_two(),
+                wgpu::BufferUsages::VERTEX,
+            );
+        }
+
+        if indices.len() as u64 > self.index_buffer.size() {
+            self.index_buffer = create_buffer(
+                &self.device,
+                "INDEX_BUFFER",
+                (indices.len() as u64).next_power_of_two(),
+                wgpu::BufferUsages::INDEX,
+            );
+        }
+
+        self.queue.write_buffer(&self.vertex_buffer, 0, vertices);
+        self.queue.write_buffer(&self.index_buffer, 0, indices);
+    }
+
+    /// Writes the debug triangles then the debug lines, growing the buffer when too small
+    fn upload_debug_vertices(&mut self, triangles: &[DebugVertex], lines: &[DebugVertex]) {
+        let triangles: &[u8] = bytemuck::cast_slice(triangles);
+        let lines: &[u8] = bytemuck::cast_slice(lines);
+        let size = (triangles.len() + lines.len()) as u64;
+
+        if size > self.debug_vertex_buffer.size() {
+            self.debug_vertex_buffer = create_buffer(
+                &self.device,
+                "DEBUG_VERTEX_BUFFER",
+                size.next_power_of_two(),
+                wgpu::BufferUsages::VERTEX,
+            );
+        }
+
+        self.queue
+            .write_buffer(&self.debug_vertex_buffer, 0, triangles);
+        self.queue
+            .write_buffer(&self.debug_vertex_buffer, triangles.len() as u64, lines);
+    }
+
     fn write_camera_uniform(&self, queue: &wgpu::Queue, camera: &crate::camera::Camera2D) {
         let uniform = crate::camera::CameraUniform::from_camera(camera);
         queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let size = self.viewport_size().as_vec2();
+        let screen = crate::camera::CameraUniform::screen(size.x, size.y);
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::bytes_of(&screen));
+    }
+
+    /// Size of the render target in pixels
+    pub fn viewport_size(&self) -> glam::UVec2 {
+        return match &self.target {
+            RenderTarget::Surface { config, .. } => glam::UVec2::new(config.width, config.height),
+            #[cfg(feature = "headless")]
+            RenderTarget::Offscreen { texture } => {
+                glam::UVec2::new(texture.width(), texture.height())
+            }
+        };
     }
 
     pub fn device(&self) -> &wgpu::Device {
@@ -248,12 +737,317 @@ impl Renderer2D {
         return &self.queue;
     }
 
+    pub fn texture_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        return &self.texture_bind_group_layout;
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
-        if width > 0 && height > 0 {
-            self.config.width = width;
-            self.config.height = height;
-            self.surface.configure(&self.device, &self.config);
-            self.is_surface_configured = true;
+        if width == 0 || height == 0 {
+            return;
         }
+
+        match &mut self.target {
+            RenderTarget::Surface {
+                surface,
+                config,
+                is_configured,
+            } => {
+                config.width = width;
+                config.height = height;
+                surface.configure(&self.device, config);
+                *is_configured = true;
+            }
+            #[cfg(feature = "headless")]
+            RenderTarget::Offscreen { texture } => {
+                *texture = create_offscreen_texture(&self.device, width, height);
+            }
+        }
+    }
+}
+
+fn create_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    size: u64,
+    usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
+    return device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+}
+
+/// 8 bit formats `read_texture` turns into an `image::RgbaImage`
+fn is_capture_format(format: wgpu::TextureFormat) -> bool {
+    return matches!(
+        format,
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb
+    ) || is_bgra(format);
+}
+
+fn is_bgra(format: wgpu::TextureFormat) -> bool {
+    return matches!(
+        format,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+    );
+}
+
+fn create_debug_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    topology: wgpu::PrimitiveTopology,
+) -> wgpu::RenderPipeline {
+    return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("DEBUG_PIPELINE"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            buffers: &[DebugVertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // Shapes can be submitted in any winding
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    });
+}
+
+#[cfg(feature = "headless")]
+fn create_offscreen_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+    return device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("OFFSCREEN_TARGET"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: OFFSCREEN_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assets::AssetId,
+        camera::{Camera2D, Camera2DConfig},
+        math::{
+            coords::{Coords2D, Coords3D},
+            uv::UvRect,
+        },
+        tilemap::{Chunk, Tile, TileAtlas, CHUNK_SIZE},
+    };
+
+    use super::*;
+
+    fn camera() -> Camera2D {
+        return Camera2D::new(Camera2DConfig {
+            position: glam::Vec2::ZERO,
+            zoom: 1.0,
+            viewport_size: glam::Vec2::new(64.0, 64.0),
+        });
+    }
+
+    /// `None` when the machine has no fallback adapter and `ENGINE_SKIP_GPU_TESTS` is
+    /// set, the test is then skipped. Without the variable a missing adapter fails
+    fn headless(camera: &Camera2D) -> Option<Renderer2D> {
+        let renderer = pollster::block_on(Renderer2D::new_headless(
+            64,
+            64,
+            Renderer2DConfig { camera },
+        ));
+
+        return match renderer {
+            Ok(renderer) => Some(renderer),
+            Err(e) if std::env::var_os("ENGINE_SKIP_GPU_TESTS").is_some() => {
+                eprintln!("skipping headless render test: {}", e);
+                None
+            }
+            Err(e) => panic!(
+                "{}, set ENGINE_SKIP_GPU_TESTS=1 to skip the render tests",
+                e
+            ),
+        };
+    }
+
+    #[test]
+    fn test_headless_sprite() {
+        let camera = camera();
+        let mut renderer = match headless(&camera) {
+            Some(renderer) => renderer,
+            None => return,
+        };
+
+        let mut textures = GpuTextureManager::new();
+        let red = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        textures.load(
+            AssetId::new(0),
+            renderer.device(),
+            renderer.queue(),
+            renderer.texture_bind_group_layout(),
+            &red,
+        );
+
+        let mut sprites = vec![SpriteDraw::from_uv(
+            AssetId::new(0),
+            UvRect::new(0.0, 0.0, 1.0, 1.0),
+            glam::Vec2::ZERO,
+            glam::Vec2::splat(32.0),
+        )];
+
+        renderer
+            .render(&camera, &textures, None, &mut sprites, None)
+            .unwrap();
+        let pixels = renderer.read_pixels();
+
+        assert_eq!(pixels.dimensions(), (64, 64));
+        assert_eq!(pixels.get_pixel(32, 32), &image::Rgba([255, 0, 0, 255]));
+        assert_ne!(pixels.get_pixel(2, 2), &image::Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_unknown_texture_draws_placeholder() {
+        let camera = camera();
+        let mut renderer = match headless(&camera) {
+            Some(renderer) => renderer,
+            None => return,
+        };
+
+        let mut textures = GpuTextureManager::new();
+        let mut sprites = vec![SpriteDraw::from_uv(
+            AssetId::new(3),
+            UvRect::new(0.0, 0.0, 1.0, 1.0),
+            glam::Vec2::ZERO,
+            glam::Vec2::splat(32.0),
+        )];
+
+        // Skipped without a placeholder
+        renderer
+            .render(&camera, &textures, None, &mut sprites, None)
+            .unwrap();
+        let background = *renderer.read_pixels().get_pixel(32, 32);
+
+        textures.load_placeholder(
+            AssetId::new(0),
+            renderer.device(),
+            renderer.queue(),
+            renderer.texture_bind_group_layout(),
+        );
+        renderer
+            .render(&camera, &textures, None, &mut sprites, None)
+            .unwrap();
+        let pixels = renderer.read_pixels();
+
+        assert_ne!(pixels.get_pixel(32, 32), &background);
+        assert!(pixels
+            .pixels()
+            .any(|p| p == &image::Rgba([255, 0, 255, 255])));
+    }
+
+    #[test]
+    fn test_chunk_without_atlas_regions() {
+        let camera = camera();
+        let mut renderer = match headless(&camera) {
+            Some(renderer) => renderer,
+            None => return,
+        };
+
+        let mut textures = GpuTextureManager::new();
+        let red = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        textures.load(
+            AssetId::new(0),
+            renderer.device(),
+            renderer.queue(),
+            renderer.texture_bind_group_layout(),
+            &red,
+        );
+
+        // Every tile is of a kind the atlas has no region for
+        let tiles = std::array::from_fn(|i| {
+            let x = (i % CHUNK_SIZE) as i32;
+            let y = (i / CHUNK_SIZE) as i32;
+            return Tile::new(7, Coords3D::new(x, y, 0));
+        });
+        let mut atlas = TileAtlas::new(AssetId::new(0), glam::Vec2::splat(8.0));
+        atlas.insert(1, UvRect::new(0.0, 0.0, 1.0, 1.0));
+        let mut map = TileMap::new(atlas);
+        map.insert_chunk(Chunk::new(tiles));
+
+        renderer
+            .render(&camera, &textures, Some(&mut map), &mut [], None)
+            .unwrap();
+        assert_ne!(
+            renderer.read_pixels().get_pixel(32, 32),
+            &image::Rgba([255, 0, 0, 255])
+        );
+
+        // A drawable chunk emptied later must drop its mesh too
+        map.chunks_mut()
+            .for_each(|c| c.tile_at_mut(Coords2D::new(0, 0)).set_kind(1));
+        renderer
+            .render(&camera, &textures, Some(&mut map), &mut [], None)
+            .unwrap();
+        map.chunks_mut()
+            .for_each(|c| c.tile_at_mut(Coords2D::new(0, 0)).set_kind(7));
+        renderer
+            .render(&camera, &textures, Some(&mut map), &mut [], None)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_capture_next_frame() {
+        let camera = camera();
+        let mut renderer = match headless(&camera) {
+            Some(renderer) => renderer,
+            None => return,
+        };
+        let textures = GpuTextureManager::new();
+
+        renderer
+            .render(&camera, &textures, None, &mut [], None)
+            .unwrap();
+        assert!(renderer.take_capture().is_none());
+
+        renderer.request_capture();
+        renderer
+            .render(&camera, &textures, None, &mut [], None)
+            .unwrap();
+
+        let capture = renderer.take_capture().expect("the frame was not captured");
+        assert_eq!(capture, renderer.read_pixels());
+        assert!(renderer.take_capture().is_none());
     }
 }
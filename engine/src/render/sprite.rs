@@ -0,0 +1,333 @@
+use std::ops::Range;
+
+use crate::{
+    assets::{sprite::Sprite, AssetId},
+    ecs::resource::Resource,
+    math::uv::UvRect,
+    render::layer::{RenderLayer, SortKey},
+    Vertex,
+};
+
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Coordinates a sprite is positioned in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DrawSpace {
+    /// World units seen through the camera, y going up
+    World,
+
+    /// Viewport pixels from the top-left corner, y going down, drawn over the world
+    Screen,
+}
+
+/// A sprite to draw on the current frame
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteDraw {
+    /// World position of the center of the sprite
+    pub position: glam::Vec2,
+    pub size: glam::Vec2,
+
+    pub texture: AssetId,
+    pub uv: UvRect,
+
+    /// Multiplied with the texture color
+    pub tint: [f32; 4],
+
+    pub layer: RenderLayer,
+
+    /// Height level within the layer, usually the z of the tile the sprite stands on
+    pub elevation: i32,
+
+    pub space: DrawSpace,
+}
+
+impl SpriteDraw {
+    pub fn new(sprite: &Sprite, position: glam::Vec2, size: glam::Vec2) -> Self {
+        return Self::from_uv(sprite.texture_id(), sprite.uv(), position, size);
+    }
+
+    pub fn from_uv(texture: AssetId, uv: UvRect, position: glam::Vec2, size: glam::Vec2) -> Self {
+        return Self {
+            position,
+            size,
+            texture,
+            uv,
+            tint: WHITE,
+            layer: RenderLayer::Objects,
+            elevation: 0,
+            space: DrawSpace::World,
+        };
+    }
+
+    pub fn with_tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        return self;
+    }
+
+    pub fn with_layer(mut self, layer: RenderLayer) -> Self {
+        self.layer = layer;
+        return self;
+    }
+
+    pub fn with_elevation(mut self, elevation: i32) -> Self {
+        self.elevation = elevation;
+        return self;
+    }
+
+    /// Positions the sprite in viewport pixels instead of world units
+    pub fn in_screen_space(mut self) -> Self {
+        self.space = DrawSpace::Screen;
+        return self;
+    }
+
+    /// Y-sorted layers sort on the bottom edge of the sprite, where it touches the ground
+    pub fn sort_key(&self) -> SortKey {
+        let base_y = match self.space {
+            DrawSpace::World => self.position.y - 0.5 * self.size.y,
+            DrawSpace::Screen => -(self.position.y + 0.5 * self.size.y),
+        };
+
+        return SortKey::new(self.layer, self.elevation, base_y, self.texture);
+    }
+}
+
+/// Sprites submitted for the current frame, inserted as a resource by the engine
+///
+/// Systems submit sprites from the `RenderExtract` stage, which runs once per frame.
+/// The batch is emptied after every frame
+pub struct SpriteBatch {
+    sprites: Vec<SpriteDraw>,
+}
+
+impl Resource for SpriteBatch {}
+
+impl SpriteBatch {
+    pub fn new() -> Self {
+        return Self {
+            sprites: Vec::new(),
+        };
+    }
+
+    pub fn draw(&mut self, sprite: SpriteDraw) {
+        self.sprites.push(sprite);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.sprites.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.sprites.is_empty();
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<SpriteDraw> {
+        return std::mem::take(&mut self.sprites);
+    }
+}
+
+/// Range of indices sharing the same texture, drawn with a single call
+#[derive(Debug, PartialEq)]
+pub(crate) struct DrawCall {
+    pub texture: AssetId,
+    pub space: DrawSpace,
+    pub indices: Range<u32>,
+}
+
+/// Geometry of a frame, ready to be uploaded to the GPU
+pub(crate) struct SpriteMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub draw_calls: Vec<DrawCall>,
+}
+
+impl SpriteMesh {
+    pub fn new() -> Self {
+        return Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            draw_calls: Vec::new(),
+        };
+    }
+
+    /// Builds one quad per sprite in draw order, world sprites first, consecutive
+    /// sprites sharing a texture end up in the same draw call
+    pub fn build(&mut self, sprites: &mut [SpriteDraw]) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.draw_calls.clear();
+
+        // Stable, so sprites with equal keys keep their submission order
+        sprites.sort_by_key(|s| (s.space, s.sort_key()));
+
+        for sprite in sprites.iter() {
+            let start = self.indices.len() as u32;
+            self.push_quad(sprite);
+            let end = self.indices.len() as u32;
+
+            match self.draw_calls.last_mut() {
+                Some(call) if call.texture == sprite.texture && call.space == sprite.space => {
+                    call.indices.end = end
+                }
+                _ => self.draw_calls.push(DrawCall {
+                    texture: sprite.texture,
+                    space: sprite.space,
+                    indices: start..end,
+                }),
+            }
+        }
+    }
+
+    fn push_quad(&mut self, sprite: &SpriteDraw) {
+        let half = sprite.size * 0.5;
+        let mut min = sprite.position - half;
+        let mut max = sprite.position + half;
+
+        // Keeps the bottom-left corner first so the quad stays counter-clockwise on screen
+        if sprite.space == DrawSpace::Screen {
+            std::mem::swap(&mut min.y, &mut max.y);
+        }
+
+        // The v axis of the texture goes down while the y axis of the world goes up
+        let [u, v, w, h] = sprite.uv.to_array();
+        let corners = [
+            ([min.x, min.y], [u, v + h]),
+            ([max.x, min.y], [u + w, v + h]),
+            ([max.x, max.y], [u + w, v]),
+            ([min.x, max.y], [u, v]),
+        ];
+
+        let base = self.vertices.len() as u32;
+        for (position, tex_coords) in corners {
+            self.vertices.push(Vertex {
+                position: [position[0], position[1], 0.0],
+                tex_coords,
+                color: sprite.tint,
+            });
+        }
+
+        self.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite(texture: u16, layer: RenderLayer) -> SpriteDraw {
+        return SpriteDraw::from_uv(
+            AssetId::new(texture),
+            UvRect::new(0.0, 0.0, 0.5, 0.5),
+            glam::Vec2::ZERO,
+            glam::Vec2::splat(16.0),
+        )
+        .with_layer(layer);
+    }
+
+    #[test]
+    fn test_one_draw_call_per_texture() {
+        let mut sprites = vec![
+            sprite(0, RenderLayer::Ground),
+            sprite(1, RenderLayer::Ground),
+            sprite(0, RenderLayer::Ground),
+            sprite(1, RenderLayer::Ground),
+        ];
+
+        let mut mesh = SpriteMesh::new();
+        mesh.build(&mut sprites);
+
+        assert_eq!(mesh.vertices.len(), 16);
+        assert_eq!(mesh.indices.len(), 24);
+        assert_eq!(
+            mesh.draw_calls,
+            vec![
+                DrawCall {
+                    texture: AssetId::new(0),
+                    space: DrawSpace::World,
+                    indices: 0..12,
+                },
+                DrawCall {
+                    texture: AssetId::new(1),
+                    space: DrawSpace::World,
+                    indices: 12..24,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layers_are_drawn_in_order() {
+        let mut sprites = vec![
+            sprite(0, RenderLayer::Objects),
+            sprite(1, RenderLayer::Ground),
+            sprite(0, RenderLayer::Ground),
+        ];
+
+        let mut mesh = SpriteMesh::new();
+        mesh.build(&mut sprites);
+
+        let textures: Vec<u16> = mesh.draw_calls.iter().map(|c| c.texture.value()).collect();
+        assert_eq!(textures, vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn test_y_sort_and_submission_order() {
+        let at = |texture: u16, y: f32, layer: RenderLayer| {
+            let mut s = sprite(texture, layer);
+            s.position.y = y;
+            return s;
+        };
+
+        let mut sprites = vec![
+            at(0, 0.0, RenderLayer::Ui),
+            at(1, 0.0, RenderLayer::Ui),
+            at(2, 10.0, RenderLayer::Objects),
+            at(3, 30.0, RenderLayer::Objects),
+            at(4, 20.0, RenderLayer::Objects),
+            at(0, 50.0, RenderLayer::Ui),
+        ];
+
+        let mut mesh = SpriteMesh::new();
+        mesh.build(&mut sprites);
+
+        let textures: Vec<u16> = mesh.draw_calls.iter().map(|c| c.texture.value()).collect();
+        assert_eq!(textures, vec![3, 4, 2, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_screen_space_after_world() {
+        let mut sprites = vec![
+            sprite(0, RenderLayer::Ui).in_screen_space(),
+            sprite(0, RenderLayer::Ui),
+        ];
+
+        let mut mesh = SpriteMesh::new();
+        mesh.build(&mut sprites);
+
+        let spaces: Vec<DrawSpace> = mesh.draw_calls.iter().map(|c| c.space).collect();
+        assert_eq!(spaces, vec![DrawSpace::World, DrawSpace::Screen]);
+
+        // Screen space goes down, the bottom-left corner has the larger y
+        assert_eq!(mesh.vertices[4].position, [-8.0, 8.0, 0.0]);
+        assert_eq!(mesh.vertices[4].tex_coords, [0.0, 0.5]);
+    }
+
+    #[test]
+    fn test_quad() {
+        let mut sprites = vec![SpriteDraw::from_uv(
+            AssetId::new(0),
+            UvRect::new(0.25, 0.5, 0.25, 0.5),
+            glam::Vec2::new(10.0, 20.0),
+            glam::Vec2::new(4.0, 8.0),
+        )];
+
+        let mut mesh = SpriteMesh::new();
+        mesh.build(&mut sprites);
+
+        assert_eq!(mesh.vertices[0].position, [8.0, 16.0, 0.0]);
+        assert_eq!(mesh.vertices[0].tex_coords, [0.25, 1.0]);
+        assert_eq!(mesh.vertices[2].position, [12.0, 24.0, 0.0]);
+        assert_eq!(mesh.vertices[2].tex_coords, [0.5, 0.5]);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+    }
+}
@@ -0,0 +1,664 @@
+use std::collections::HashMap;
+
+use crate::{
+    assets::{font::Font, AssetId, AssetsRegistry},
+    ecs::resource::Resource,
+    math::uv::UvRect,
+    render::{
+        layer::RenderLayer,
+        renderer::Renderer2D,
+        sprite::{DrawSpace, SpriteDraw},
+        texture::GpuTextureManager,
+    },
+};
+
+/// Side length of the glyph atlas texture, in pixels
+const GLYPH_ATLAS_SIZE: u32 = 1024;
+
+/// Empty pixels around each glyph so sampling never bleeds into a neighbour
+const GLYPH_PADDING: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// A text to draw on the current frame
+#[derive(Debug, Clone)]
+pub struct TextDraw {
+    pub text: String,
+    pub font: AssetId,
+
+    /// Height of a line in pixels, or in world units for world space text
+    pub size: f32,
+
+    /// Top-left corner of the text box
+    pub position: glam::Vec2,
+    pub color: [f32; 4],
+    pub align: TextAlign,
+
+    /// Lines longer than this wrap at the last space, the box is as wide as the
+    /// longest line when `None`
+    pub max_width: Option<f32>,
+
+    pub space: DrawSpace,
+    pub layer: RenderLayer,
+    pub elevation: i32,
+}
+
+impl TextDraw {
+    pub fn new(text: &str, font: AssetId, size: f32, position: glam::Vec2) -> Self {
+        return Self {
+            text: text.to_string(),
+            font,
+            size,
+            position,
+            color: [1.0, 1.0, 1.0, 1.0],
+            align: TextAlign::Left,
+            max_width: None,
+            space: DrawSpace::World,
+            layer: RenderLayer::Ui,
+            elevation: 0,
+        };
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        return self;
+    }
+
+    pub fn with_align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        return self;
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        return self;
+    }
+
+    pub fn with_layer(mut self, layer: RenderLayer) -> Self {
+        self.layer = layer;
+        return self;
+    }
+
+    pub fn with_elevation(mut self, elevation: i32) -> Self {
+        self.elevation = elevation;
+        return self;
+    }
+
+    /// Positions the text in viewport pixels instead of world units
+    pub fn in_screen_space(mut self) -> Self {
+        self.space = DrawSpace::Screen;
+        return self;
+    }
+}
+
+/// Texts submitted for the current frame, inserted as a resource by the engine
+///
+/// Like `SpriteBatch`, texts are submitted from the `RenderExtract` stage and the
+/// batch is emptied after every frame
+pub struct TextBatch {
+    texts: Vec<TextDraw>,
+}
+
+impl Resource for TextBatch {}
+
+impl TextBatch {
+    pub fn new() -> Self {
+        return Self { texts: Vec::new() };
+    }
+
+    pub fn draw(&mut self, text: TextDraw) {
+        self.texts.push(text);
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<TextDraw> {
+        return std::mem::take(&mut self.texts);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedGlyph {
+    pub c: char,
+
+    /// Top-left corner relative to the top-left corner of the text box, y going down
+    pub min: glam::Vec2,
+    pub size: glam::Vec2,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+
+    /// Size of the text box
+    pub size: glam::Vec2,
+}
+
+struct LineGlyph {
+    c: char,
+    x: f32,
+}
+
+struct Line {
+    glyphs: Vec<LineGlyph>,
+    width: f32,
+}
+
+/// Places the glyphs of the text, wrapping lines longer than `max_width`
+///
+/// Also used to measure a text before drawing it, e.g. to size a UI panel
+pub fn layout(
+    font: &Font,
+    text: &str,
+    size: f32,
+    align: TextAlign,
+    max_width: Option<f32>,
+) -> TextLayout {
+    let size = font.snap_size(size);
+
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        wrap_paragraph(font, paragraph, size, max_width, &mut lines);
+    }
+
+    let widest = lines.iter().map(|l| l.width).fold(0.0, f32::max);
+    let box_width = max_width.unwrap_or(widest);
+    let line_height = font.line_height(size);
+    let ascent = font.ascent(size);
+
+    let mut glyphs = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let offset = match align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => 0.5 * (box_width - line.width),
+            TextAlign::Right => box_width - line.width,
+        };
+        let baseline = ascent + i as f32 * line_height;
+
+        for glyph in &line.glyphs {
+            if glyph.c.is_whitespace() {
+                continue;
+            }
+
+            let metrics = font.glyph(glyph.c, size);
+            if metrics.size.x == 0.0 || metrics.size.y == 0.0 {
+                continue;
+            }
+
+            glyphs.push(PositionedGlyph {
+                c: glyph.c,
+                min: glam::Vec2::new(offset + glyph.x, baseline) + metrics.offset,
+                size: metrics.size,
+            });
+        }
+    }
+
+    return TextLayout {
+        glyphs,
+        size: glam::Vec2::new(box_width, lines.len() as f32 * line_height),
+    };
+}
+
+fn wrap_paragraph(
+    font: &Font,
+    paragraph: &str,
+    size: f32,
+    max_width: Option<f32>,
+    lines: &mut Vec<Line>,
+) {
+    let mut glyphs: Vec<LineGlyph> = Vec::new();
+    let mut pen = 0.0;
+    let mut width = 0.0;
+    let mut previous = None;
+
+    // Index of the first glyph after the last space, the pen position there and
+    // the width of the line when it breaks at that space
+    let mut last_break: Option<(usize, f32, f32)> = None;
+
+    for c in paragraph.chars() {
+        if let Some(previous) = previous {
+            pen += font.kern(previous, c, size);
+        }
+        previous = Some(c);
+
+        let advance = font.glyph(c, size).advance;
+
+        if c == ' ' {
+            pen += advance;
+            last_break = Some((glyphs.len() + 1, pen, width));
+            glyphs.push(LineGlyph {
+                c,
+                x: pen - advance,
+            });
+            continue;
+        }
+
+        let overflows = max_width.is_some_and(|max| pen + advance > max);
+        if overflows && !glyphs.is_empty() {
+            // Break at the last space, or in the middle of a word longer than the line
+            let (index, x, line_width) = last_break.unwrap_or((glyphs.len(), pen, width));
+            let rest = glyphs.split_off(index);
+
+            lines.push(Line {
+                glyphs,
+                width: line_width,
+            });
+
+            glyphs = rest
+                .into_iter()
+                .map(|g| LineGlyph { c: g.c, x: g.x - x })
+                .collect();
+            pen -= x;
+            last_break = None;
+        }
+
+        glyphs.push(LineGlyph { c, x: pen });
+        pen += advance;
+        width = pen;
+    }
+
+    lines.push(Line { glyphs, width });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: AssetId,
+    c: char,
+    size: u32,
+}
+
+/// Packs glyph bitmaps in rows of a square texture
+struct GlyphPacker {
+    size: u32,
+
+    /// Top of the current row, its height and the first free x in it
+    row_y: u32,
+    row_height: u32,
+    cursor_x: u32,
+}
+
+impl GlyphPacker {
+    fn new(size: u32) -> Self {
+        return Self {
+            size,
+            row_y: 0,
+            row_height: 0,
+            cursor_x: 0,
+        };
+    }
+
+    /// Top-left corner of a free region, `None` when the texture is full
+    fn allocate(&mut self, width: u32, height: u32) -> Option<glam::UVec2> {
+        let width = width + GLYPH_PADDING;
+        let height = height + GLYPH_PADDING;
+
+        if width > self.size {
+            return None;
+        }
+
+        if self.cursor_x + width > self.size {
+            self.row_y += self.row_height;
+            self.row_height = 0;
+            self.cursor_x = 0;
+        }
+
+        if self.row_y + height > self.size {
+            return None;
+        }
+
+        let position = glam::UVec2::new(self.cursor_x, self.row_y);
+        self.cursor_x += width;
+        self.row_height = self.row_height.max(height);
+
+        return Some(position);
+    }
+
+    fn clear(&mut self) {
+        self.row_y = 0;
+        self.row_height = 0;
+        self.cursor_x = 0;
+    }
+}
+
+/// Turns texts into sprites, rasterizing the glyphs of vector fonts into a glyph atlas
+pub(crate) struct TextRenderer {
+    /// Texture of the glyph atlas, created on the first vector glyph
+    atlas_texture: AssetId,
+    packer: GlyphPacker,
+    glyphs: HashMap<GlyphKey, UvRect>,
+
+    /// A glyph of the current batch did not fit in the atlas
+    is_full: bool,
+}
+
+impl TextRenderer {
+    pub fn new(atlas_texture: AssetId) -> Self {
+        return Self {
+            atlas_texture,
+            packer: GlyphPacker::new(GLYPH_ATLAS_SIZE),
+            glyphs: HashMap::new(),
+            is_full: false,
+        };
+    }
+
+    /// Appends a sprite per visible glyph, texts with an unknown font are skipped
+    pub fn prepare(
+        &mut self,
+        texts: &[TextDraw],
+        assets: &AssetsRegistry,
+        textures: &mut GpuTextureManager,
+        renderer: &Renderer2D,
+        sprites: &mut Vec<SpriteDraw>,
+    ) {
+        let first_sprite = sprites.len();
+        self.prepare_texts(texts, assets, textures, renderer, sprites);
+
+        // The sprites already made point anywhere in the atlas, so it is emptied and
+        // the whole batch laid out again. Glyphs that still do not fit are skipped
+        if self.is_full {
+            sprites.truncate(first_sprite);
+            self.packer.clear();
+            self.glyphs.clear();
+
+            self.prepare_texts(texts, assets, textures, renderer, sprites);
+        }
+    }
+
+    fn prepare_texts(
+        &mut self,
+        texts: &[TextDraw],
+        assets: &AssetsRegistry,
+        textures: &mut GpuTextureManager,
+        renderer: &Renderer2D,
+        sprites: &mut Vec<SpriteDraw>,
+    ) {
+        self.is_full = false;
+
+        for text in texts {
+            let font = match assets.font(text.font) {
+                Some(font) => font,
+                None => continue,
+            };
+
+            let layout = layout(font, &text.text, text.size, text.align, text.max_width);
+            let size = font.snap_size(text.size);
+
+            for glyph in &layout.glyphs {
+                let region = match font {
+                    Font::Vector(vector) => {
+                        let key = GlyphKey {
+                            font: text.font,
+                            c: glyph.c,
+                            size: size as u32,
+                        };
+
+                        self.vector_glyph(key, vector, textures, renderer)
+                            .map(|uv| (self.atlas_texture, uv))
+                    }
+                    Font::Bitmap(bitmap) => bitmap.uv(glyph.c).map(|uv| (bitmap.texture(), uv)),
+                };
+
+                let (texture, uv) = match region {
+                    Some(region) => region,
+                    None => continue,
+                };
+
+                let center = glyph.min + 0.5 * glyph.size;
+                let position = match text.space {
+                    DrawSpace::World => text.position + glam::Vec2::new(center.x, -center.y),
+                    DrawSpace::Screen => text.position + center,
+                };
+
+                let mut sprite = SpriteDraw::from_uv(texture, uv, position, glyph.size)
+                    .with_tint(text.color)
+                    .with_layer(text.layer)
+                    .with_elevation(text.elevation);
+                sprite.space = text.space;
+
+                sprites.push(sprite);
+            }
+        }
+    }
+
+    /// Region of the glyph in the atlas, rasterized on first use
+    fn vector_glyph(
+        &mut self,
+        key: GlyphKey,
+        font: &fontdue::Font,
+        textures: &mut GpuTextureManager,
+        renderer: &Renderer2D,
+    ) -> Option<UvRect> {
+        if let Some(uv) = self.glyphs.get(&key) {
+            return Some(*uv);
+        }
+
+        if !textures.contains(self.atlas_texture) {
+            textures.create(
+                self.atlas_texture,
+                renderer.device(),
+                renderer.texture_bind_group_layout(),
+                GLYPH_ATLAS_SIZE,
+                GLYPH_ATLAS_SIZE,
+            );
+        }
+
+        let (metrics, coverage) = font.rasterize(key.c, key.size as f32);
+        let (width, height) = (metrics.width as u32, metrics.height as u32);
+
+        let Some(position) = self.packer.allocate(width, height) else {
+            self.is_full = true;
+            return None;
+        };
+
+        // White glyph, the text color is applied as the sprite tint
+        let image = image::RgbaImage::from_fn(width, height, |x, y| {
+            let alpha = coverage[(y * width + x) as usize];
+            return image::Rgba([255, 255, 255, alpha]);
+        });
+        textures
+            .write(
+                self.atlas_texture,
+                renderer.queue(),
+                position.x,
+                position.y,
+                &image,
+            )
+            .ok()?;
+
+        let atlas_size = GLYPH_ATLAS_SIZE as f32;
+        let uv = UvRect::new(
+            position.x as f32 / atlas_size,
+            position.y as f32 / atlas_size,
+            width as f32 / atlas_size,
+            height as f32 / atlas_size,
+        );
+        self.glyphs.insert(key, uv);
+
+        return Some(uv);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assets::font::{BitmapFont, BitmapFontConfig},
+        camera::{Camera2D, Camera2DConfig},
+        render::renderer::Renderer2DConfig,
+    };
+
+    use super::*;
+
+    /// 8x8 glyphs from '!' to '~', a space is not part of the sheet
+    fn bitmap_font() -> Font {
+        return Font::Bitmap(BitmapFont::new(
+            AssetId::new(0),
+            glam::UVec2::new(128, 48),
+            BitmapFontConfig {
+                glyph_size: glam::UVec2::new(8, 8),
+                first_char: '!',
+                glyph_count: 94,
+            },
+        ));
+    }
+
+    fn vector_font() -> Font {
+        let bytes = include_bytes!("../../../assets/fonts/Cantarell-Regular.ttf");
+        return Font::from_bytes(bytes).unwrap();
+    }
+
+    fn xs(layout: &TextLayout) -> Vec<f32> {
+        return layout.glyphs.iter().map(|g| g.min.x).collect();
+    }
+
+    #[test]
+    fn test_layout_single_line() {
+        let layout = layout(&bitmap_font(), "ab c", 8.0, TextAlign::Left, None);
+
+        assert_eq!(xs(&layout), vec![0.0, 8.0, 24.0]);
+        assert_eq!(layout.glyphs[0].min.y, 0.0);
+        assert_eq!(layout.size, glam::Vec2::new(32.0, 8.0));
+    }
+
+    #[test]
+    fn test_layout_newline_and_alignment() {
+        let font = bitmap_font();
+
+        let right = layout(&font, "abc\nd", 8.0, TextAlign::Right, None);
+        assert_eq!(xs(&right), vec![0.0, 8.0, 16.0, 16.0]);
+        assert_eq!(right.glyphs[3].min.y, 8.0);
+        assert_eq!(right.size, glam::Vec2::new(24.0, 16.0));
+
+        let center = layout(&font, "abc\nd", 8.0, TextAlign::Center, Some(40.0));
+        assert_eq!(xs(&center), vec![8.0, 16.0, 24.0, 16.0]);
+    }
+
+    #[test]
+    fn test_layout_wraps_at_spaces() {
+        let left = layout(&bitmap_font(), "ab cd ef", 8.0, TextAlign::Left, Some(40.0));
+
+        let lines: Vec<(f32, f32)> = left.glyphs.iter().map(|g| (g.min.x, g.min.y)).collect();
+        assert_eq!(
+            lines,
+            vec![
+                (0.0, 0.0),
+                (8.0, 0.0),
+                (24.0, 0.0),
+                (32.0, 0.0),
+                (0.0, 8.0),
+                (8.0, 8.0)
+            ]
+        );
+        assert_eq!(left.size, glam::Vec2::new(40.0, 16.0));
+
+        // The trailing space and the word moved down are not part of the first line
+        let center = layout(
+            &bitmap_font(),
+            "ab cdef",
+            8.0,
+            TextAlign::Center,
+            Some(40.0),
+        );
+        assert_eq!(xs(&center), vec![12.0, 20.0, 4.0, 12.0, 20.0, 28.0]);
+
+        let right = layout(&bitmap_font(), "ab cdef", 8.0, TextAlign::Right, Some(40.0));
+        assert_eq!(xs(&right), vec![24.0, 32.0, 8.0, 16.0, 24.0, 32.0]);
+    }
+
+    #[test]
+    fn test_layout_breaks_long_words() {
+        let layout = layout(&bitmap_font(), "abcdef", 8.0, TextAlign::Left, Some(24.0));
+
+        let rows: Vec<f32> = layout.glyphs.iter().map(|g| g.min.y).collect();
+        assert_eq!(rows, vec![0.0, 0.0, 0.0, 8.0, 8.0, 8.0]);
+        assert_eq!(xs(&layout), vec![0.0, 8.0, 16.0, 0.0, 8.0, 16.0]);
+    }
+
+    #[test]
+    fn test_layout_vector_font() {
+        let font = vector_font();
+
+        let layout = layout(&font, "Hi\nthere", 15.6, TextAlign::Left, None);
+
+        assert_eq!(layout.glyphs.len(), 7);
+        assert!(layout.glyphs[0].min.y > 0.0);
+        assert!(layout.glyphs[1].min.x > layout.glyphs[0].min.x);
+        assert!(layout.glyphs[2].min.y > layout.glyphs[0].min.y);
+        assert_eq!(layout.size.y, 2.0 * font.line_height(16.0));
+    }
+
+    #[test]
+    fn test_full_atlas_lays_out_the_batch_again() {
+        let camera = Camera2D::new(Camera2DConfig {
+            position: glam::Vec2::ZERO,
+            zoom: 1.0,
+            viewport_size: glam::Vec2::new(64.0, 64.0),
+        });
+        let renderer = match pollster::block_on(Renderer2D::new_headless(
+            64,
+            64,
+            Renderer2DConfig { camera: &camera },
+        )) {
+            Ok(renderer) => renderer,
+            Err(e) if std::env::var_os("ENGINE_SKIP_GPU_TESTS").is_some() => {
+                eprintln!("skipping headless text test: {}", e);
+                return;
+            }
+            Err(e) => panic!(
+                "{}, set ENGINE_SKIP_GPU_TESTS=1 to skip the render tests",
+                e
+            ),
+        };
+
+        let mut assets = AssetsRegistry::new();
+        let font = assets.insert_font(vector_font());
+        let mut textures = GpuTextureManager::new();
+
+        // Room for the four glyphs of a frame, not for the seven of both
+        let mut text_renderer = TextRenderer::new(AssetId::new(1));
+        text_renderer.packer = GlyphPacker::new(30);
+
+        let mut prepare = |text_renderer: &mut TextRenderer, text: &str| -> Vec<SpriteDraw> {
+            let mut sprites = Vec::new();
+            text_renderer.prepare(
+                &[TextDraw::new(text, font, 16.0, glam::Vec2::ZERO)],
+                &assets,
+                &mut textures,
+                &renderer,
+                &mut sprites,
+            );
+            return sprites;
+        };
+
+        assert_eq!(prepare(&mut text_renderer, "abcd").len(), 4);
+
+        // 'a' is cached from the previous frame, the atlas fills on 'f'
+        let sprites = prepare(&mut text_renderer, "aefg");
+        assert_eq!(sprites.len(), 4);
+        assert_eq!(text_renderer.glyphs.len(), 4);
+        for (i, first) in sprites.iter().enumerate() {
+            for second in &sprites[i + 1..] {
+                let [u0, v0, w0, h0] = first.uv.to_array();
+                let [u1, v1, w1, h1] = second.uv.to_array();
+                let overlaps = u0 < u1 + w1 && u1 < u0 + w0 && v0 < v1 + h1 && v1 < v0 + h0;
+                assert!(!overlaps, "{:?} overlaps {:?}", first.uv, second.uv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_glyph_packer() {
+        let mut packer = GlyphPacker::new(16);
+
+        assert_eq!(packer.allocate(7, 4), Some(glam::UVec2::new(0, 0)));
+        assert_eq!(packer.allocate(7, 6), Some(glam::UVec2::new(8, 0)));
+        assert_eq!(packer.allocate(3, 3), Some(glam::UVec2::new(0, 7)));
+        assert_eq!(packer.allocate(16, 1), None);
+        assert_eq!(packer.allocate(10, 9), None);
+
+        packer.clear();
+        assert_eq!(packer.allocate(10, 9), Some(glam::UVec2::new(0, 0)));
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::assets::AssetId;
+use crate::assets::{error::AssetError, AssetId};
 
 pub struct GpuTexture {
     pub texture: wgpu::Texture,
@@ -9,14 +9,32 @@ pub struct GpuTexture {
     pub bind_group: wgpu::BindGroup,
 }
 
+/// Side of the cells of the missing texture checkerboard, in pixels
+const MISSING_CELL: u32 = 4;
+const MISSING_SIZE: u32 = MISSING_CELL * 4;
+
+/// Magenta and black checkerboard, drawn in place of textures that failed to load
+pub fn missing_texture_image() -> image::RgbaImage {
+    return image::RgbaImage::from_fn(MISSING_SIZE, MISSING_SIZE, |x, y| {
+        return match (x / MISSING_CELL + y / MISSING_CELL) % 2 {
+            0 => image::Rgba([255, 0, 255, 255]),
+            _ => image::Rgba([0, 0, 0, 255]),
+        };
+    });
+}
+
 pub struct GpuTextureManager {
     textures: HashMap<AssetId, GpuTexture>,
+
+    /// Drawn for ids without a texture, see `load_placeholder`
+    placeholder: Option<AssetId>,
 }
 
 impl GpuTextureManager {
     pub fn new() -> Self {
         return Self {
             textures: HashMap::new(),
+            placeholder: None,
         };
     }
 
@@ -25,13 +43,52 @@ impl GpuTextureManager {
         id: AssetId,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
         img: &image::RgbaImage,
     ) {
-        let dimensions = img.dimensions();
+        let (width, height) = img.dimensions();
+
+        self.create(id, device, layout, width, height);
+        write_texture(&self.textures[&id].texture, queue, 0, 0, img);
+    }
 
+    /// Uploads the missing texture as `id`, then draws it for every unknown id
+    pub fn load_placeholder(
+        &mut self,
+        id: AssetId,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+    ) {
+        self.load(id, device, queue, layout, &missing_texture_image());
+        self.placeholder = Some(id);
+    }
+
+    /// Writes the image into the texture, its top-left corner at `x`, `y`
+    pub fn write(
+        &self,
+        id: AssetId,
+        queue: &wgpu::Queue,
+        x: u32,
+        y: u32,
+        img: &image::RgbaImage,
+    ) -> Result<(), AssetError> {
+        write_texture(&self.get(id)?.texture, queue, x, y, img);
+        return Ok(());
+    }
+
+    /// Creates a transparent texture, filled later with `write`
+    pub fn create(
+        &mut self,
+        id: AssetId,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) {
         let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
@@ -46,58 +103,21 @@ impl GpuTextureManager {
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &img,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
-
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Nearest filtering keeps the pixel art sharp when zoomed in
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Nearest,
             min_filter: wgpu::FilterMode::Nearest,
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some(&id.value().to_string()),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-        });
-
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some(&id.value().to_string()),
-            layout: &bind_group_layout,
+            layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -120,10 +140,82 @@ impl GpuTextureManager {
         self.textures.insert(id, v);
     }
 
-    pub fn get(&self, id: AssetId) -> &GpuTexture {
+    /// Releases the GPU memory of the texture, `false` when there was none
+    pub fn remove(&mut self, id: AssetId) -> bool {
+        return self.textures.remove(&id).is_some();
+    }
+
+    pub fn contains(&self, id: AssetId) -> bool {
+        return self.textures.contains_key(&id);
+    }
+
+    pub fn get(&self, id: AssetId) -> Result<&GpuTexture, AssetError> {
+        return self.textures.get(&id).ok_or(AssetError::NotFound(id));
+    }
+
+    /// The texture, or the placeholder when there is none
+    pub fn get_or_placeholder(&self, id: AssetId) -> Option<&GpuTexture> {
         return self
             .textures
             .get(&id)
-            .expect("Tried to get unregistered GPU texture");
+            .or_else(|| self.placeholder.and_then(|p| self.textures.get(&p)));
+    }
+}
+
+fn write_texture(
+    texture: &wgpu::Texture,
+    queue: &wgpu::Queue,
+    x: u32,
+    y: u32,
+    img: &image::RgbaImage,
+) {
+    let dimensions = img.dimensions();
+
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d { x, y, z: 0 },
+            aspect: wgpu::TextureAspect::All,
+        },
+        img,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * dimensions.0),
+            rows_per_image: Some(dimensions.1),
+        },
+        wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_texture_checkerboard() {
+        let image = missing_texture_image();
+
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 255, 255]);
+        assert_eq!(image.get_pixel(MISSING_CELL, 0).0, [0, 0, 0, 255]);
+        assert_eq!(
+            image.get_pixel(MISSING_CELL, MISSING_CELL).0,
+            [255, 0, 255, 255]
+        );
+    }
+
+    #[test]
+    fn test_unknown_id() {
+        let textures = GpuTextureManager::new();
+
+        assert!(matches!(
+            textures.get(AssetId::new(4)),
+            Err(AssetError::NotFound(id)) if id == AssetId::new(4)
+        ));
+        assert!(textures.get_or_placeholder(AssetId::new(4)).is_none());
     }
 }
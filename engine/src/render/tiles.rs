@@ -1,4 +1,11 @@
-use crate::tilemap;
+use std::collections::HashMap;
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    tilemap::{self, TileAtlas, TileMap, CHUNK_SIZE},
+    Vertex,
+};
 
 pub struct TileChunkMesh {
     pub vertex_buffer: wgpu::Buffer,
@@ -6,10 +13,247 @@ pub struct TileChunkMesh {
     pub num_indices: u32,
 
     pub chunk_coords: (i32, i32),
-
-    pub dirty: bool,
 }
 
 impl TileChunkMesh {
-    pub fn from_chunk(chunk: tilemap::Chunk) {}
+    /// `None` when no tile of the chunk has a region in the atlas, wgpu cannot draw
+    /// from empty buffers
+    pub fn from_chunk(
+        device: &wgpu::Device,
+        chunk: &tilemap::Chunk,
+        atlas: &TileAtlas,
+    ) -> Option<Self> {
+        let (vertices, indices) = chunk_geometry(chunk, atlas);
+        if indices.is_empty() {
+            return None;
+        }
+
+        let label = format!("TILE_CHUNK_{}_{}", chunk.coords().x(), chunk.coords().y());
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        return Some(Self {
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+            chunk_coords: chunk.chunk_coords(),
+        });
+    }
+}
+
+/// One quad per tile with a region in the atlas
+///
+/// The z of a tile is its elevation in the ground layer: quads are ordered by z,
+/// so raised tiles are drawn over the lower ones
+fn chunk_geometry(chunk: &tilemap::Chunk, atlas: &TileAtlas) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE * 4);
+    let mut indices = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE * 6);
+
+    let mut tiles: Vec<&tilemap::Tile> = chunk.tiles().iter().collect();
+    tiles.sort_by_key(|tile| tile.coords().z());
+
+    let size = atlas.tile_size();
+    for tile in tiles {
+        let uv = match atlas.uv(tile.kind_id()) {
+            Some(uv) => uv,
+            None => continue,
+        };
+
+        let coords = tile.coords();
+        let min = glam::Vec2::new(coords.x() as f32, coords.y() as f32) * size;
+        let max = min + size;
+
+        // The v axis of the texture goes down while the y axis of the world goes up
+        let [u, v, w, h] = uv.to_array();
+        let corners = [
+            ([min.x, min.y], [u, v + h]),
+            ([max.x, min.y], [u + w, v + h]),
+            ([max.x, max.y], [u + w, v]),
+            ([min.x, max.y], [u, v]),
+        ];
+
+        let base = vertices.len() as u32;
+        for (position, tex_coords) in corners {
+            vertices.push(Vertex {
+                position: [position[0], position[1], 0.0],
+                tex_coords,
+                color: [1.0, 1.0, 1.0, 1.0],
+            });
+        }
+
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    return (vertices, indices);
+}
+
+/// Bottom-left and top-right corners of the chunk, in world space
+fn chunk_bounds(chunk_coords: (i32, i32), tile_size: glam::Vec2) -> (glam::Vec2, glam::Vec2) {
+    let size = tile_size * CHUNK_SIZE as f32;
+    let min = glam::Vec2::new(chunk_coords.0 as f32, chunk_coords.1 as f32) * size;
+
+    return (min, min + size);
+}
+
+fn is_visible(
+    chunk_coords: (i32, i32),
+    tile_size: glam::Vec2,
+    view: (glam::Vec2, glam::Vec2),
+) -> bool {
+    let (min, max) = chunk_bounds(chunk_coords, tile_size);
+    return min.x < view.1.x && max.x > view.0.x && min.y < view.1.y && max.y > view.0.y;
+}
+
+/// Meshes of the tile map chunks, keyed by chunk coordinates
+pub struct TileMeshCache {
+    meshes: HashMap<(i32, i32), TileChunkMesh>,
+}
+
+impl TileMeshCache {
+    pub fn new() -> Self {
+        return Self {
+            meshes: HashMap::new(),
+        };
+    }
+
+    /// Rebuilds the meshes of dirty chunks and drops the ones of removed chunks
+    pub fn prepare(&mut self, device: &wgpu::Device, map: &mut TileMap) {
+        self.meshes.retain(|coords, _| map.chunk(*coords).is_some());
+
+        let mut rebuilt = Vec::new();
+        for chunk in map.chunks().filter(|c| c.is_dirty()) {
+            let mesh = TileChunkMesh::from_chunk(device, chunk, map.atlas());
+            rebuilt.push((chunk.chunk_coords(), mesh));
+        }
+
+        for chunk in map.chunks_mut() {
+            chunk.mark_clean();
+        }
+
+        // A chunk left without drawable tiles loses its previous mesh
+        for (coords, mesh) in rebuilt {
+            match mesh {
+                Some(mesh) => self.meshes.insert(coords, mesh),
+                None => self.meshes.remove(&coords),
+            };
+        }
+    }
+
+    /// Meshes of the chunks intersecting the view
+    pub fn visible(
+        &self,
+        tile_size: glam::Vec2,
+        view: (glam::Vec2, glam::Vec2),
+    ) -> impl Iterator<Item = &TileChunkMesh> {
+        return self
+            .meshes
+            .values()
+            .filter(move |mesh| is_visible(mesh.chunk_coords, tile_size, view));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assets::AssetId,
+        math::{
+            coords::{Coords2D, Coords3D},
+            uv::UvRect,
+        },
+        tilemap::{Chunk, Tile},
+    };
+
+    use super::*;
+
+    fn chunk(origin: (i32, i32)) -> Chunk {
+        let tiles = std::array::from_fn(|i| {
+            let x = origin.0 + (i % CHUNK_SIZE) as i32;
+            let y = origin.1 + (i / CHUNK_SIZE) as i32;
+
+            return Tile::new(1, Coords3D::new(x, y, 0));
+        });
+
+        return Chunk::new(tiles);
+    }
+
+    fn atlas() -> TileAtlas {
+        let mut atlas = TileAtlas::new(AssetId::new(0), glam::Vec2::splat(16.0));
+        atlas.insert(1, UvRect::new(0.0, 0.0, 0.5, 0.5));
+        atlas.insert(2, UvRect::new(0.5, 0.0, 0.5, 0.5));
+
+        return atlas;
+    }
+
+    #[test]
+    fn test_chunk_geometry() {
+        let mut chunk = chunk((64, 0));
+        chunk.tile_at_mut(Coords2D::new(64, 0)).set_kind(2);
+        chunk.tile_at_mut(Coords2D::new(65, 0)).set_kind(0);
+
+        let (vertices, indices) = chunk_geometry(&chunk, &atlas());
+
+        // The tile of kind 0 has no region and is skipped
+        assert_eq!(vertices.len(), (CHUNK_SIZE * CHUNK_SIZE - 1) * 4);
+        assert_eq!(indices.len(), (CHUNK_SIZE * CHUNK_SIZE - 1) * 6);
+
+        assert_eq!(vertices[0].position, [1024.0, 0.0, 0.0]);
+        assert_eq!(vertices[0].tex_coords, [0.5, 0.5]);
+        assert_eq!(vertices[2].position, [1040.0, 16.0, 0.0]);
+        assert_eq!(vertices[2].tex_coords, [1.0, 0.0]);
+
+        // Second drawn tile is at x = 66
+        assert_eq!(vertices[4].position, [1056.0, 0.0, 0.0]);
+        assert_eq!(vertices[4].tex_coords, [0.0, 0.5]);
+    }
+
+    #[test]
+    fn test_elevated_tiles_are_drawn_last() {
+        let tiles = std::array::from_fn(|i| {
+            let x = (i % CHUNK_SIZE) as i32;
+            let y = (i / CHUNK_SIZE) as i32;
+            let z = match (x, y) {
+                (0, 0) => 1,
+                _ => 0,
+            };
+
+            return Tile::new(1, Coords3D::new(x, y, z));
+        });
+
+        let (vertices, _) = chunk_geometry(&Chunk::new(tiles), &atlas());
+
+        assert_eq!(vertices[0].position, [16.0, 0.0, 0.0]);
+        assert_eq!(vertices[vertices.len() - 4].position, [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_chunk_bounds() {
+        let (min, max) = chunk_bounds((-1, 2), glam::Vec2::splat(16.0));
+
+        assert_eq!(min, glam::Vec2::new(-1024.0, 2048.0));
+        assert_eq!(max, glam::Vec2::new(0.0, 3072.0));
+    }
+
+    #[test]
+    fn test_is_visible() {
+        let tile_size = glam::Vec2::splat(16.0);
+        let view = (
+            glam::Vec2::new(-400.0, -300.0),
+            glam::Vec2::new(400.0, 300.0),
+        );
+
+        assert!(is_visible((0, 0), tile_size, view));
+        assert!(is_visible((-1, -1), tile_size, view));
+        assert!(!is_visible((1, 0), tile_size, view));
+        assert!(!is_visible((0, -2), tile_size, view));
+    }
 }
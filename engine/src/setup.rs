@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use crate::{
+    assets::{
+        aseprite::{AsepriteAsset, AsepriteError, AsepriteFile},
+        atlas::{Atlas, AtlasError, AtlasFile},
+        error::AssetError,
+        font::{BitmapFont, BitmapFontConfig, Font},
+        packer::{AtlasPacker, PackError, PackedSprites},
+        texture::Texture,
+        AssetId, AssetsRegistry, Handle,
+    },
+    ecs::ECS,
+    EngineConfig,
+};
+
+/// Everything registered on the `Engine` before the window and the GPU exist
+///
+/// The setup is consumed when `Internal` is created: the textures are uploaded,
+/// then the startup systems run with every resource already in place
+pub struct Setup {
+    pub config: EngineConfig,
+    pub ecs: ECS,
+    pub assets_registry: AssetsRegistry,
+
+    /// Textures reserved in the registry that are still waiting for the GPU
+    pub pending_textures: Vec<(AssetId, String)>,
+
+    /// Textures built in memory, e.g. aseprite sheets, waiting for the GPU
+    pub pending_images: Vec<(AssetId, image::RgbaImage)>,
+}
+
+impl Setup {
+    pub fn new(config: EngineConfig) -> Self {
+        return Self {
+            config,
+            ecs: ECS::new(),
+            assets_registry: AssetsRegistry::new(),
+            pending_textures: Vec::new(),
+            pending_images: Vec::new(),
+        };
+    }
+
+    /// Reserves the texture id right away, the file is loaded after GPU init. Loading
+    /// a file again returns the handle of the first load while it is alive
+    ///
+    /// Dropping the last handle unloads the texture, the caller has to keep it
+    pub fn load_texture(&mut self, path: &str) -> Handle<Texture> {
+        if let Some(handle) = self.assets_registry.texture_by_path(path) {
+            return handle;
+        }
+
+        let handle = self
+            .assets_registry
+            .add_texture(Texture::new(), Some(Path::new(path)));
+        self.pending_textures.push((handle.id(), path.to_string()));
+
+        return handle;
+    }
+
+    /// Fonts need no GPU upload, their glyphs are rasterized when first drawn
+    pub fn load_font(&mut self, path: &str) -> Result<AssetId, AssetError> {
+        let bytes = std::fs::read(path).map_err(|e| AssetError::io(path, e))?;
+        let font = Font::from_bytes(&bytes).map_err(|message| AssetError::Font {
+            path: path.into(),
+            message: message.to_string(),
+        })?;
+
+        return Ok(self.assets_registry.insert_font(font));
+    }
+
+    /// The sheet is queued like any texture, only its size is read right away
+    pub fn load_bitmap_font(
+        &mut self,
+        path: &str,
+        config: BitmapFontConfig,
+    ) -> Result<AssetId, AssetError> {
+        let (width, height) =
+            image::image_dimensions(path).map_err(|e| AssetError::image(path, e))?;
+        let texture = self.load_texture(path);
+        let font = BitmapFont::new(texture.id(), glam::UVec2::new(width, height), config)
+            .with_texture_handle(texture);
+
+        return Ok(self.assets_registry.insert_font(Font::Bitmap(font)));
+    }
+
+    /// Reads the atlas description and the size of its texture right away, the
+    /// texture itself is queued like any other
+    pub fn load_atlas(&mut self, path: &str) -> Result<Atlas, AtlasError> {
+        let file = AtlasFile::load(path)?;
+        let texture = self.load_texture(&file.texture_path().to_string_lossy());
+        let atlas = file.build(texture.id())?.with_texture_handle(texture);
+
+        return Ok(atlas);
+    }
+
+    /// Packs the images right away, each page is queued for upload as its own texture
+    pub fn pack_atlas(&mut self, packer: &AtlasPacker) -> Result<PackedSprites, PackError> {
+        let atlas = packer.pack()?;
+        let mut textures = Vec::with_capacity(atlas.pages.len());
+
+        for page in &atlas.pages {
+            let id = self.assets_registry.insert_texture(Texture::new());
+            self.pending_images.push((id, page.clone()));
+            textures.push(id);
+        }
+
+        return Ok(atlas.sprites(&textures));
+    }
+
+    /// Flattens the frames right away and registers their sprites and clips, the
+    /// sheet of frames is queued for upload like any texture
+    pub fn load_aseprite(&mut self, path: &str) -> Result<AsepriteAsset, AsepriteError> {
+        let file = AsepriteFile::load(path)?;
+        let sheet = file.sheet();
+        let texture = self.assets_registry.insert_texture(Texture::new());
+        let asset = file.register(&sheet, texture, &mut self.assets_registry);
+
+        self.pending_images.push((texture, sheet.image));
+
+        return Ok(asset);
+    }
+}
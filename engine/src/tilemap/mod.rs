@@ -1,6 +1,12 @@
+use std::collections::HashMap;
+
 use crate::{
     assets::AssetId,
-    math::coords::{Coords2D, Coords3D},
+    ecs::resource::Resource,
+    math::{
+        coords::{Coords2D, Coords3D},
+        uv::UvRect,
+    },
 };
 
 struct TileKind {
@@ -23,9 +29,17 @@ impl Tile {
     pub fn coords(&self) -> Coords3D<i32> {
         return self.coords;
     }
+
+    pub fn kind_id(&self) -> u16 {
+        return self.kind_id;
+    }
+
+    pub fn set_kind(&mut self, kind_id: u16) {
+        self.kind_id = kind_id;
+    }
 }
 
-const CHUNK_SIZE: usize = 64;
+pub const CHUNK_SIZE: usize = 64;
 
 pub struct Chunk {
     /// The tiles contained in the chunk
@@ -34,6 +48,9 @@ pub struct Chunk {
 
     /// Coordinates of the left-bottom tile of the chunk
     coords: Coords2D<i32>,
+
+    /// Set when a tile may have changed since the chunk mesh was built
+    dirty: bool,
 }
 
 impl Chunk {
@@ -42,9 +59,46 @@ impl Chunk {
     ///
     /// # Arguments
     /// * `tiles` - chunk tiles ordered from left to right, bottom to top
+    ///
+    /// The first tile must sit on a multiple of `CHUNK_SIZE`, the tile map finds the
+    /// chunk of a tile by dividing its coordinates
     pub fn new(tiles: [Tile; CHUNK_SIZE * CHUNK_SIZE]) -> Self {
         let coords = tiles[0].coords().to_2d();
-        return Self { tiles, coords };
+        let size = CHUNK_SIZE as i32;
+        assert!(
+            coords.x().rem_euclid(size) == 0 && coords.y().rem_euclid(size) == 0,
+            "A chunk must start on a multiple of CHUNK_SIZE, got ({}, {})",
+            coords.x(),
+            coords.y()
+        );
+
+        return Self {
+            tiles,
+            coords,
+            dirty: true,
+        };
+    }
+
+    /// Coordinates of the left-bottom tile of the chunk
+    pub fn coords(&self) -> Coords2D<i32> {
+        return self.coords;
+    }
+
+    /// Position of the chunk in the grid of chunks
+    pub fn chunk_coords(&self) -> (i32, i32) {
+        return chunk_coords_of(self.coords);
+    }
+
+    pub fn tiles(&self) -> &[Tile] {
+        return &self.tiles;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        return self.dirty;
+    }
+
+    pub(crate) fn mark_clean(&mut self) {
+        self.dirty = false;
     }
 
     fn contains(&self, coords: Coords2D<i32>) -> bool {
@@ -106,12 +160,115 @@ impl Chunk {
         return &self.tiles[idx];
     }
 
+    /// Marks the chunk as dirty, the mesh is rebuilt before the next frame
     pub fn tile_at_mut(&mut self, coords: Coords2D<i32>) -> &mut Tile {
         let idx = self.index_of(coords);
+        self.dirty = true;
         return &mut self.tiles[idx];
     }
 }
 
+/// Position in the grid of chunks of the chunk containing the tile
+pub fn chunk_coords_of(coords: Coords2D<i32>) -> (i32, i32) {
+    let size = CHUNK_SIZE as i32;
+    return (coords.x().div_euclid(size), coords.y().div_euclid(size));
+}
+
+/// Atlas region drawn for each tile kind
+pub struct TileAtlas {
+    texture: AssetId,
+
+    /// Size of a tile in world units
+    tile_size: glam::Vec2,
+
+    uvs: HashMap<u16, UvRect>,
+}
+
+impl TileAtlas {
+    pub fn new(texture: AssetId, tile_size: glam::Vec2) -> Self {
+        return Self {
+            texture,
+            tile_size,
+            uvs: HashMap::new(),
+        };
+    }
+
+    pub fn insert(&mut self, kind_id: u16, uv: UvRect) {
+        self.uvs.insert(kind_id, uv);
+    }
+
+    pub fn texture(&self) -> AssetId {
+        return self.texture;
+    }
+
+    pub fn tile_size(&self) -> glam::Vec2 {
+        return self.tile_size;
+    }
+
+    /// `None` for kinds without a region, such tiles are not drawn
+    pub fn uv(&self, kind_id: u16) -> Option<UvRect> {
+        return self.uvs.get(&kind_id).copied();
+    }
+}
+
+/// Chunks of the world map, inserted as a resource by the game
+///
+/// The renderer keeps one mesh per chunk and rebuilds it only when the chunk is dirty
+pub struct TileMap {
+    atlas: TileAtlas,
+    chunks: HashMap<(i32, i32), Chunk>,
+}
+
+impl Resource for TileMap {}
+
+impl TileMap {
+    pub fn new(atlas: TileAtlas) -> Self {
+        return Self {
+            atlas,
+            chunks: HashMap::new(),
+        };
+    }
+
+    pub fn atlas(&self) -> &TileAtlas {
+        return &self.atlas;
+    }
+
+    /// Inserts the chunk, replacing the one at the same position
+    pub fn insert_chunk(&mut self, chunk: Chunk) -> Option<Chunk> {
+        return self.chunks.insert(chunk.chunk_coords(), chunk);
+    }
+
+    pub fn remove_chunk(&mut self, chunk_coords: (i32, i32)) -> Option<Chunk> {
+        return self.chunks.remove(&chunk_coords);
+    }
+
+    pub fn chunk(&self, chunk_coords: (i32, i32)) -> Option<&Chunk> {
+        return self.chunks.get(&chunk_coords);
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+        return self.chunks.values();
+    }
+
+    pub(crate) fn chunks_mut(&mut self) -> impl Iterator<Item = &mut Chunk> {
+        return self.chunks.values_mut();
+    }
+
+    pub fn tile_at(&self, coords: Coords2D<i32>) -> Option<&Tile> {
+        return self
+            .chunks
+            .get(&chunk_coords_of(coords))
+            .map(|chunk| chunk.tile_at(coords));
+    }
+
+    pub fn tile_at_mut(&mut self, coords: Coords2D<i32>) -> Option<&mut Tile> {
+        return self
+            .chunks
+            .get_mut(&chunk_coords_of(coords))
+            .map(|chunk| chunk.tile_at_mut(coords));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::panic;
@@ -145,6 +302,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_tile_at_mut_marks_dirty() {
+        let tiles: [Tile; CHUNK_SIZE * CHUNK_SIZE] = std::array::from_fn(|i| {
+            let x = (i % CHUNK_SIZE) as i32;
+            let y = (i / CHUNK_SIZE) as i32;
+
+            let coords = Coords3D::new(x - 64, y, 0);
+            return Tile::new(0, coords);
+        });
+
+        let mut chunk = Chunk::new(tiles);
+        assert_eq!(chunk.chunk_coords(), (-1, 0));
+        assert!(chunk.is_dirty());
+
+        chunk.mark_clean();
+        chunk.tile_at(Coords2D::new(-1, 3));
+        assert!(!chunk.is_dirty());
+
+        chunk.tile_at_mut(Coords2D::new(-1, 3)).set_kind(2);
+        assert!(chunk.is_dirty());
+
+        let mut map = TileMap::new(TileAtlas::new(AssetId::new(0), glam::Vec2::ONE));
+        map.insert_chunk(chunk);
+        assert_eq!(map.tile_at(Coords2D::new(-1, 3)).unwrap().kind_id(), 2);
+        assert!(map.tile_at(Coords2D::new(0, 3)).is_none());
+    }
+
     #[test]
     fn test_tile_at_out_of_bounds() {
         let tiles: [Tile; CHUNK_SIZE * CHUNK_SIZE] = std::array::from_fn(|i| {
@@ -172,4 +356,17 @@ mod tests {
             assert!(r.is_err());
         }
     }
+
+    #[test]
+    #[should_panic(expected = "A chunk must start on a multiple of CHUNK_SIZE")]
+    fn test_unaligned_chunk() {
+        let tiles: [Tile; CHUNK_SIZE * CHUNK_SIZE] = std::array::from_fn(|i| {
+            let x = (i % CHUNK_SIZE) as i32;
+            let y = (i / CHUNK_SIZE) as i32;
+
+            return Tile::new(0, Coords3D::new(x + 10, y - 64, 0));
+        });
+
+        Chunk::new(tiles);
+    }
 }
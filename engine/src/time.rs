@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use crate::ecs::resource::Resource;
+
+pub struct TimestepConfig {
+    /// Simulation steps per second
+    pub tick_rate: u32,
+
+    /// Maximum simulation steps run in a single frame
+    ///
+    /// When a frame takes longer than this many steps, the remaining time is dropped
+    /// instead of trying to catch up, so a slow frame cannot snowball into slower ones
+    pub max_catch_up_steps: u32,
+}
+
+impl Default for TimestepConfig {
+    fn default() -> Self {
+        return Self {
+            tick_rate: 60,
+            max_catch_up_steps: 5,
+        };
+    }
+}
+
+/// Accumulates real frame time and converts it into fixed simulation steps
+///
+/// Running the simulation with a constant delta keeps it deterministic no matter the
+/// frame rate, while rendering interpolates between steps using `alpha`
+pub struct FixedTimestep {
+    step: Duration,
+    max_catch_up_steps: u32,
+
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(config: TimestepConfig) -> Self {
+        if config.tick_rate == 0 {
+            panic!("tick rate must be positive");
+        }
+
+        return Self {
+            step: Duration::from_secs(1) / config.tick_rate,
+            max_catch_up_steps: config.max_catch_up_steps,
+            accumulator: Duration::ZERO,
+        };
+    }
+
+    pub fn step(&self) -> Duration {
+        return self.step;
+    }
+
+    /// Adds the frame time, returning how many simulation steps must run this frame
+    pub fn advance(&mut self, frame_delta: Duration) -> u32 {
+        self.accumulator += frame_delta;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step && steps < self.max_catch_up_steps {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        if self.accumulator >= self.step {
+            // Too far behind, keep only the progress towards the next step
+            self.accumulator =
+                Duration::from_nanos((self.accumulator.as_nanos() % self.step.as_nanos()) as u64);
+        }
+
+        return steps;
+    }
+
+    /// How far the current frame is between the last step and the next one, in `[0, 1)`
+    pub fn alpha(&self) -> f32 {
+        return self.accumulator.as_secs_f32() / self.step.as_secs_f32();
+    }
+}
+
+/// Clock shared with the systems as a resource
+pub struct Time {
+    fixed_delta: Duration,
+    frame_delta: Duration,
+    elapsed: Duration,
+    tick: u64,
+    alpha: f32,
+}
+
+impl Resource for Time {}
+
+impl Time {
+    pub fn new(fixed_delta: Duration) -> Self {
+        return Self {
+            fixed_delta,
+            frame_delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            tick: 0,
+            alpha: 0.0,
+        };
+    }
+
+    /// Duration of a simulation step
+    pub fn fixed_delta(&self) -> Duration {
+        return self.fixed_delta;
+    }
+
+    /// Real time between the last two frames
+    pub fn frame_delta(&self) -> Duration {
+        return self.frame_delta;
+    }
+
+    /// Simulated time, advanced by `fixed_delta` on every step
+    pub fn elapsed(&self) -> Duration {
+        return self.elapsed;
+    }
+
+    /// Number of simulation steps run so far
+    pub fn tick(&self) -> u64 {
+        return self.tick;
+    }
+
+    /// Interpolation factor between the previous and the current simulation state,
+    /// meant for the `RenderExtract` stage
+    pub fn alpha(&self) -> f32 {
+        return self.alpha;
+    }
+
+    pub(crate) fn advance_step(&mut self) {
+        self.elapsed += self.fixed_delta;
+        self.tick += 1;
+    }
+
+    pub(crate) fn set_frame(&mut self, frame_delta: Duration, alpha: f32) {
+        self.frame_delta = frame_delta;
+        self.alpha = alpha;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestep() -> FixedTimestep {
+        return FixedTimestep::new(TimestepConfig {
+            tick_rate: 10,
+            max_catch_up_steps: 3,
+        });
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut t = timestep();
+
+        assert_eq!(t.advance(Duration::from_millis(50)), 0);
+        assert!((t.alpha() - 0.5).abs() < 1e-6);
+
+        assert_eq!(t.advance(Duration::from_millis(60)), 1);
+        assert!((t.alpha() - 0.1).abs() < 1e-6);
+
+        assert_eq!(t.advance(Duration::from_millis(190)), 2);
+        assert!(t.alpha().abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_max_catch_up_steps() {
+        let mut t = timestep();
+
+        assert_eq!(t.advance(Duration::from_millis(1050)), 3);
+        assert!((t.alpha() - 0.5).abs() < 1e-6);
+
+        assert_eq!(t.advance(Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn test_steps_do_not_depend_on_frame_rate() {
+        let mut slow = timestep();
+        let mut fast = timestep();
+
+        let slow_steps: u32 = (0..10)
+            .map(|_| slow.advance(Duration::from_millis(100)))
+            .sum();
+        let fast_steps: u32 = (0..100)
+            .map(|_| fast.advance(Duration::from_millis(10)))
+            .sum();
+
+        assert_eq!(slow_steps, 10);
+        assert_eq!(fast_steps, 10);
+    }
+
+    #[test]
+    fn test_time() {
+        let mut time = Time::new(Duration::from_millis(100));
+
+        time.advance_step();
+        time.advance_step();
+        time.set_frame(Duration::from_millis(16), 0.25);
+
+        assert_eq!(time.tick(), 2);
+        assert_eq!(time.elapsed(), Duration::from_millis(200));
+        assert_eq!(time.frame_delta(), Duration::from_millis(16));
+        assert_eq!(time.alpha(), 0.25);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::{
+    assets::{AssetId, AssetsRegistry},
+    math::uv::UvRect,
+    render::{
+        layer::RenderLayer,
+        sprite::SpriteDraw,
+        text::{self, TextAlign, TextDraw},
+    },
+    ui::{Align, Direction, Root, Ui, UiRect, Widget},
+};
+
+/// Sprites, texts and hit regions of the UI on a frame
+pub(crate) struct UiFrame {
+    pub sprites: Vec<SpriteDraw>,
+    pub texts: Vec<TextDraw>,
+    pub regions: Vec<(String, UiRect)>,
+    pub blocking: Vec<UiRect>,
+}
+
+/// Lays the roots out in the viewport, in submission order
+///
+/// Each root is drawn at the elevation of its index, so a later root covers the
+/// texts of an earlier one too. Solid backgrounds sample the `white` texture
+pub(crate) fn build(
+    roots: &[Root],
+    ui: &Ui,
+    viewport: glam::Vec2,
+    assets: &AssetsRegistry,
+    white: AssetId,
+) -> UiFrame {
+    let mut builder = FrameBuilder {
+        ui,
+        assets,
+        white,
+        elevation: 0,
+        frame: UiFrame {
+            sprites: Vec::new(),
+            texts: Vec::new(),
+            regions: Vec::new(),
+            blocking: Vec::new(),
+        },
+    };
+
+    for (i, root) in roots.iter().enumerate() {
+        let widget = Widget::Panel(root.panel.clone());
+        let size = measure(&widget, assets);
+        let factors = root.anchor.factors();
+        let min = viewport * factors + root.offset - size * factors;
+
+        builder.elevation = i as i32;
+        builder.frame.blocking.push(UiRect::new(min, size));
+        builder.arrange(&widget, UiRect::new(min, size));
+    }
+
+    return builder.frame;
+}
+
+/// Size a widget needs for its content
+pub(crate) fn measure(widget: &Widget, assets: &AssetsRegistry) -> glam::Vec2 {
+    return match widget {
+        Widget::Panel(panel) => {
+            let sizes: Vec<glam::Vec2> =
+                panel.children.iter().map(|c| measure(c, assets)).collect();
+            let gaps = panel.spacing * sizes.len().saturating_sub(1) as f32;
+
+            let content = match panel.direction {
+                Direction::Vertical => glam::Vec2::new(
+                    sizes.iter().map(|s| s.x).fold(0.0, f32::max),
+                    sizes.iter().map(|s| s.y).sum::<f32>() + gaps,
+                ),
+                Direction::Horizontal => glam::Vec2::new(
+                    sizes.iter().map(|s| s.x).sum::<f32>() + gaps,
+                    sizes.iter().map(|s| s.y).fold(0.0, f32::max),
+                ),
+            };
+
+            (content + glam::Vec2::splat(2.0 * panel.padding)).max(panel.min_size)
+        }
+        Widget::Label(label) => match assets.font(label.font) {
+            Some(font) => {
+                text::layout(
+                    font,
+                    &label.text,
+                    label.size,
+                    TextAlign::Left,
+                    label.max_width,
+                )
+                .size
+            }
+            None => glam::Vec2::ZERO,
+        },
+        Widget::Button(button) => {
+            let label = measure(&Widget::Label(button.label.clone()), assets);
+            (label + glam::Vec2::splat(2.0 * button.padding)).max(button.min_size)
+        }
+        Widget::Image(image) => image.size,
+        Widget::Spacer(size) => *size,
+    };
+}
+
+struct FrameBuilder<'a> {
+    ui: &'a Ui,
+    assets: &'a AssetsRegistry,
+    white: AssetId,
+    elevation: i32,
+    frame: UiFrame,
+}
+
+impl FrameBuilder<'_> {
+    fn arrange(&mut self, widget: &Widget, rect: UiRect) {
+        match widget {
+            Widget::Panel(panel) => {
+                if let Some(color) = panel.background {
+                    self.solid(rect, color);
+                }
+
+                if let Some(id) = &panel.id {
+                    self.frame.regions.push((id.clone(), rect));
+                }
+
+                let inner_size = rect.size - glam::Vec2::splat(2.0 * panel.padding);
+                let mut cursor = rect.min + glam::Vec2::splat(panel.padding);
+
+                for child in &panel.children {
+                    let size = measure(child, self.assets);
+
+                    let min = match panel.direction {
+                        Direction::Vertical => glam::Vec2::new(
+                            cursor.x + align(panel.align, inner_size.x, size.x),
+                            cursor.y,
+                        ),
+                        Direction::Horizontal => glam::Vec2::new(
+                            cursor.x,
+                            cursor.y + align(panel.align, inner_size.y, size.y),
+                        ),
+                    };
+
+                    self.arrange(child, UiRect::new(min, size));
+
+                    match panel.direction {
+                        Direction::Vertical => cursor.y += size.y + panel.spacing,
+                        Direction::Horizontal => cursor.x += size.x + panel.spacing,
+                    }
+                }
+            }
+            Widget::Label(label) => {
+                let mut text = TextDraw::new(&label.text, label.font, label.size, rect.min)
+                    .with_color(label.color)
+                    .with_elevation(self.elevation)
+                    .in_screen_space();
+                text.max_width = label.max_width;
+
+                self.frame.texts.push(text);
+            }
+            Widget::Button(button) => {
+                let color = if self.ui.pressed(&button.id) {
+                    button.style.pressed
+                } else if self.ui.hovered(&button.id) {
+                    button.style.hovered
+                } else {
+                    button.style.normal
+                };
+                self.solid(rect, color);
+                self.frame.regions.push((button.id.clone(), rect));
+
+                // Centered in the button when it is larger than its label
+                let label = Widget::Label(button.label.clone());
+                let size = measure(&label, self.assets);
+                let min = rect.min + 0.5 * (rect.size - size);
+                self.arrange(&label, UiRect::new(min, size));
+            }
+            Widget::Image(image) => {
+                self.frame.sprites.push(
+                    SpriteDraw::from_uv(image.texture, image.uv, rect.center(), rect.size)
+                        .with_tint(image.tint)
+                        .with_layer(RenderLayer::Ui)
+                        .with_elevation(self.elevation)
+                        .in_screen_space(),
+                );
+
+                if let Some(id) = &image.id {
+                    self.frame.regions.push((id.clone(), rect));
+                }
+            }
+            Widget::Spacer(_) => {}
+        }
+    }
+
+    fn solid(&mut self, rect: UiRect, color: [f32; 4]) {
+        self.frame.sprites.push(
+            SpriteDraw::from_uv(
+                self.white,
+                UvRect::new(0.0, 0.0, 1.0, 1.0),
+                rect.center(),
+                rect.size,
+            )
+            .with_tint(color)
+            .with_layer(RenderLayer::Ui)
+            .with_elevation(self.elevation)
+            .in_screen_space(),
+        );
+    }
+}
+
+/// Offset of a child across the stacking axis of its panel
+fn align(align: Align, available: f32, size: f32) -> f32 {
+    return match align {
+        Align::Start => 0.0,
+        Align::Center => 0.5 * (available - size),
+        Align::End => available - size,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assets::font::{BitmapFont, BitmapFontConfig, Font},
+        ui::{Anchor, Button, Image, Label, Panel},
+    };
+
+    use super::*;
+
+    const WHITE: AssetId = AssetId::new(100);
+
+    /// Registry with an 8x8 bitmap font covering '!' to '~'
+    fn assets() -> (AssetsRegistry, AssetId) {
+        let mut assets = AssetsRegistry::new();
+        let font = assets.insert_font(Font::Bitmap(BitmapFont::new(
+            AssetId::new(50),
+            glam::UVec2::new(128, 48),
+            BitmapFontConfig {
+                glyph_size: glam::UVec2::new(8, 8),
+                first_char: '!',
+                glyph_count: 94,
+            },
+        )));
+
+        return (assets, font);
+    }
+
+    fn slot(id: &str) -> Image {
+        return Image::from_uv(
+            AssetId::new(7),
+            UvRect::new(0.0, 0.0, 1.0, 1.0),
+            glam::Vec2::splat(16.0),
+        )
+        .with_id(id);
+    }
+
+    #[test]
+    fn test_stacking_and_padding() {
+        let (assets, font) = assets();
+        let panel = Panel::vertical()
+            .with_padding(4.0)
+            .with_spacing(2.0)
+            .child(Label::new("Shop", font, 8.0))
+            .child(Panel::horizontal().child(slot("a")).child(slot("b")));
+
+        let size = measure(&Widget::Panel(panel.clone()), &assets);
+        assert_eq!(size, glam::Vec2::new(40.0, 34.0));
+
+        let mut ui = Ui::new();
+        ui.add(Anchor::TopLeft, glam::Vec2::new(10.0, 10.0), panel);
+        let frame = build(
+            &ui.take_roots(),
+            &ui,
+            glam::Vec2::new(200.0, 100.0),
+            &assets,
+            WHITE,
+        );
+
+        assert_eq!(frame.texts[0].position, glam::Vec2::new(14.0, 14.0));
+        assert_eq!(
+            frame.regions,
+            vec![
+                (
+                    "a".to_string(),
+                    UiRect::new(glam::Vec2::new(14.0, 24.0), glam::Vec2::splat(16.0))
+                ),
+                (
+                    "b".to_string(),
+                    UiRect::new(glam::Vec2::new(30.0, 24.0), glam::Vec2::splat(16.0))
+                ),
+            ]
+        );
+        assert_eq!(
+            frame.blocking,
+            vec![UiRect::new(
+                glam::Vec2::new(10.0, 10.0),
+                glam::Vec2::new(40.0, 34.0)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_anchoring() {
+        let (assets, _) = assets();
+        let mut ui = Ui::new();
+        let panel = || Panel::vertical().with_min_size(glam::Vec2::new(40.0, 20.0));
+
+        ui.add(Anchor::BottomRight, glam::Vec2::new(-5.0, -5.0), panel());
+        ui.add(Anchor::Center, glam::Vec2::ZERO, panel());
+        ui.add(Anchor::Top, glam::Vec2::new(0.0, 5.0), panel());
+
+        let frame = build(
+            &ui.take_roots(),
+            &ui,
+            glam::Vec2::new(200.0, 100.0),
+            &assets,
+            WHITE,
+        );
+
+        let mins: Vec<glam::Vec2> = frame.blocking.iter().map(|r| r.min).collect();
+        assert_eq!(
+            mins,
+            vec![
+                glam::Vec2::new(155.0, 75.0),
+                glam::Vec2::new(80.0, 40.0),
+                glam::Vec2::new(80.0, 5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cross_alignment() {
+        let (assets, _) = assets();
+        let panel = Panel::vertical()
+            .with_align(Align::Center)
+            .with_min_size(glam::Vec2::new(48.0, 0.0))
+            .child(slot("a"));
+
+        let mut ui = Ui::new();
+        ui.add(Anchor::TopLeft, glam::Vec2::ZERO, panel);
+        let frame = build(
+            &ui.take_roots(),
+            &ui,
+            glam::Vec2::splat(100.0),
+            &assets,
+            WHITE,
+        );
+
+        assert_eq!(frame.regions[0].1.min, glam::Vec2::new(16.0, 0.0));
+    }
+
+    #[test]
+    fn test_button_state_colors() {
+        let (assets, font) = assets();
+        let style = crate::ui::ButtonStyle::default();
+        let panel = || {
+            Panel::vertical().child(
+                Button::new("buy", Label::new("Buy", font, 8.0))
+                    .with_min_size(glam::Vec2::new(40.0, 16.0)),
+            )
+        };
+
+        let mut ui = Ui::new();
+        ui.add(Anchor::TopLeft, glam::Vec2::ZERO, panel());
+        let frame = build(
+            &ui.take_roots(),
+            &ui,
+            glam::Vec2::splat(100.0),
+            &assets,
+            WHITE,
+        );
+        assert_eq!(frame.sprites[0].tint, style.normal);
+
+        // Label centered in the button
+        assert_eq!(frame.texts[0].position, glam::Vec2::new(8.0, 4.0));
+
+        ui.set_layout(frame.regions, frame.blocking);
+        ui.pointer_moved(Some(glam::Vec2::new(20.0, 8.0)));
+        ui.add(Anchor::TopLeft, glam::Vec2::ZERO, panel());
+        let frame = build(
+            &ui.take_roots(),
+            &ui,
+            glam::Vec2::splat(100.0),
+            &assets,
+            WHITE,
+        );
+        assert_eq!(frame.sprites[0].tint, style.hovered);
+    }
+
+    #[test]
+    fn test_later_roots_are_drawn_higher() {
+        let (assets, font) = assets();
+        let mut ui = Ui::new();
+        ui.add(
+            Anchor::TopLeft,
+            glam::Vec2::ZERO,
+            Panel::vertical().child(Label::new("a", font, 8.0)),
+        );
+        ui.add(
+            Anchor::TopLeft,
+            glam::Vec2::ZERO,
+            Panel::vertical()
+                .with_background([0.0, 0.0, 0.0, 1.0])
+                .with_min_size(glam::Vec2::splat(8.0)),
+        );
+
+        let frame = build(
+            &ui.take_roots(),
+            &ui,
+            glam::Vec2::splat(100.0),
+            &assets,
+            WHITE,
+        );
+
+        assert_eq!(frame.texts[0].elevation, 0);
+        assert_eq!(frame.sprites[0].elevation, 1);
+    }
+}
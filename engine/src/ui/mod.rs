@@ -0,0 +1,531 @@
+//! Screen-space UI positioned in viewport pixels, independent of the camera
+//!
+//! Like sprites, the UI is submitted every frame: systems add root panels to the `Ui`
+//! resource from the `RenderExtract` stage. The engine lays them out, draws them over
+//! the world and hit-tests the widgets with an id against the cursor. Hover and click
+//! results are read back from `Ui` by id on the next frame
+
+use crate::{
+    assets::{sprite::Sprite, AssetId},
+    ecs::resource::Resource,
+    math::uv::UvRect,
+};
+
+pub(crate) mod layout;
+
+/// Point of the viewport a root panel is attached to, and the matching point of the panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Position of the anchor as a fraction of a size, y going down
+    pub fn factors(self) -> glam::Vec2 {
+        let (x, y) = match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        };
+
+        return glam::Vec2::new(x, y);
+    }
+}
+
+/// Axis a panel stacks its children along
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Vertical,
+    Horizontal,
+}
+
+/// Placement of the children of a panel across its stacking axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+/// Rectangle in viewport pixels, y going down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UiRect {
+    /// Top-left corner
+    pub min: glam::Vec2,
+    pub size: glam::Vec2,
+}
+
+impl UiRect {
+    pub fn new(min: glam::Vec2, size: glam::Vec2) -> Self {
+        return Self { min, size };
+    }
+
+    pub fn max(&self) -> glam::Vec2 {
+        return self.min + self.size;
+    }
+
+    pub fn center(&self) -> glam::Vec2 {
+        return self.min + 0.5 * self.size;
+    }
+
+    pub fn contains(&self, point: glam::Vec2) -> bool {
+        let max = self.max();
+        return point.x >= self.min.x
+            && point.y >= self.min.y
+            && point.x < max.x
+            && point.y < max.y;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Widget {
+    Panel(Panel),
+    Label(Label),
+    Button(Button),
+    Image(Image),
+
+    /// Empty space
+    Spacer(glam::Vec2),
+}
+
+/// Container stacking its children, optionally over a solid background
+#[derive(Debug, Clone)]
+pub struct Panel {
+    pub id: Option<String>,
+    pub direction: Direction,
+    pub align: Align,
+
+    /// Space between the border and the children
+    pub padding: f32,
+
+    /// Space between two children
+    pub spacing: f32,
+    pub background: Option<[f32; 4]>,
+
+    /// The panel grows to fit its children, but never shrinks below this size
+    pub min_size: glam::Vec2,
+    pub children: Vec<Widget>,
+}
+
+impl Panel {
+    pub fn new(direction: Direction) -> Self {
+        return Self {
+            id: None,
+            direction,
+            align: Align::Start,
+            padding: 0.0,
+            spacing: 0.0,
+            background: None,
+            min_size: glam::Vec2::ZERO,
+            children: Vec::new(),
+        };
+    }
+
+    pub fn vertical() -> Self {
+        return Self::new(Direction::Vertical);
+    }
+
+    pub fn horizontal() -> Self {
+        return Self::new(Direction::Horizontal);
+    }
+
+    /// Makes the panel hoverable and clickable
+    pub fn with_id(mut self, id: &str) -> Self {
+        self.id = Some(id.to_string());
+        return self;
+    }
+
+    pub fn with_align(mut self, align: Align) -> Self {
+        self.align = align;
+        return self;
+    }
+
+    pub fn with_padding(mut self, padding: f32) -> Self {
+        self.padding = padding;
+        return self;
+    }
+
+    pub fn with_spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+        return self;
+    }
+
+    pub fn with_background(mut self, color: [f32; 4]) -> Self {
+        self.background = Some(color);
+        return self;
+    }
+
+    pub fn with_min_size(mut self, min_size: glam::Vec2) -> Self {
+        self.min_size = min_size;
+        return self;
+    }
+
+    pub fn child(mut self, widget: impl Into<Widget>) -> Self {
+        self.children.push(widget.into());
+        return self;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub text: String,
+    pub font: AssetId,
+    pub size: f32,
+    pub color: [f32; 4],
+
+    /// Wraps the text at this width, see `render::text::layout`
+    pub max_width: Option<f32>,
+}
+
+impl Label {
+    pub fn new(text: &str, font: AssetId, size: f32) -> Self {
+        return Self {
+            text: text.to_string(),
+            font,
+            size,
+            color: [1.0, 1.0, 1.0, 1.0],
+            max_width: None,
+        };
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        return self;
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        return self;
+    }
+}
+
+/// Background colors of a button in each of its states
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ButtonStyle {
+    pub normal: [f32; 4],
+    pub hovered: [f32; 4],
+    pub pressed: [f32; 4],
+}
+
+impl Default for ButtonStyle {
+    fn default() -> Self {
+        return Self {
+            normal: [0.25, 0.25, 0.3, 1.0],
+            hovered: [0.35, 0.35, 0.42, 1.0],
+            pressed: [0.18, 0.18, 0.22, 1.0],
+        };
+    }
+}
+
+/// Label over a background reacting to the cursor, clicks are read with `Ui::clicked`
+#[derive(Debug, Clone)]
+pub struct Button {
+    pub id: String,
+    pub label: Label,
+    pub padding: f32,
+    pub min_size: glam::Vec2,
+    pub style: ButtonStyle,
+}
+
+impl Button {
+    pub fn new(id: &str, label: Label) -> Self {
+        return Self {
+            id: id.to_string(),
+            label,
+            padding: 4.0,
+            min_size: glam::Vec2::ZERO,
+            style: ButtonStyle::default(),
+        };
+    }
+
+    pub fn with_padding(mut self, padding: f32) -> Self {
+        self.padding = padding;
+        return self;
+    }
+
+    pub fn with_min_size(mut self, min_size: glam::Vec2) -> Self {
+        self.min_size = min_size;
+        return self;
+    }
+
+    pub fn with_style(mut self, style: ButtonStyle) -> Self {
+        self.style = style;
+        return self;
+    }
+}
+
+/// Region of a texture drawn at a fixed size, e.g. an inventory slot
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub id: Option<String>,
+    pub texture: AssetId,
+    pub uv: UvRect,
+    pub size: glam::Vec2,
+    pub tint: [f32; 4],
+}
+
+impl Image {
+    pub fn new(sprite: &Sprite, size: glam::Vec2) -> Self {
+        return Self::from_uv(sprite.texture_id(), sprite.uv(), size);
+    }
+
+    pub fn from_uv(texture: AssetId, uv: UvRect, size: glam::Vec2) -> Self {
+        return Self {
+            id: None,
+            texture,
+            uv,
+            size,
+            tint: [1.0, 1.0, 1.0, 1.0],
+        };
+    }
+
+    /// Makes the image hoverable and clickable
+    pub fn with_id(mut self, id: &str) -> Self {
+        self.id = Some(id.to_string());
+        return self;
+    }
+
+    pub fn with_tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        return self;
+    }
+}
+
+impl From<Panel> for Widget {
+    fn from(panel: Panel) -> Self {
+        return Widget::Panel(panel);
+    }
+}
+
+impl From<Label> for Widget {
+    fn from(label: Label) -> Self {
+        return Widget::Label(label);
+    }
+}
+
+impl From<Button> for Widget {
+    fn from(button: Button) -> Self {
+        return Widget::Button(button);
+    }
+}
+
+impl From<Image> for Widget {
+    fn from(image: Image) -> Self {
+        return Widget::Image(image);
+    }
+}
+
+/// Panel attached to a point of the viewport
+#[derive(Debug, Clone)]
+pub(crate) struct Root {
+    pub anchor: Anchor,
+
+    /// Pixels between the anchor of the viewport and the anchor of the panel
+    pub offset: glam::Vec2,
+    pub panel: Panel,
+}
+
+/// Screen-space UI, inserted as a resource by the engine
+///
+/// Roots added later are drawn over the earlier ones. While the cursor is over a root,
+/// mouse presses and the wheel go to the UI only and never reach `Input`, so clicks
+/// on the UI do not fall through to the world
+pub struct Ui {
+    roots: Vec<Root>,
+
+    /// Widgets with an id and the root panels, from the last layout, in draw order
+    regions: Vec<(String, UiRect)>,
+    blocking: Vec<UiRect>,
+
+    cursor: Option<glam::Vec2>,
+    hovered: Option<String>,
+
+    /// Widget the left button went down on, a click needs the release on it too
+    pressed: Option<String>,
+    clicked: Vec<String>,
+}
+
+impl Resource for Ui {}
+
+impl Ui {
+    pub fn new() -> Self {
+        return Self {
+            roots: Vec::new(),
+            regions: Vec::new(),
+            blocking: Vec::new(),
+            cursor: None,
+            hovered: None,
+            pressed: None,
+            clicked: Vec::new(),
+        };
+    }
+
+    /// Draws the panel on the current frame, its `anchor` point placed at the
+    /// `anchor` point of the viewport moved by `offset`
+    pub fn add(&mut self, anchor: Anchor, offset: glam::Vec2, panel: Panel) {
+        self.roots.push(Root {
+            anchor,
+            offset,
+            panel,
+        });
+    }
+
+    pub fn hovered(&self, id: &str) -> bool {
+        return self.hovered.as_deref() == Some(id);
+    }
+
+    /// Whether the left button went down on the widget and is still held
+    pub fn pressed(&self, id: &str) -> bool {
+        return self.pressed.as_deref() == Some(id);
+    }
+
+    /// Whether the widget was clicked since the last frame
+    pub fn clicked(&self, id: &str) -> bool {
+        return self.clicked.iter().any(|c| c == id);
+    }
+
+    /// Whether the cursor is over the UI, the world should then ignore the pointer
+    pub fn wants_pointer(&self) -> bool {
+        return match self.cursor {
+            Some(cursor) => self.blocking.iter().any(|r| r.contains(cursor)),
+            None => false,
+        };
+    }
+
+    pub(crate) fn take_roots(&mut self) -> Vec<Root> {
+        return std::mem::take(&mut self.roots);
+    }
+
+    pub(crate) fn set_layout(&mut self, regions: Vec<(String, UiRect)>, blocking: Vec<UiRect>) {
+        self.regions = regions;
+        self.blocking = blocking;
+        self.update_hovered();
+    }
+
+    pub(crate) fn pointer_moved(&mut self, cursor: Option<glam::Vec2>) {
+        self.cursor = cursor;
+        self.update_hovered();
+    }
+
+    /// Returns whether the UI consumed the press
+    pub(crate) fn pointer_pressed(&mut self) -> bool {
+        if !self.wants_pointer() {
+            return false;
+        }
+
+        self.pressed = self.hovered.clone();
+        return true;
+    }
+
+    pub(crate) fn pointer_released(&mut self) {
+        if let Some(pressed) = self.pressed.take() {
+            if self.hovered.as_ref() == Some(&pressed) {
+                self.clicked.push(pressed);
+            }
+        }
+    }
+
+    /// Clicks are visible to the systems of a single frame
+    pub(crate) fn end_frame(&mut self) {
+        self.clicked.clear();
+    }
+
+    /// The topmost widget under the cursor, the last one drawn
+    fn update_hovered(&mut self) {
+        self.hovered = match self.cursor {
+            Some(cursor) => self
+                .regions
+                .iter()
+                .rev()
+                .find(|(_, rect)| rect.contains(cursor))
+                .map(|(id, _)| id.clone()),
+            None => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ui() -> Ui {
+        let mut ui = Ui::new();
+        let panel = UiRect::new(glam::Vec2::ZERO, glam::Vec2::splat(100.0));
+        ui.set_layout(
+            vec![
+                (
+                    "ok".to_string(),
+                    UiRect::new(glam::Vec2::splat(10.0), glam::Vec2::splat(20.0)),
+                ),
+                (
+                    "cancel".to_string(),
+                    UiRect::new(glam::Vec2::new(40.0, 10.0), glam::Vec2::splat(20.0)),
+                ),
+            ],
+            vec![panel],
+        );
+
+        return ui;
+    }
+
+    #[test]
+    fn test_click() {
+        let mut ui = ui();
+
+        ui.pointer_moved(Some(glam::Vec2::splat(15.0)));
+        assert!(ui.hovered("ok"));
+        assert!(ui.pointer_pressed());
+        assert!(ui.pressed("ok"));
+
+        ui.pointer_released();
+        assert!(ui.clicked("ok"));
+        assert!(!ui.clicked("cancel"));
+
+        ui.end_frame();
+        assert!(!ui.clicked("ok"));
+    }
+
+    #[test]
+    fn test_release_elsewhere_is_not_a_click() {
+        let mut ui = ui();
+
+        ui.pointer_moved(Some(glam::Vec2::splat(15.0)));
+        ui.pointer_pressed();
+        ui.pointer_moved(Some(glam::Vec2::new(45.0, 15.0)));
+        ui.pointer_released();
+
+        assert!(!ui.clicked("ok"));
+        assert!(!ui.clicked("cancel"));
+    }
+
+    #[test]
+    fn test_consumes_presses_over_panels_only() {
+        let mut ui = ui();
+
+        // Over the panel but no widget: consumed, nothing clicked
+        ui.pointer_moved(Some(glam::Vec2::splat(80.0)));
+        assert!(ui.wants_pointer());
+        assert!(ui.pointer_pressed());
+
+        ui.pointer_moved(Some(glam::Vec2::splat(150.0)));
+        assert!(!ui.wants_pointer());
+        assert!(!ui.pointer_pressed());
+
+        ui.pointer_moved(None);
+        assert!(!ui.wants_pointer());
+    }
+}
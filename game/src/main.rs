@@ -1,18 +1,99 @@
-use engine::{ecs::scheduler::System, Engine};
+use std::collections::HashSet;
 
-struct TestSystem {}
-impl System for TestSystem {
-    fn run(&mut self, world: &mut engine::ecs::world::World, dt: std::time::Duration) {
-        println!("Hello World!");
+use engine::{
+    assets::atlas::Atlas,
+    ecs::{
+        resource::Resource,
+        scheduler::{Stage, System, SystemConfig},
+        world::World,
+    },
+    input::{action::ActionMap, Input},
+    render::sprite::{SpriteBatch, SpriteDraw},
+    Engine,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
+enum GameAction {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Plow,
+}
+
+/// Size of a tile of the field on screen
+const TILE_SIZE: f32 = 64.0;
+
+/// Tiles of the field turned to soil, as grid coordinates
+struct PlowedTiles {
+    tiles: HashSet<glam::IVec2>,
+}
+impl Resource for PlowedTiles {}
+
+struct PlowSystem {}
+impl System for PlowSystem {
+    fn run(&mut self, world: &World, _dt: std::time::Duration) {
+        let input = world.resource::<Input>();
+        let actions = world.resource::<ActionMap<GameAction>>();
+
+        if !actions.just_pressed(&input, GameAction::Plow) {
+            return;
+        }
+
+        if let Some(cursor) = input.cursor_world() {
+            let tile = (cursor / TILE_SIZE).round().as_ivec2();
+            world.resource_mut::<PlowedTiles>().tiles.insert(tile);
+        }
+    }
+}
+
+struct DrawAtlasSystem {}
+impl System for DrawAtlasSystem {
+    fn run(&mut self, world: &World, _dt: std::time::Duration) {
+        let atlas = world.resource::<Atlas>();
+        let plowed = world.resource::<PlowedTiles>();
+        let mut batch = world.resource_mut::<SpriteBatch>();
+        let size = glam::Vec2::splat(TILE_SIZE);
+
+        for (i, name) in ["soil", "grass", "water"].iter().enumerate() {
+            let sprite = atlas.sprite_by_name(name).expect("Missing atlas sprite");
+            let position = glam::Vec2::new((i as f32 - 1.0) * TILE_SIZE, 0.0);
+
+            batch.draw(SpriteDraw::new(&sprite, position, size));
+        }
+
+        let soil = atlas.sprite_by_name("soil").expect("Missing atlas sprite");
+        for tile in &plowed.tiles {
+            let position = tile.as_vec2() * TILE_SIZE;
+            batch.draw(SpriteDraw::new(&soil, position, size));
+        }
     }
 }
 
 pub fn main() {
     let mut engine = Engine::new();
 
-    engine.run();
+    let atlas = engine
+        .load_atlas(concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/atlas.ron"))
+        .unwrap_or_else(|e| panic!("{}", e));
+    engine.insert_resource(atlas);
+
+    let actions =
+        ActionMap::<GameAction>::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/input.ron"))
+            .expect("Could not load input bindings");
+    engine.insert_resource(actions);
+    engine.insert_resource(PlowedTiles {
+        tiles: HashSet::new(),
+    });
 
-    engine.load_texture("../../assets/atlas.png");
+    engine.add_system(PlowSystem {});
+    engine.add_system_with_config(
+        DrawAtlasSystem {},
+        SystemConfig {
+            stage: Stage::RenderExtract,
+            ..Default::default()
+        },
+    );
 
-    engine.add_system(TestSystem {});
+    engine.run().unwrap_or_else(|e| panic!("{}", e));
 }